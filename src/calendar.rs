@@ -0,0 +1,273 @@
+//! Business-day calendars for settlement dates
+//!
+//! An FX trade settles in both of its currencies, so its value and delivery dates have
+//! to be good business days in each: a *joint* business day. Every [`Currency`] can
+//! have a [`HolidayCalendar`] of weekend days and holidays; a currency without one
+//! only closes at the weekend. With calendars set,
+//! [`TradeService`](crate::service::TradeService) refuses submits and updates whose
+//! value or delivery date falls on a day either currency is closed.
+//!
+//! With the `config` feature, calendars load from a TOML file with a table per
+//! currency. `weekend` defaults to Saturday and Sunday:
+//!
+//! ```toml
+//! [USD]
+//! holidays = ["2025-07-04", "2025-12-25"]
+//!
+//! [EUR]
+//! weekend = ["Sat", "Sun"]
+//! holidays = ["2025-12-25", "2025-12-26"]
+//! ```
+
+use super::error::ValidationError;
+use super::trade::{Currency, TimeStamp, TradeDetails};
+use chrono::{Datelike, NaiveDate, Utc, Weekday};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Business days from the trade date to the spot date
+pub const SPOT_DAYS: u32 = 2;
+
+/// How many days past a date [`BusinessCalendars::roll_forward`] looks for a joint
+/// business day. Two currencies whose weekends cover the week between them never
+/// share one.
+pub const MAX_ROLL_DAYS: u32 = 366;
+
+/// The days one currency's market is closed: a weekly weekend and dated holidays
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(try_from = "CalendarFile")
+)]
+pub struct HolidayCalendar {
+    /// Closed weekdays, indexed from Monday
+    weekend: [bool; 7],
+    holidays: BTreeSet<NaiveDate>,
+}
+
+/// Closed on Saturdays and Sundays only, the calendar of a currency without one
+static WEEKENDS_ONLY: HolidayCalendar = HolidayCalendar {
+    weekend: [false, false, false, false, false, true, true],
+    holidays: BTreeSet::new(),
+};
+
+impl Default for HolidayCalendar {
+    fn default() -> Self {
+        WEEKENDS_ONLY.clone()
+    }
+}
+
+impl HolidayCalendar {
+    /// A calendar closed on Saturdays and Sundays, without holidays
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the weekend. A weekend covering the whole week is refused, as no date
+    /// could ever settle.
+    pub fn with_weekend(mut self, days: &[Weekday]) -> Result<Self, ValidationError> {
+        let mut weekend = [false; 7];
+        for day in days {
+            weekend[day.num_days_from_monday() as usize] = true;
+        }
+        if weekend.iter().all(|closed| *closed) {
+            return Err(ValidationError::InvalidCalendar(
+                "the weekend covers every day of the week".to_string(),
+            ));
+        }
+        self.weekend = weekend;
+        Ok(self)
+    }
+
+    /// Close the market on `date` as well
+    pub fn with_holiday(mut self, date: NaiveDate) -> Self {
+        self.holidays.insert(date);
+        self
+    }
+
+    pub fn is_weekend(&self, date: NaiveDate) -> bool {
+        self.weekend[date.weekday().num_days_from_monday() as usize]
+    }
+
+    pub fn is_holiday(&self, date: NaiveDate) -> bool {
+        self.holidays.contains(&date)
+    }
+
+    pub fn is_business_day(&self, date: NaiveDate) -> bool {
+        !self.is_weekend(date) && !self.is_holiday(date)
+    }
+}
+
+/// A calendar as written in TOML
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CalendarFile {
+    weekend: Option<Vec<String>>,
+    #[serde(default)]
+    holidays: Vec<String>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<CalendarFile> for HolidayCalendar {
+    type Error = ValidationError;
+
+    fn try_from(file: CalendarFile) -> Result<Self, Self::Error> {
+        let mut calendar = Self::new();
+        if let Some(weekend) = file.weekend {
+            let days = weekend
+                .iter()
+                .map(|day| {
+                    day.parse::<Weekday>().map_err(|_| {
+                        ValidationError::InvalidCalendar(format!("`{}` is not a weekday", day))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            calendar = calendar.with_weekend(&days)?;
+        }
+        for holiday in &file.holidays {
+            let date = holiday.parse::<NaiveDate>().map_err(|_| {
+                ValidationError::InvalidCalendar(format!("`{}` is not a YYYY-MM-DD date", holiday))
+            })?;
+            calendar = calendar.with_holiday(date);
+        }
+        Ok(calendar)
+    }
+}
+
+/// A holiday calendar per currency
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize), serde(transparent))]
+pub struct BusinessCalendars {
+    calendars: BTreeMap<Currency, HolidayCalendar>,
+}
+
+impl BusinessCalendars {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `calendar` for `currency`, replacing any it had
+    pub fn with_calendar(mut self, currency: Currency, calendar: HolidayCalendar) -> Self {
+        self.calendars.insert(currency, calendar);
+        self
+    }
+
+    /// The calendar of `currency`, weekends only if none was given
+    pub fn calendar(&self, currency: Currency) -> &HolidayCalendar {
+        self.calendars.get(&currency).unwrap_or(&WEEKENDS_ONLY)
+    }
+
+    /// Whether `date` is a business day in every one of `currencies`
+    pub fn is_business_day(&self, date: NaiveDate, currencies: &[Currency]) -> bool {
+        currencies
+            .iter()
+            .all(|currency| self.calendar(*currency).is_business_day(date))
+    }
+
+    /// `date` if it is a joint business day of `currencies`, otherwise the next one.
+    /// Fails with [`ValidationError::NoBusinessDay`] when there is none within
+    /// [`MAX_ROLL_DAYS`].
+    pub fn roll_forward(
+        &self,
+        date: NaiveDate,
+        currencies: &[Currency],
+    ) -> Result<NaiveDate, ValidationError> {
+        // `iter_days` stops short of `NaiveDate::MAX`, which can still be a business day
+        std::iter::successors(Some(date), NaiveDate::succ_opt)
+            .take(MAX_ROLL_DAYS as usize + 1)
+            .find(|day| self.is_business_day(*day, currencies))
+            .ok_or_else(|| ValidationError::NoBusinessDay {
+                date,
+                currencies: currency_names(currencies),
+                days: MAX_ROLL_DAYS,
+            })
+    }
+
+    /// The date `days` joint business days of `currencies` after `date`, failing as
+    /// [`roll_forward`](Self::roll_forward) does, or with
+    /// [`ValidationError::DateOutOfRange`] past the last date chrono can represent
+    pub fn add_business_days(
+        &self,
+        date: NaiveDate,
+        days: u32,
+        currencies: &[Currency],
+    ) -> Result<NaiveDate, ValidationError> {
+        (0..days).try_fold(date, |day, _| {
+            let next = day
+                .succ_opt()
+                .ok_or(ValidationError::DateOutOfRange { date, days })?;
+            self.roll_forward(next, currencies)
+        })
+    }
+
+    /// The spot date of a trade made on `trade_date`: [`SPOT_DAYS`] joint business
+    /// days later
+    pub fn spot_date(
+        &self,
+        trade_date: NaiveDate,
+        currencies: &[Currency],
+    ) -> Result<NaiveDate, ValidationError> {
+        self.add_business_days(trade_date, SPOT_DAYS, currencies)
+    }
+
+    /// Check the value and delivery dates of `details` are joint business days of its
+    /// notional and underlying currencies. Dates are taken in UTC.
+    pub fn validate(&self, details: &TradeDetails) -> Result<(), ValidationError> {
+        let currencies: Vec<Currency> =
+            [details.notional_currency(), details.underlying_currency()]
+                .into_iter()
+                .flatten()
+                .collect();
+
+        let dates = [
+            ("value_date", details.value_date()),
+            ("delivery_date", details.delivery_date()),
+        ];
+        for (field, date) in dates {
+            let Some(date) = date.map(date_of) else {
+                continue;
+            };
+            let closed: Vec<Currency> = currencies
+                .iter()
+                .copied()
+                .filter(|currency| !self.calendar(*currency).is_business_day(date))
+                .collect();
+            if !closed.is_empty() {
+                return Err(ValidationError::NonBusinessDay {
+                    field,
+                    date,
+                    currencies: currency_names(&closed),
+                    next: self.roll_forward(date, &currencies)?,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse calendars from TOML, one table per currency
+    #[cfg(feature = "config")]
+    pub fn from_toml(contents: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    /// Read calendars from a TOML file
+    #[cfg(feature = "config")]
+    pub fn load(path: &std::path::Path) -> anyhow::Result<Self> {
+        super::config::load_toml(path, Self::from_toml)
+    }
+}
+
+/// Currencies as listed in errors, `USD, EUR`
+fn currency_names(currencies: &[Currency]) -> String {
+    currencies
+        .iter()
+        .map(|currency| format!("{:?}", currency))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The UTC calendar date of a timestamp
+fn date_of(timestamp: &TimeStamp<Utc>) -> NaiveDate {
+    timestamp.to_datetime_utc().date_naive()
+}
@@ -0,0 +1,281 @@
+//! Canonical CBOR for content addressing
+//!
+//! Content hashes are taken over CBOR bytes, so two encoders that disagree on how to
+//! write the same value would give the same object two IDs. The rules used here are the
+//! core deterministic encoding requirements of RFC 8949 §4.2.1:
+//!
+//! - integers, lengths and tags use the shortest possible argument
+//! - arrays, maps and strings have definite lengths
+//! - map keys are sorted by the bytewise order of their encodings, with no duplicates
+//!
+//! Values are first encoded with minicbor and then rewritten by a small independent
+//! encoder, so hashes do not depend on minicbor's choices. Floating point values never
+//! appear in trade objects and are rejected rather than normalised.
+
+use super::error::ValidationError;
+
+/// A decoded CBOR data item
+enum Item {
+    Unsigned(u64),
+    Negative(u64),
+    Bytes(Vec<u8>),
+    Text(Vec<u8>),
+    Array(Vec<Item>),
+    Map(Vec<(Item, Item)>),
+    Tag(u64, Box<Item>),
+    Simple(u8),
+}
+
+const BREAK: u8 = 0xff;
+
+/// How deeply arrays, maps and tags may nest. Trade objects stay far below this, and
+/// the limit keeps hostile input from exhausting the stack.
+pub const MAX_DEPTH: usize = 64;
+
+fn malformed(reason: &str) -> ValidationError {
+    ValidationError::NonCanonical(reason.to_string())
+}
+
+/// Reads data items, accepting any well-formed encoding
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], ValidationError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| malformed("unexpected end of input"))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    /// Read the argument for additional info `info`, `None` for indefinite length
+    fn argument(&mut self, info: u8) -> Result<Option<u64>, ValidationError> {
+        let arg = match info {
+            0..=23 => u64::from(info),
+            24 => u64::from(self.take(1)?[0]),
+            25 => u64::from(u16::from_be_bytes(self.take(2)?.try_into().unwrap())),
+            26 => u64::from(u32::from_be_bytes(self.take(4)?.try_into().unwrap())),
+            27 => u64::from_be_bytes(self.take(8)?.try_into().unwrap()),
+            31 => return Ok(None),
+            _ => return Err(malformed("reserved additional info")),
+        };
+        Ok(Some(arg))
+    }
+
+    fn length(&mut self, info: u8) -> Result<Option<usize>, ValidationError> {
+        match self.argument(info)? {
+            Some(len) => usize::try_from(len)
+                .map(Some)
+                .map_err(|_| malformed("length does not fit in memory")),
+            None => Ok(None),
+        }
+    }
+
+    fn at_break(&mut self) -> bool {
+        if self.peek() == Some(BREAK) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    /// Read a byte or text string, joining the chunks of an indefinite one
+    fn string(&mut self, major: u8, info: u8) -> Result<Vec<u8>, ValidationError> {
+        match self.length(info)? {
+            Some(len) => Ok(self.take(len)?.to_vec()),
+            None => {
+                let mut joined = Vec::new();
+                while !self.at_break() {
+                    let initial = self.take(1)?[0];
+                    if initial >> 5 != major || initial & 0x1f == 31 {
+                        return Err(malformed("invalid chunk in indefinite-length string"));
+                    }
+                    joined.extend(self.string(major, initial & 0x1f)?);
+                }
+                Ok(joined)
+            }
+        }
+    }
+
+    /// Read one data item nested `depth` levels inside the outermost one
+    fn item(&mut self, depth: usize) -> Result<Item, ValidationError> {
+        if depth > MAX_DEPTH {
+            return Err(malformed("data items nested too deeply"));
+        }
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+
+        match major {
+            0 | 1 | 6 => {
+                let arg = self
+                    .argument(info)?
+                    .ok_or_else(|| malformed("indefinite length on an integer or tag"))?;
+                Ok(match major {
+                    0 => Item::Unsigned(arg),
+                    1 => Item::Negative(arg),
+                    _ => Item::Tag(arg, Box::new(self.item(depth + 1)?)),
+                })
+            }
+            2 => Ok(Item::Bytes(self.string(major, info)?)),
+            3 => {
+                let text = self.string(major, info)?;
+                std::str::from_utf8(&text).map_err(|_| malformed("text is not UTF-8"))?;
+                Ok(Item::Text(text))
+            }
+            4 => {
+                let mut items = Vec::new();
+                match self.length(info)? {
+                    Some(len) => {
+                        for _ in 0..len {
+                            items.push(self.item(depth + 1)?);
+                        }
+                    }
+                    None => {
+                        while !self.at_break() {
+                            items.push(self.item(depth + 1)?);
+                        }
+                    }
+                }
+                Ok(Item::Array(items))
+            }
+            5 => {
+                let mut entries = Vec::new();
+                match self.length(info)? {
+                    Some(len) => {
+                        for _ in 0..len {
+                            entries.push((self.item(depth + 1)?, self.item(depth + 1)?));
+                        }
+                    }
+                    None => {
+                        while !self.at_break() {
+                            entries.push((self.item(depth + 1)?, self.item(depth + 1)?));
+                        }
+                    }
+                }
+                Ok(Item::Map(entries))
+            }
+            _ => match info {
+                0..=23 => Ok(Item::Simple(info)),
+                24 => {
+                    let value = self.take(1)?[0];
+                    if value < 32 {
+                        return Err(malformed("simple value in two-byte form"));
+                    }
+                    Ok(Item::Simple(value))
+                }
+                25..=27 => Err(malformed("floating point values are not supported")),
+                _ => Err(malformed("unexpected break or reserved simple value")),
+            },
+        }
+    }
+}
+
+/// Write the head of a data item with the shortest argument
+fn write_head(out: &mut Vec<u8>, major: u8, arg: u64) {
+    let major = major << 5;
+    if arg < 24 {
+        out.push(major | arg as u8);
+    } else if arg <= u64::from(u8::MAX) {
+        out.push(major | 24);
+        out.push(arg as u8);
+    } else if arg <= u64::from(u16::MAX) {
+        out.push(major | 25);
+        out.extend((arg as u16).to_be_bytes());
+    } else if arg <= u64::from(u32::MAX) {
+        out.push(major | 26);
+        out.extend((arg as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend(arg.to_be_bytes());
+    }
+}
+
+fn write_item(out: &mut Vec<u8>, item: &Item) -> Result<(), ValidationError> {
+    match item {
+        Item::Unsigned(n) => write_head(out, 0, *n),
+        Item::Negative(n) => write_head(out, 1, *n),
+        Item::Bytes(bytes) => {
+            write_head(out, 2, bytes.len() as u64);
+            out.extend_from_slice(bytes);
+        }
+        Item::Text(text) => {
+            write_head(out, 3, text.len() as u64);
+            out.extend_from_slice(text);
+        }
+        Item::Array(items) => {
+            write_head(out, 4, items.len() as u64);
+            for item in items {
+                write_item(out, item)?;
+            }
+        }
+        Item::Map(entries) => {
+            let mut encoded = Vec::with_capacity(entries.len());
+            for (key, value) in entries {
+                let mut key_bytes = Vec::new();
+                write_item(&mut key_bytes, key)?;
+                let mut value_bytes = Vec::new();
+                write_item(&mut value_bytes, value)?;
+                encoded.push((key_bytes, value_bytes));
+            }
+            encoded.sort();
+            if encoded.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+                return Err(malformed("duplicate map key"));
+            }
+
+            write_head(out, 5, encoded.len() as u64);
+            for (key, value) in encoded {
+                out.extend(key);
+                out.extend(value);
+            }
+        }
+        Item::Tag(tag, item) => {
+            write_head(out, 6, *tag);
+            write_item(out, item)?;
+        }
+        Item::Simple(value) => write_head(out, 7, u64::from(*value)),
+    }
+    Ok(())
+}
+
+/// Rewrite a single CBOR data item in canonical form
+pub fn canonicalize(bytes: &[u8]) -> Result<Vec<u8>, ValidationError> {
+    let mut parser = Parser { bytes, pos: 0 };
+    let item = parser.item(0)?;
+    if parser.pos != bytes.len() {
+        return Err(malformed("trailing bytes after data item"));
+    }
+
+    let mut out = Vec::with_capacity(bytes.len());
+    write_item(&mut out, &item)?;
+    Ok(out)
+}
+
+/// Check that `bytes` is exactly one data item already in canonical form
+pub fn verify_canonical(bytes: &[u8]) -> Result<(), ValidationError> {
+    if canonicalize(bytes)? != bytes {
+        return Err(malformed("encoding is not canonical"));
+    }
+    Ok(())
+}
+
+/// Encode a value to canonical CBOR
+pub fn to_canonical_vec<T: minicbor::Encode<()>>(value: &T) -> anyhow::Result<Vec<u8>> {
+    let bytes = minicbor::to_vec(value)?;
+    Ok(canonicalize(&bytes)?)
+}
+
+/// Decode a value, rejecting bytes that are not in canonical form
+pub fn decode_canonical<'b, T: minicbor::Decode<'b, ()>>(bytes: &'b [u8]) -> anyhow::Result<T> {
+    verify_canonical(bytes)?;
+    Ok(minicbor::decode(bytes)?)
+}
@@ -0,0 +1,55 @@
+//! Time sources for the service
+//!
+//! [`TradeService`](crate::service::TradeService) stamps every witness, and checks
+//! delegation windows, with the time its [`Clock`] reports. [`SystemClock`] is the
+//! default; [`FixedClock`] stands still until it is told to move, so tests and replays
+//! can control exactly what time each action happened at.
+
+use super::trade::TimeStamp;
+use chrono::{DateTime, TimeDelta, Utc};
+use std::sync::RwLock;
+
+/// Source of the current time
+pub trait Clock: Send + Sync {
+    fn now(&self) -> TimeStamp<Utc>;
+}
+
+/// The system's wall clock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> TimeStamp<Utc> {
+        TimeStamp::new()
+    }
+}
+
+/// A clock that reports the same time until it is set or advanced
+#[derive(Debug)]
+pub struct FixedClock {
+    now: RwLock<DateTime<Utc>>,
+}
+
+impl FixedClock {
+    pub fn new(now: TimeStamp<Utc>) -> Self {
+        Self {
+            now: RwLock::new(now.to_datetime_utc()),
+        }
+    }
+
+    /// Move the clock to `now`, which may be in its past
+    pub fn set(&self, now: TimeStamp<Utc>) {
+        *self.now.write().unwrap_or_else(|e| e.into_inner()) = now.to_datetime_utc();
+    }
+
+    /// Move the clock by `by`, backwards if it is negative
+    pub fn advance(&self, by: TimeDelta) {
+        *self.now.write().unwrap_or_else(|e| e.into_inner()) += by;
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> TimeStamp<Utc> {
+        (*self.now.read().unwrap_or_else(|e| e.into_inner())).into()
+    }
+}
@@ -0,0 +1,184 @@
+//! Service configuration and rule-based approval routing
+//!
+//! Instead of naming the approver on every Submit, the approvals each kind of trade
+//! needs can be declared once as rules over its details, and
+//! [`TradeService::submit_routed_trade`](crate::service::TradeService::submit_routed_trade)
+//! picks the policy of the first rule that matches. Rules are checked in order, so a
+//! catch-all rule without conditions belongs last. With the `config` feature a
+//! configuration can be loaded from TOML:
+//!
+//! ```toml
+//! max_retries = 3
+//! max_clock_skew_ms = 500
+//!
+//! [segregation_of_duties]
+//! approve = true
+//!
+//! [required_reasons]
+//! cancel = true
+//!
+//! [[rules]]
+//! name = "large USD buys"
+//! direction = "Buy"
+//! notional_currency = "USD"
+//! min_notional = 1000000
+//!
+//! [[rules.levels]]
+//! approvers = ["desk_head"]
+//! required = 1
+//!
+//! [[rules.levels]]
+//! approvers = ["risk_1", "risk_2", "risk_3"]
+//! required = 2
+//!
+//! [[rules]]
+//! name = "everything else"
+//! levels = [{ approvers = ["desk_head"], required = 1 }]
+//!
+//! [[limits]]
+//! counter_party = "counter_1xyz"
+//! currency = "USD"
+//! cap = 10000000
+//!
+//! [calendars.USD]
+//! holidays = ["2025-07-04", "2025-12-25"]
+//! ```
+
+use super::calendar::BusinessCalendars;
+use super::context::{ApprovalLevel, ApprovalPolicy};
+use super::error::ValidationError;
+use super::limits::NotionalLimit;
+use super::service::{RequiredReasons, SegregationOfDuties};
+use super::trade::{Currency, Direction, TradeDetails};
+
+/// Settings applied to a service with
+/// [`TradeService::with_config`](crate::service::TradeService::with_config)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct ServiceConfig {
+    /// How many times a mutation is replayed after losing a race to another writer
+    pub max_retries: usize,
+    /// Actions the trade's originators are not allowed to take themselves
+    pub segregation_of_duties: SegregationOfDuties,
+    /// Actions that must give a reason in their note
+    pub required_reasons: RequiredReasons,
+    /// Approval routing, first match wins
+    pub rules: Vec<RoutingRule>,
+    /// Notional caps checked on submit, update and approve
+    pub limits: Vec<NotionalLimit>,
+    /// How many milliseconds a witness may be timestamped before the one it follows
+    pub max_clock_skew_ms: u64,
+    /// Holiday calendars per currency for settlement dates, unchecked if absent
+    pub calendars: Option<BusinessCalendars>,
+}
+
+/// Conditions on trade details, and the approval levels a matching trade needs. A
+/// condition that is not set matches anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(deny_unknown_fields)
+)]
+pub struct RoutingRule {
+    /// Identifies the rule in errors
+    pub name: String,
+    pub direction: Option<Direction>,
+    pub notional_currency: Option<Currency>,
+    pub underlying_currency: Option<Currency>,
+    pub counter_party: Option<String>,
+    /// Matches notional amounts of at least this much
+    pub min_notional: Option<u64>,
+    /// Matches notional amounts below this
+    pub max_notional: Option<u64>,
+    pub levels: Vec<ApprovalLevel>,
+}
+
+impl RoutingRule {
+    /// Whether every condition set on the rule holds for `details`
+    pub fn matches(&self, details: &TradeDetails) -> bool {
+        let notional = details.notional_amount();
+
+        self.direction
+            .is_none_or(|d| details.direction() == Some(d))
+            && self
+                .notional_currency
+                .is_none_or(|c| details.notional_currency() == Some(c))
+            && self
+                .underlying_currency
+                .is_none_or(|c| details.underlying_currency() == Some(c))
+            && self
+                .counter_party
+                .as_deref()
+                .is_none_or(|cp| details.counter_party() == Some(cp))
+            && self.min_notional.is_none_or(|min| notional >= min)
+            && self.max_notional.is_none_or(|max| notional < max)
+    }
+
+    /// The approval policy a matching trade is submitted with
+    pub fn policy(&self) -> ApprovalPolicy {
+        ApprovalPolicy {
+            levels: self.levels.clone(),
+        }
+    }
+}
+
+impl ServiceConfig {
+    /// Parse a TOML configuration and check its rules
+    #[cfg(feature = "config")]
+    pub fn from_toml(contents: &str) -> anyhow::Result<Self> {
+        let config: Self = toml::from_str(contents)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Read a TOML configuration file and check its rules
+    #[cfg(feature = "config")]
+    pub fn load(path: &std::path::Path) -> anyhow::Result<Self> {
+        load_toml(path, Self::from_toml)
+    }
+
+    /// Check every rule describes a policy that can be satisfied and a notional range
+    /// that is not empty, and every limit leaves room for a trade
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        for rule in &self.rules {
+            let invalid = |reason: String| ValidationError::InvalidRoutingRule {
+                rule: rule.name.clone(),
+                reason,
+            };
+
+            if let (Some(min), Some(max)) = (rule.min_notional, rule.max_notional)
+                && min >= max
+            {
+                return Err(invalid(format!(
+                    "min_notional {} is not below max_notional {}",
+                    min, max
+                )));
+            }
+            rule.policy().validate().map_err(|e| match e {
+                ValidationError::InvalidApprovalPolicy(reason) => invalid(reason),
+                e => e,
+            })?;
+        }
+        for limit in &self.limits {
+            limit.validate()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Read the TOML file at `path` and parse it with `from_toml`
+#[cfg(feature = "config")]
+pub(crate) fn load_toml<T>(
+    path: &std::path::Path,
+    from_toml: fn(&str) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("reading {}: {}", path.display(), e))?;
+    from_toml(&contents)
+}
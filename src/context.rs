@@ -1,15 +1,27 @@
 #![allow(dead_code)]
 //! Trade context and witness management for state derivation
 
-use super::trade::TimeStamp;
+use super::canonical::{decode_canonical, to_canonical_vec};
+use super::error::ValidationError;
+use super::keys::KeyRegistry;
+use super::store::TradeStore;
+use super::timeline::{History, TimelineEntry, TimelineFormat};
+use super::trade::{FieldChange, TimeStamp, TradeDetails};
 use super::utils::new_uuid_to_bech32;
-use chrono::Utc;
+use chrono::{TimeDelta, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use std::collections::BTreeMap;
+
+/// Human-readable prefix of every trade_id
+pub const TRADE_ID_HRP: &str = "trade_";
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum TradeState {
     Draft,           // No Submit yet
     PendingApproval, // Latest action is Submit or Update
     Approved,        // Latest action is Approve (and no Update after)
+    Rejected,        // Latest action is Reject, back with the requester to update
     Cancelled,
     SentToExecute,
     Executed,
@@ -17,6 +29,7 @@ pub enum TradeState {
 }
 
 #[derive(Debug, minicbor::Encode, minicbor::Decode)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct TradeContext {
     /// uses a bech32-encoded UUID string. This string is also referenced in the witness
     #[n(0)]
@@ -26,6 +39,7 @@ pub struct TradeContext {
 }
 
 #[derive(Debug, PartialEq, Eq, minicbor::Encode, minicbor::Decode, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Witness {
     #[n(0)]
     pub trade_id: String,
@@ -37,9 +51,46 @@ pub struct Witness {
     /// Issued when the witness set is created
     #[n(3)]
     pub witness_type: WitnessType,
+    /// SHA256 of the preceding witness, `None` for the first witness in the chain
+    #[n(4)]
+    pub parent_hash: Option<String>,
+    /// Hex-encoded Ed25519 signature by `user_id` over the witness without this field
+    #[n(5)]
+    pub signature: Option<String>,
+    /// Why the action was taken. Covered by the signature, and left out of the
+    /// encoding when absent so witnesses written before notes existed keep their bytes.
+    #[n(6)]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub note: Option<Note>,
+}
+
+/// Free-text reason and structured tags explaining a witness, e.g. why a trade was
+/// cancelled or the conditions an approver attached
+#[derive(Debug, Default, PartialEq, Eq, minicbor::Encode, minicbor::Decode, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Note {
+    #[n(0)]
+    pub reason: Option<String>,
+    #[n(1)]
+    pub tags: Vec<Tag>,
 }
 
+/// A `key=value` label on a [`Note`]
 #[derive(Debug, PartialEq, Eq, minicbor::Encode, minicbor::Decode, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tag {
+    #[n(0)]
+    pub key: String,
+    #[n(1)]
+    pub value: String,
+}
+
+#[derive(Debug, PartialEq, Eq, minicbor::Encode, minicbor::Decode, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "type"))]
 pub enum WitnessType {
     /// If we pass validation checks on build, we are in the pending approval stage
     #[n(0)]
@@ -50,9 +101,22 @@ pub enum WitnessType {
         requester_id: String,
         #[n(2)]
         approver_id: String, // who is responsible to approving the trade
+        /// Quorum required before the trade is approved. `None` means `approver_id`
+        /// alone approves; otherwise `approver_id` is the policy's first approver.
+        /// `None` is left out of the encoding, so single-approver Submits keep their
+        /// original bytes and hashes.
+        #[cbor(n(3), encode_with = "minicbor::Encode::encode", is_nil = "is_none")]
+        approval_policy: Option<ApprovalPolicy>,
     },
     #[n(1)]
-    Approve,
+    Approve {
+        /// The approver a delegate approved for. `None` when the signer approved in
+        /// their own right, and left out of the encoding so those Approves keep the
+        /// bytes they had before delegation existed.
+        #[cbor(n(0), encode_with = "minicbor::Encode::encode", is_nil = "is_none")]
+        #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+        on_behalf_of: Option<String>,
+    },
     #[n(2)]
     Cancel,
     #[n(3)]
@@ -67,6 +131,80 @@ pub enum WitnessType {
         #[n(0)]
         strike: u64,
     },
+    /// An approver sends the trade back to the requester, who can Update it to
+    /// resubmit or Cancel it
+    #[n(6)]
+    Reject {
+        #[n(0)]
+        reason: String,
+    },
+}
+
+/// Used as the `is_nil` check of optional variant fields. The derive binds variant
+/// fields by reference, and the `Option` impl of `is_nil` is not reached through one,
+/// so without this a trailing `None` would be written out as null.
+fn is_none<T>(value: &Option<T>) -> bool {
+    value.is_none()
+}
+
+impl Note {
+    pub fn reason(reason: impl Into<String>) -> Self {
+        Self {
+            reason: Some(reason.into()),
+            tags: Vec::new(),
+        }
+    }
+    /// Add a tag, keeping those added before it
+    pub fn tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.push(Tag {
+            key: key.into(),
+            value: value.into(),
+        });
+        self
+    }
+    pub fn is_empty(&self) -> bool {
+        self.reason.is_none() && self.tags.is_empty()
+    }
+    /// Whether the note gives a reason that is not blank
+    pub fn has_reason(&self) -> bool {
+        self.reason
+            .as_deref()
+            .is_some_and(|reason| !reason.trim().is_empty())
+    }
+}
+
+impl std::fmt::Display for Note {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(reason) = &self.reason {
+            parts.push(format!("reason: {}", reason));
+        }
+        if !self.tags.is_empty() {
+            let tags: Vec<String> = self.tags.iter().map(Tag::to_string).collect();
+            parts.push(format!("tags: {}", tags.join(", ")));
+        }
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+impl std::fmt::Display for Tag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.key, self.value)
+    }
+}
+
+impl std::str::FromStr for Tag {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((key, value)) if !key.is_empty() => Ok(Self {
+                key: key.to_string(),
+                value: value.to_string(),
+            }),
+            _ => Err(anyhow::anyhow!("Tag is not key=value: {}", s)),
+        }
+    }
 }
 
 /// primary action type that drives the trade.
@@ -76,6 +214,7 @@ impl WitnessType {
             details_hash,
             requester_id,
             approver_id,
+            approval_policy: None,
         }
     }
     fn new_update(details_hash: String) -> Self {
@@ -84,6 +223,244 @@ impl WitnessType {
     fn new_book(strike: u64) -> Self {
         Self::Book { strike }
     }
+    /// Name of the action, without its payload
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Submit { .. } => "Submit",
+            Self::Approve { .. } => "Approve",
+            Self::Cancel => "Cancel",
+            Self::Update { .. } => "Update",
+            Self::SendToExecute => "SendToExecute",
+            Self::Book { .. } => "Book",
+            Self::Reject { .. } => "Reject",
+        }
+    }
+}
+
+/// Who must approve a trade before it leaves PendingApproval. Levels are worked through
+/// in order: approvals only count towards a level once every earlier level has met its
+/// quorum, and the trade is approved when the last level does.
+#[derive(Debug, PartialEq, Eq, minicbor::Encode, minicbor::Decode, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ApprovalPolicy {
+    #[n(0)]
+    pub levels: Vec<ApprovalLevel>,
+}
+
+/// A group of approvers, `required` distinct members of which must approve
+#[derive(Debug, PartialEq, Eq, minicbor::Encode, minicbor::Decode, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ApprovalLevel {
+    #[n(0)]
+    pub approvers: Vec<String>,
+    #[n(1)]
+    pub required: u32,
+}
+
+/// How far a trade has got through the approvals its latest Submit or Update requires
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ApprovalProgress {
+    /// Approvers whose approval counted, in the order they approved
+    pub approved_by: Vec<String>,
+    /// Approvals needed across every level
+    pub required: usize,
+    /// Who may approve next: members of the first unsatisfied level yet to approve
+    pub awaiting: Vec<String>,
+}
+
+impl ApprovalProgress {
+    pub fn is_complete(&self) -> bool {
+        self.approved_by.len() >= self.required
+    }
+}
+
+impl ApprovalPolicy {
+    /// A single approver, the policy of a Submit that does not carry one
+    pub fn single(approver_id: impl Into<String>) -> Self {
+        Self::quorum(vec![approver_id.into()], 1)
+    }
+    /// Any `required` distinct approvers out of `approvers`
+    pub fn quorum(approvers: Vec<String>, required: u32) -> Self {
+        Self {
+            levels: vec![ApprovalLevel {
+                approvers,
+                required,
+            }],
+        }
+    }
+    /// Add a level that only opens once the earlier levels have met their quorum
+    pub fn then(mut self, approvers: Vec<String>, required: u32) -> Self {
+        self.levels.push(ApprovalLevel {
+            approvers,
+            required,
+        });
+        self
+    }
+    /// Every approver named by the policy, in level order
+    pub fn approvers(&self) -> impl Iterator<Item = &str> {
+        self.levels
+            .iter()
+            .flat_map(|level| level.approvers.iter().map(String::as_str))
+    }
+    /// Approvals needed across every level
+    pub fn required(&self) -> usize {
+        self.levels
+            .iter()
+            .map(|level| level.required as usize)
+            .sum()
+    }
+    /// Check every level can be satisfied and nobody is named twice
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let invalid = |reason: String| Err(ValidationError::InvalidApprovalPolicy(reason));
+
+        if self.levels.is_empty() {
+            return invalid("no approval levels".to_string());
+        }
+        for level in &self.levels {
+            if level.approvers.is_empty() {
+                return invalid("a level names no approvers".to_string());
+            }
+            if level.required == 0 {
+                return invalid("a level requires no approvals".to_string());
+            }
+            if level.required as usize > level.approvers.len() {
+                return invalid(format!(
+                    "a level requires {} approvals from {} approvers",
+                    level.required,
+                    level.approvers.len()
+                ));
+            }
+        }
+        let mut seen = std::collections::BTreeSet::new();
+        if let Some(approver_id) = self.approvers().find(|id| !seen.insert(*id)) {
+            return invalid(format!("`{}` is named more than once", approver_id));
+        }
+
+        Ok(())
+    }
+    /// Count `approvals`, given in chain order, against the policy. Approvals from
+    /// non-members, repeats, and approvals for a level that is not open yet are ignored.
+    pub fn tally<'a>(&self, approvals: impl IntoIterator<Item = &'a str>) -> ApprovalProgress {
+        let mut approved_by: Vec<String> = Vec::new();
+        let mut level = 0;
+        let mut in_level = 0;
+
+        for approver_id in approvals {
+            let Some(current) = self.levels.get(level) else {
+                break;
+            };
+            if current.approvers.iter().any(|id| id == approver_id)
+                && !approved_by.iter().any(|id| id == approver_id)
+            {
+                approved_by.push(approver_id.to_string());
+                in_level += 1;
+                if in_level >= current.required as usize {
+                    level += 1;
+                    in_level = 0;
+                }
+            }
+        }
+
+        let awaiting = match self.levels.get(level) {
+            Some(current) => current
+                .approvers
+                .iter()
+                .filter(|id| !approved_by.contains(id))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+
+        ApprovalProgress {
+            approved_by,
+            required: self.required(),
+            awaiting,
+        }
+    }
+}
+
+impl std::fmt::Display for ApprovalPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, level) in self.levels.iter().enumerate() {
+            if idx > 0 {
+                write!(f, " then ")?;
+            }
+            write!(f, "{} of [{}]", level.required, level.approvers.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for TradeState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Draft" => Ok(Self::Draft),
+            "PendingApproval" => Ok(Self::PendingApproval),
+            "Approved" => Ok(Self::Approved),
+            "Rejected" => Ok(Self::Rejected),
+            "Cancelled" => Ok(Self::Cancelled),
+            "SentToExecute" => Ok(Self::SentToExecute),
+            "Executed" => Ok(Self::Executed),
+            "Booked" => Ok(Self::Booked),
+            _ => Err(anyhow::anyhow!("Unknown trade state: {}", s)),
+        }
+    }
+}
+
+impl TradeState {
+    /// The transition table: whether a witness of this type may be appended in this
+    /// state. Every service mutation consults this table, so it is the single place
+    /// workflow rules live. The state a witness leads to is not part of the table, as it
+    /// can depend on more than the action: an Approve only approves the trade once the
+    /// approval policy is satisfied. [`TradeContext::current_state`] derives it from the
+    /// chain.
+    pub fn transition(&self, action: &WitnessType) -> Result<(), ValidationError> {
+        use TradeState as S;
+        use WitnessType as W;
+
+        let verdict = match (self, action) {
+            (S::Draft, W::Submit { .. }) => Ok(()),
+            (S::Draft, _) => Err("trade has not been submitted"),
+
+            // Updating a rejected trade resubmits it for approval
+            (S::PendingApproval | S::Approved | S::Rejected, W::Update { .. }) => Ok(()),
+            (S::PendingApproval | S::Approved | S::Rejected | S::SentToExecute, W::Cancel) => {
+                Ok(())
+            }
+            (
+                S::PendingApproval | S::Approved | S::Rejected | S::SentToExecute,
+                W::Submit { .. },
+            ) => Err("trade has already been submitted"),
+
+            // An Approve leaves the trade pending until the policy has the approvals it needs
+            (S::PendingApproval, W::Approve { .. } | W::Reject { .. }) => Ok(()),
+            (S::PendingApproval, W::SendToExecute) => Err("trade must be approved first"),
+            (S::PendingApproval, W::Book { .. }) => Err("trade must be sent to execute first"),
+
+            (S::Approved, W::SendToExecute) => Ok(()),
+            (S::Approved, W::Approve { .. } | W::Reject { .. }) => Err("trade is already approved"),
+            (S::Approved, W::Book { .. }) => Err("trade must be sent to execute first"),
+
+            (S::SentToExecute | S::Executed, W::Book { .. }) => Ok(()),
+            (S::SentToExecute | S::Executed, _) => {
+                Err("trade has been sent to execute and can only be booked")
+            }
+
+            (S::Rejected, _) => Err("trade was rejected and must be updated first"),
+
+            (S::Booked, _) => Err("trade has already been booked"),
+            (S::Cancelled, _) => Err("trade has been cancelled"),
+        };
+
+        verdict.map_err(|reason| ValidationError::InvalidTransition {
+            from: self.clone(),
+            action: action.name(),
+            reason,
+        })
+    }
 }
 
 impl Witness {
@@ -98,19 +475,100 @@ impl Witness {
             user_id,
             user_timestamp,
             witness_type,
+            parent_hash: None,
+            signature: None,
+            note: None,
+        }
+    }
+    /// Attach a note, which is signed with the rest of the witness. An empty note is
+    /// not recorded.
+    pub fn with_note(mut self, note: Note) -> Self {
+        self.note = (!note.is_empty()).then_some(note);
+        self
+    }
+    /// Error unless this witness, at `index` in the chain, is timestamped no more than
+    /// `max_clock_skew` before `previous`
+    pub fn check_follows(
+        &self,
+        previous: &Witness,
+        index: usize,
+        max_clock_skew: TimeDelta,
+    ) -> Result<(), ValidationError> {
+        let previous = previous.user_timestamp.to_datetime_utc();
+        let found = self.user_timestamp.to_datetime_utc();
+        if found
+            .checked_add_signed(max_clock_skew)
+            .is_some_and(|allowed| allowed < previous)
+        {
+            return Err(ValidationError::TimestampRegression {
+                index,
+                previous,
+                found,
+            });
+        }
+        Ok(())
+    }
+    /// Whose approval an Approve witness counts as: the approver a delegate acted for,
+    /// otherwise the signer. `None` for every other witness type.
+    pub fn approval_of(&self) -> Option<&str> {
+        match &self.witness_type {
+            WitnessType::Approve { on_behalf_of } => {
+                Some(on_behalf_of.as_deref().unwrap_or(&self.user_id))
+            }
+            _ => None,
         }
     }
-    /// Encode to CBOR then return the hash and the encoded contents.
+    /// Encode to canonical CBOR then return the hash and the encoded contents.
     pub fn serialize_with_hash(&self) -> anyhow::Result<(String, Vec<u8>)> {
-        let cbor = minicbor::to_vec(self)?;
-        let id = self.trade_id.clone();
+        let cbor = to_canonical_vec(self)?;
+        let hash = sha256::digest(&cbor);
+
+        Ok((hash, cbor))
+    }
+
+    /// The bytes covered by the signature: the canonical CBOR encoding with the
+    /// signature removed
+    pub fn signing_payload(&self) -> anyhow::Result<Vec<u8>> {
+        let unsigned = Self {
+            signature: None,
+            ..self.clone()
+        };
+        to_canonical_vec(&unsigned)
+    }
+
+    /// Sign the witness. Must happen after the parent hash is set, as the parent
+    /// hash is part of the signed payload.
+    pub fn sign(&mut self, key: &SigningKey) -> anyhow::Result<()> {
+        let payload = self.signing_payload()?;
+        let signature = key.sign(&payload);
+        self.signature = Some(hex::encode(signature.to_bytes()));
+        Ok(())
+    }
+
+    /// Check the signature was produced by the holder of `key`
+    pub fn verify_signature(&self, key: &VerifyingKey) -> anyhow::Result<()> {
+        let Some(signature) = &self.signature else {
+            return Err(ValidationError::MissingSignature(self.user_id.clone()).into());
+        };
 
-        Ok((id, cbor))
+        let bytes = hex::decode(signature)
+            .map_err(|_| ValidationError::InvalidSignature(self.user_id.clone()))?;
+        let signature = Signature::from_slice(&bytes)
+            .map_err(|_| ValidationError::InvalidSignature(self.user_id.clone()))?;
+
+        key.verify_strict(&self.signing_payload()?, &signature)
+            .map_err(|_| ValidationError::InvalidSignature(self.user_id.clone()))?;
+
+        Ok(())
     }
 }
 impl TradeContext {
+    /// Name of the sled tree holding contexts, keyed by trade_id
+    pub const TREE: &'static str = "contexts";
+
     pub fn new() -> Self {
-        let trade_id = new_uuid_to_bech32("trade_").expect("generate new ID for trade_context ");
+        let trade_id =
+            new_uuid_to_bech32(TRADE_ID_HRP).expect("generate new ID for trade_context ");
         Self {
             trade_id,
             witness_set: vec![],
@@ -123,110 +581,236 @@ impl TradeContext {
             witness_set: vec![],
         }
     }
-    pub fn insert_witness(&mut self, witness: Witness) {
+    /// Append a witness to the chain, linking it to the hash of the current head
+    pub fn insert_witness(&mut self, mut witness: Witness) -> anyhow::Result<()> {
+        witness.parent_hash = self.head_hash()?;
         self.witness_set.push(witness);
+        Ok(())
     }
 
-    /// Serialize to CBOR with content hash for integrity
+    /// Hash of the latest witness in the chain, `None` while the chain is empty
+    pub fn head_hash(&self) -> anyhow::Result<Option<String>> {
+        match self.witness_set.last() {
+            Some(witness) => Ok(Some(witness.serialize_with_hash()?.0)),
+            None => Ok(None),
+        }
+    }
+
+    /// Walk the witness chain and check every witness commits to the hash of its
+    /// predecessor and is not timestamped before it. Returns the first violation found.
+    pub fn verify_chain(&self) -> anyhow::Result<()> {
+        self.verify_chain_with_skew(TimeDelta::zero())
+    }
+
+    /// [`TradeContext::verify_chain`], allowing each witness to be timestamped up to
+    /// `max_clock_skew` before the one it follows, as the service does on append
+    pub fn verify_chain_with_skew(&self, max_clock_skew: TimeDelta) -> anyhow::Result<()> {
+        let mut expected_parent: Option<String> = None;
+
+        for (index, witness) in self.witness_set.iter().enumerate() {
+            if witness.trade_id != self.trade_id {
+                return Err(ValidationError::ForeignWitness {
+                    index,
+                    expected: self.trade_id.clone(),
+                    found: witness.trade_id.clone(),
+                }
+                .into());
+            }
+            if witness.parent_hash != expected_parent {
+                return Err(ValidationError::BrokenChain {
+                    index,
+                    expected: expected_parent,
+                    found: witness.parent_hash.clone(),
+                }
+                .into());
+            }
+            if let Some(previous) = index.checked_sub(1) {
+                witness.check_follows(&self.witness_set[previous], index, max_clock_skew)?;
+            }
+
+            let (hash, _) = witness.serialize_with_hash()?;
+            expected_parent = Some(hash);
+        }
+
+        Ok(())
+    }
+
+    /// Check every witness is signed by the key its `user_id` held at the witness's
+    /// timestamp
+    pub fn verify_signatures(&self, keys: &dyn KeyRegistry) -> anyhow::Result<()> {
+        for witness in self.witness_set.iter() {
+            let key = keys
+                .key_at(&witness.user_id, &witness.user_timestamp)?
+                .ok_or_else(|| ValidationError::UnknownSigner(witness.user_id.clone()))?;
+            witness.verify_signature(&key)?;
+        }
+
+        Ok(())
+    }
+
+    /// Serialize to canonical CBOR with content hash for integrity
     pub fn serialize_with_hash(&self) -> anyhow::Result<(String, Vec<u8>)> {
-        let cbor = minicbor::to_vec(self)?;
+        let cbor = to_canonical_vec(self)?;
         let hash = sha256::digest(&cbor);
         Ok((hash, cbor))
     }
 
-    /// Save to database using trade_id as key
-    pub fn save_to_db(&self, db: &sled::Db) -> anyhow::Result<String> {
+    /// Save to a store using trade_id as key
+    pub fn save_to_store(&self, store: &dyn TradeStore) -> anyhow::Result<String> {
         let (content_hash, cbor) = self.serialize_with_hash()?;
 
         // Use trade_id (unhashed) as the key
-        db.insert(self.trade_id.as_bytes(), cbor)?;
+        store.put_context(&self.trade_id, &cbor)?;
 
         // Return hash for audit/verification purposes
         Ok(content_hash)
     }
 
-    /// Load from database using trade_id
-    pub fn load_from_db(db: &sled::Db, trade_id: &str) -> anyhow::Result<Self> {
-        let bytes = db
-            .get(trade_id.as_bytes())?
+    /// Load from a store using trade_id, rejecting contexts whose witness chain
+    /// has been tampered with
+    pub fn load_from_store(store: &dyn TradeStore, trade_id: &str) -> anyhow::Result<Self> {
+        let bytes = store
+            .get_context(trade_id)?
             .ok_or_else(|| anyhow::anyhow!("Trade not found: {}", trade_id))?;
 
-        let trade_context: TradeContext = minicbor::decode(&bytes)?;
+        Self::from_cbor(&bytes)
+    }
+
+    /// Decode a stored context, rejecting non-canonical bytes, and verify its witness
+    /// chain as [`TradeContext::verify_chain`] does
+    pub fn from_cbor(bytes: &[u8]) -> anyhow::Result<Self> {
+        Self::from_cbor_with_skew(bytes, TimeDelta::zero())
+    }
+
+    /// [`TradeContext::from_cbor`] for a chain written with up to `max_clock_skew`
+    /// between a witness and the one it follows
+    pub fn from_cbor_with_skew(bytes: &[u8], max_clock_skew: TimeDelta) -> anyhow::Result<Self> {
+        let trade_context: TradeContext = decode_canonical(bytes)?;
+        trade_context.verify_chain_with_skew(max_clock_skew)?;
+
         Ok(trade_context)
     }
+    /// The witness history as structured entries, each with the state the trade was
+    /// in once that witness was applied
+    pub fn timeline(&self) -> Vec<TimelineEntry> {
+        self.witness_set
+            .iter()
+            .enumerate()
+            .map(|(idx, wit)| {
+                let progress = match wit.witness_type {
+                    WitnessType::Approve { .. } => self
+                        .approval_progress_at(idx)
+                        .ok()
+                        .filter(|progress| !progress.is_complete()),
+                    _ => None,
+                };
+
+                TimelineEntry {
+                    index: idx,
+                    timestamp: wit.user_timestamp.clone(),
+                    user_id: wit.user_id.clone(),
+                    action: wit.witness_type.name(),
+                    payload: wit.witness_type.clone(),
+                    note: wit.note.clone(),
+                    progress,
+                    state_after: self.state_after(idx),
+                    changes: Vec::new(),
+                }
+            })
+            .collect()
+    }
+
+    /// [`TradeContext::timeline`], with the fields each Update changed resolved from
+    /// the store
+    pub fn timeline_with_changes(
+        &self,
+        store: &dyn TradeStore,
+    ) -> anyhow::Result<Vec<TimelineEntry>> {
+        let mut changes = self.detail_changes(store)?;
+        let mut timeline = self.timeline();
+        for entry in &mut timeline {
+            entry.changes = changes.remove(&entry.index).unwrap_or_default();
+        }
+        Ok(timeline)
+    }
+
+    /// The whole timeline with the trade it belongs to, ready to display
+    pub fn history(&self) -> History {
+        self.history_from(self.timeline())
+    }
+
+    /// The whole timeline, listing the fields each Update changed
+    pub fn history_with_changes(&self, store: &dyn TradeStore) -> anyhow::Result<History> {
+        Ok(self.history_from(self.timeline_with_changes(store)?))
+    }
+
+    fn history_from(&self, entries: Vec<TimelineEntry>) -> History {
+        History {
+            trade_id: self.trade_id.clone(),
+            entries,
+            state: self.current_state(),
+        }
+    }
+
     /// Display the witness history in a human-readable timeline format
     pub fn view_history(&self) {
-        println!("\nTrade Timeline: {}\n", self.trade_id);
+        self.print_history(&self.history(), &TimelineFormat::default());
+    }
 
-        if self.witness_set.is_empty() {
-            println!("  No witnesses recorded yet (Draft state)");
-            return;
-        }
+    /// Display the witness history, listing the fields each Update changed
+    pub fn view_history_with_changes(&self, store: &dyn TradeStore) -> anyhow::Result<()> {
+        self.print_history(
+            &self.history_with_changes(store)?,
+            &TimelineFormat::default(),
+        );
+        Ok(())
+    }
 
-        for (idx, wit) in self.witness_set.iter().enumerate() {
-            let date = wit.user_timestamp.to_datetime_utc();
-            let timestamp = date.format("%Y-%m-%d %H:%M:%S UTC");
+    /// Display the witness history with IDs and timestamps rendered as `format` says
+    pub fn view_history_with_format(&self, format: &TimelineFormat) {
+        self.print_history(&self.history(), format);
+    }
 
-            let (action, details, next_state) = match &wit.witness_type {
-                WitnessType::Submit {
-                    details_hash: _,
-                    requester_id,
-                    approver_id,
-                } => {
-                    let detail_str = format!(
-                        "requester: {}, approver: {}",
-                        Self::truncate_id(requester_id, 12),
-                        Self::truncate_id(approver_id, 12)
-                    );
-                    ("Submit", detail_str, "PendingApproval")
-                }
-                WitnessType::Approve => ("Approve", String::new(), "Approved"),
-                WitnessType::Update { details_hash } => {
-                    let detail_str = format!("new hash: {}...", &details_hash[..8]);
-                    ("Update", detail_str, "PendingApproval")
-                }
-                WitnessType::Cancel => ("Cancel", String::new(), "Cancelled"),
-                WitnessType::SendToExecute => ("SendToExecute", String::new(), "SentToExecute"),
-                WitnessType::Book { strike } => {
-                    let detail_str = format!("strike: {}", strike);
-                    ("Book", detail_str, "Booked")
-                }
-            };
+    fn print_history(&self, history: &History, format: &TimelineFormat) {
+        println!("\n{}\n", history.display(format));
+    }
 
-            let user_display = Self::truncate_id(&wit.user_id, 15);
-
-            if details.is_empty() {
-                println!(
-                    "{:>2}. {} │ {:<17} │ {} → {}",
-                    idx + 1,
-                    timestamp,
-                    action,
-                    user_display,
-                    next_state
-                );
-            } else {
-                println!(
-                    "{:>2}. {} │ {:<17} │ {} → {}",
-                    idx + 1,
-                    timestamp,
-                    action,
-                    user_display,
-                    next_state
-                );
-                println!("     {}", details);
-            }
+    /// State the trade was in once the witness at `index` was applied
+    pub fn state_at_index(&self, index: usize) -> anyhow::Result<TradeState> {
+        let len = self.witness_set.len();
+        if index >= len {
+            return Err(ValidationError::NoSuchWitness { index, len }.into());
+        }
+        Ok(self.state_after(index))
+    }
+
+    /// State the trade was in at `at`, derived from the witnesses recorded by then.
+    /// A trade with no witness by then is a Draft.
+    pub fn state_at(&self, at: TimeStamp<Utc>) -> TradeState {
+        match self.witnesses_at(&at) {
+            0 => TradeState::Draft,
+            count => self.state_after(count - 1),
         }
+    }
 
-        println!("\nCurrent State: {:?}\n", self.current_state());
+    /// How many witnesses had been recorded by `at`: the leading witnesses of the chain
+    /// timestamped at or before it. Counting stops at the first later witness, so one
+    /// recorded out of order cannot pull its successors back in time.
+    pub fn witnesses_at(&self, at: &TimeStamp<Utc>) -> usize {
+        let at = at.to_datetime_utc();
+        self.witness_set
+            .iter()
+            .position(|witness| witness.user_timestamp.to_datetime_utc() > at)
+            .unwrap_or(self.witness_set.len())
     }
 
-    /// Truncate long IDs for display purposes
-    fn truncate_id(id: &str, max_len: usize) -> String {
-        if id.len() <= max_len {
-            id.to_string()
-        } else {
-            format!("{}...", &id[..max_len])
+    /// State once the witness at `index` was applied, derived from the chain up to it
+    fn state_after(&self, index: usize) -> TradeState {
+        TradeContext {
+            trade_id: self.trade_id.clone(),
+            witness_set: self.witness_set[..=index].to_vec(),
         }
+        .current_state()
     }
 
     /// Determine current state by examining witness chain
@@ -263,9 +847,10 @@ impl TradeContext {
         }
 
         // No terminal states found, walk backwards for normal state derivation
+        let last = self.witness_set.len() - 1;
         let mut approved = false;
 
-        for witness in self.witness_set.iter().rev() {
+        for (idx, witness) in self.witness_set.iter().enumerate().rev() {
             match &witness.witness_type {
                 WitnessType::Book { .. } | WitnessType::Cancel => {
                     // Should have been caught above
@@ -276,27 +861,20 @@ impl TradeContext {
                     // If we see this, we must have been approved prior.
                     return TradeState::SentToExecute;
                 }
-                WitnessType::Submit { .. } => {
-                    // This is an "approval required" point.
-                    // If we've seen an approval *after* this (walking rev), we're Approved.
-                    if approved {
+                WitnessType::Submit { .. } | WitnessType::Update { .. } => {
+                    // This is an "approval required" point. We're Approved once the
+                    // approvals seen *after* it (walking rev) satisfy the policy.
+                    if self.progress_between(idx, last).is_complete() {
                         return TradeState::Approved;
                     } else {
-                        // No approval seen since this submit.
                         return TradeState::PendingApproval;
                     }
                 }
-                WitnessType::Update { .. } => {
-                    // This is also an "approval required" point, just like Submit.
-                    // If we've seen an approval *after* this (walking rev), we're Approved.
-                    if approved {
-                        return TradeState::Approved;
-                    } else {
-                        // No approval seen since this update.
-                        return TradeState::PendingApproval;
-                    }
+                WitnessType::Reject { .. } => {
+                    // Nothing after the Reject resubmitted the trade
+                    return TradeState::Rejected;
                 }
-                WitnessType::Approve => {
+                WitnessType::Approve { .. } => {
                     approved = true;
                     // Keep checking - might be an Update/Submit before this
                 }
@@ -318,7 +896,9 @@ impl TradeContext {
         matches!(self.current_state(), TradeState::PendingApproval)
     }
 
-    /// Get the expected approver from the latest Submit or Update with approver info
+    /// Get the expected approver from the latest Submit. For a quorum policy this is
+    /// only the first approver, see [`TradeContext::approval_policy`]. Delegations are
+    /// kept outside the chain, see `TradeService::get_expected_approvers`.
     pub fn get_expected_approver(&self) -> anyhow::Result<String> {
         // Walk backwards to find the latest Submit or Update
         for witness in self.witness_set.iter().rev() {
@@ -334,8 +914,197 @@ impl TradeContext {
             }
         }
 
-        Err(anyhow::anyhow!(
-            "No Submit witness found with approver information"
-        ))
+        Err(ValidationError::MissingSubmit.into())
+    }
+    /// The approval policy of the latest Submit, a single approver if it has none
+    pub fn approval_policy(&self) -> anyhow::Result<ApprovalPolicy> {
+        self.witness_set
+            .iter()
+            .rev()
+            .find_map(|witness| match &witness.witness_type {
+                WitnessType::Submit {
+                    approver_id,
+                    approval_policy,
+                    ..
+                } => Some(
+                    approval_policy
+                        .clone()
+                        .unwrap_or_else(|| ApprovalPolicy::single(approver_id)),
+                ),
+                _ => None,
+            })
+            .ok_or_else(|| ValidationError::MissingSubmit.into())
+    }
+    /// Approval progress since the latest Submit or Update
+    pub fn approval_progress(&self) -> anyhow::Result<ApprovalProgress> {
+        if self.witness_set.is_empty() {
+            return Err(ValidationError::MissingSubmit.into());
+        }
+        self.approval_progress_at(self.witness_set.len() - 1)
+    }
+    /// Approval progress once the witness at `index` was applied, counting the
+    /// approvals since the latest Submit or Update at or before it
+    pub fn approval_progress_at(&self, index: usize) -> anyhow::Result<ApprovalProgress> {
+        let len = self.witness_set.len();
+        if index >= len {
+            return Err(ValidationError::NoSuchWitness { index, len }.into());
+        }
+
+        let start = self.witness_set[..=index]
+            .iter()
+            .rposition(|witness| {
+                matches!(
+                    witness.witness_type,
+                    WitnessType::Submit { .. } | WitnessType::Update { .. }
+                )
+            })
+            .ok_or(ValidationError::MissingSubmit)?;
+
+        Ok(self.progress_between(start, index))
+    }
+    /// Who signed the Approves since the latest Submit or Update. Unlike the
+    /// `approved_by` of [`TradeContext::approval_progress`], a delegate appears as
+    /// themselves rather than as the approver they acted for.
+    pub fn approval_signers(&self) -> Vec<&str> {
+        let start = self
+            .witness_set
+            .iter()
+            .rposition(|witness| {
+                matches!(
+                    witness.witness_type,
+                    WitnessType::Submit { .. } | WitnessType::Update { .. }
+                )
+            })
+            .map_or(0, |idx| idx + 1);
+
+        self.witness_set[start..]
+            .iter()
+            .filter(|witness| matches!(witness.witness_type, WitnessType::Approve { .. }))
+            .map(|witness| witness.user_id.as_str())
+            .collect()
+    }
+    /// Tally the approvals after the Submit or Update at `start`, up to and including
+    /// `end`, against the policy of the latest Submit at or before `start`
+    fn progress_between(&self, start: usize, end: usize) -> ApprovalProgress {
+        let approvals: Vec<&str> = self.witness_set[start + 1..=end]
+            .iter()
+            .filter_map(Witness::approval_of)
+            .collect();
+
+        let submit = self.witness_set[..=start].iter().rev().find_map(|witness| {
+            match &witness.witness_type {
+                WitnessType::Submit {
+                    approver_id,
+                    approval_policy,
+                    ..
+                } => Some((approver_id, approval_policy)),
+                _ => None,
+            }
+        });
+
+        match submit {
+            Some((_, Some(policy))) => policy.tally(approvals),
+            // Without a policy the service only accepts Approves from `approver_id`,
+            // and the first one approves the trade
+            Some((approver_id, None)) => ApprovalProgress {
+                approved_by: approvals.iter().take(1).map(|id| id.to_string()).collect(),
+                required: 1,
+                awaiting: if approvals.is_empty() {
+                    vec![approver_id.clone()]
+                } else {
+                    Vec::new()
+                },
+            },
+            None => ApprovalProgress {
+                approved_by: approvals.iter().take(1).map(|id| id.to_string()).collect(),
+                required: 1,
+                awaiting: Vec::new(),
+            },
+        }
+    }
+    /// Principals responsible for the details currently in force: the requester and
+    /// signer of the latest Submit, and the signer of the latest Update after it
+    pub fn originators(&self) -> Vec<String> {
+        let mut originators: Vec<String> = Vec::new();
+        let mut last_update = None;
+
+        for witness in self.witness_set.iter().rev() {
+            match &witness.witness_type {
+                WitnessType::Update { .. } if last_update.is_none() => {
+                    last_update = Some(witness.user_id.clone());
+                }
+                WitnessType::Submit { requester_id, .. } => {
+                    originators.push(requester_id.clone());
+                    originators.push(witness.user_id.clone());
+                    break;
+                }
+                _ => {}
+            }
+        }
+        originators.extend(last_update);
+        originators.sort();
+        originators.dedup();
+        originators
+    }
+    /// Who requested the trade, taken from the latest Submit
+    pub fn get_requester(&self) -> anyhow::Result<String> {
+        self.witness_set
+            .iter()
+            .rev()
+            .find_map(|witness| match &witness.witness_type {
+                WitnessType::Submit { requester_id, .. } => Some(requester_id.clone()),
+                _ => None,
+            })
+            .ok_or_else(|| ValidationError::MissingSubmit.into())
+    }
+    /// Hash of the details currently in force: those of the latest Submit or Update
+    pub fn current_details_hash(&self) -> anyhow::Result<String> {
+        if self.witness_set.is_empty() {
+            return Err(ValidationError::MissingSubmit.into());
+        }
+        self.details_hash_at(self.witness_set.len() - 1)
+    }
+    /// Hash of the details in force once the witness at `index` was applied: those of
+    /// the latest Submit or Update at or before it
+    pub fn details_hash_at(&self, index: usize) -> anyhow::Result<String> {
+        let len = self.witness_set.len();
+        if index >= len {
+            return Err(ValidationError::NoSuchWitness { index, len }.into());
+        }
+
+        self.witness_set[..=index]
+            .iter()
+            .rev()
+            .find_map(|witness| match &witness.witness_type {
+                WitnessType::Submit { details_hash, .. } | WitnessType::Update { details_hash } => {
+                    Some(details_hash.clone())
+                }
+                _ => None,
+            })
+            .ok_or_else(|| ValidationError::MissingSubmit.into())
+    }
+    /// Fetch the details currently in force from a store, verifying their content hash
+    pub fn current_details(&self, store: &dyn TradeStore) -> anyhow::Result<TradeDetails> {
+        TradeDetails::load_from_store(store, &self.current_details_hash()?)
+    }
+    /// The field changes introduced by each Update, keyed by the Update's index
+    pub fn detail_changes(
+        &self,
+        store: &dyn TradeStore,
+    ) -> anyhow::Result<BTreeMap<usize, Vec<FieldChange>>> {
+        let mut changes = BTreeMap::new();
+
+        for (idx, witness) in self.witness_set.iter().enumerate() {
+            if let WitnessType::Update { details_hash } = &witness.witness_type {
+                if idx == 0 {
+                    continue;
+                }
+                let before = TradeDetails::load_from_store(store, &self.details_hash_at(idx - 1)?)?;
+                let after = TradeDetails::load_from_store(store, details_hash)?;
+                changes.insert(idx, before.diff(&after));
+            }
+        }
+
+        Ok(changes)
     }
 }
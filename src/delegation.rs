@@ -0,0 +1,197 @@
+//! Delegation of approval authority
+//!
+//! An approver who is away can hand their approvals to a delegate for a window of
+//! time, optionally only for trades up to a notional ceiling. While a [`Delegation`]
+//! is active the service accepts an Approve from the delegate in place of the
+//! delegator. The witness records both: the delegate signs it as `user_id`, and the
+//! delegator is named in `on_behalf_of`, so the approval counts towards the policy as
+//! the delegator's.
+
+use super::error::ValidationError;
+use super::trade::TimeStamp;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Approval authority handed from `delegator` to `delegate` for
+/// `valid_from..valid_until`
+#[derive(Debug, Clone, PartialEq, Eq, minicbor::Encode, minicbor::Decode)]
+pub struct Delegation {
+    #[n(0)]
+    pub delegator: String,
+    #[n(1)]
+    pub delegate: String,
+    /// Start of the window, inclusive
+    #[n(2)]
+    pub valid_from: TimeStamp<Utc>,
+    /// End of the window, exclusive
+    #[n(3)]
+    pub valid_until: TimeStamp<Utc>,
+    /// Largest notional amount the delegate may approve, any amount if `None`
+    #[n(4)]
+    pub max_notional: Option<u64>,
+}
+
+impl Delegation {
+    pub fn new(
+        delegator: impl Into<String>,
+        delegate: impl Into<String>,
+        valid_from: TimeStamp<Utc>,
+        valid_until: TimeStamp<Utc>,
+    ) -> Self {
+        Self {
+            delegator: delegator.into(),
+            delegate: delegate.into(),
+            valid_from,
+            valid_until,
+            max_notional: None,
+        }
+    }
+
+    /// Only cover trades with a notional amount up to `max_notional`
+    pub fn with_max_notional(mut self, max_notional: u64) -> Self {
+        self.max_notional = Some(max_notional);
+        self
+    }
+
+    /// Reject delegations to oneself and windows that are empty
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if self.delegator == self.delegate {
+            return Err(ValidationError::InvalidDelegation(format!(
+                "`{}` cannot delegate to themselves",
+                self.delegator
+            )));
+        }
+        if self.valid_from.to_datetime_utc() >= self.valid_until.to_datetime_utc() {
+            return Err(ValidationError::InvalidDelegation(
+                "valid_from is not before valid_until".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the delegate may approve a trade of `notional` on the delegator's
+    /// behalf at time `at`
+    pub fn covers(&self, notional: u64, at: &TimeStamp<Utc>) -> bool {
+        let at = at.to_datetime_utc();
+        self.valid_from.to_datetime_utc() <= at
+            && at < self.valid_until.to_datetime_utc()
+            && self.max_notional.is_none_or(|max| notional <= max)
+    }
+}
+
+/// An approver a trade is waiting on, and the delegates currently able to approve it
+/// for them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedApprover {
+    pub approver_id: String,
+    pub delegates: Vec<String>,
+}
+
+/// Storage of delegations, at most one per delegator and delegate
+pub trait DelegationStore: Send + Sync {
+    /// Record (or replace) the delegation between its delegator and delegate
+    fn put(&self, delegation: &Delegation) -> anyhow::Result<()>;
+    /// Remove the delegation from `delegator` to `delegate`, returning whether there
+    /// was one
+    fn revoke(&self, delegator: &str, delegate: &str) -> anyhow::Result<bool>;
+    /// Every delegation made by `delegator`, expired ones included
+    fn delegations_from(&self, delegator: &str) -> anyhow::Result<Vec<Delegation>>;
+}
+
+/// Delegations held in memory, useful for tests and short-lived services
+#[derive(Default)]
+pub struct InMemoryDelegationStore {
+    delegations: RwLock<HashMap<String, Vec<Delegation>>>,
+}
+
+impl InMemoryDelegationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl DelegationStore for InMemoryDelegationStore {
+    fn put(&self, delegation: &Delegation) -> anyhow::Result<()> {
+        let mut delegations = self
+            .delegations
+            .write()
+            .map_err(|_| anyhow::anyhow!("delegation store lock poisoned"))?;
+        let from = delegations.entry(delegation.delegator.clone()).or_default();
+        from.retain(|existing| existing.delegate != delegation.delegate);
+        from.push(delegation.clone());
+        Ok(())
+    }
+
+    fn revoke(&self, delegator: &str, delegate: &str) -> anyhow::Result<bool> {
+        let mut delegations = self
+            .delegations
+            .write()
+            .map_err(|_| anyhow::anyhow!("delegation store lock poisoned"))?;
+        let Some(from) = delegations.get_mut(delegator) else {
+            return Ok(false);
+        };
+        let before = from.len();
+        from.retain(|existing| existing.delegate != delegate);
+        Ok(from.len() < before)
+    }
+
+    fn delegations_from(&self, delegator: &str) -> anyhow::Result<Vec<Delegation>> {
+        let delegations = self
+            .delegations
+            .read()
+            .map_err(|_| anyhow::anyhow!("delegation store lock poisoned"))?;
+        Ok(delegations.get(delegator).cloned().unwrap_or_default())
+    }
+}
+
+/// Delegations persisted in their own sled tree, keyed by delegator then delegate
+pub struct SledDelegationStore {
+    tree: sled::Tree,
+}
+
+impl SledDelegationStore {
+    /// Name of the sled tree holding the delegations
+    pub const TREE: &'static str = "delegations";
+
+    pub fn new(db: &sled::Db) -> anyhow::Result<Self> {
+        let tree = db.open_tree(Self::TREE)?;
+        Ok(Self { tree })
+    }
+
+    /// Prefix shared by every delegation `delegator` made. The separator keeps one
+    /// user ID from matching another it is a prefix of.
+    fn prefix(delegator: &str) -> Vec<u8> {
+        let mut key = delegator.as_bytes().to_vec();
+        key.push(0);
+        key
+    }
+
+    fn key(delegator: &str, delegate: &str) -> Vec<u8> {
+        let mut key = Self::prefix(delegator);
+        key.extend_from_slice(delegate.as_bytes());
+        key
+    }
+}
+
+impl DelegationStore for SledDelegationStore {
+    fn put(&self, delegation: &Delegation) -> anyhow::Result<()> {
+        let key = Self::key(&delegation.delegator, &delegation.delegate);
+        self.tree.insert(key, minicbor::to_vec(delegation)?)?;
+        Ok(())
+    }
+
+    fn revoke(&self, delegator: &str, delegate: &str) -> anyhow::Result<bool> {
+        Ok(self.tree.remove(Self::key(delegator, delegate))?.is_some())
+    }
+
+    fn delegations_from(&self, delegator: &str) -> anyhow::Result<Vec<Delegation>> {
+        self.tree
+            .scan_prefix(Self::prefix(delegator))
+            .map(|entry| {
+                let (_, bytes) = entry?;
+                Ok(minicbor::decode(&bytes)?)
+            })
+            .collect()
+    }
+}
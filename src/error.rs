@@ -1,7 +1,8 @@
 //! Validation and operational error types
-use chrono::Utc;
+use chrono::{DateTime, NaiveDate, Utc};
 
-use super::trade::TimeStamp;
+use super::context::TradeState;
+use super::trade::{Currency, TimeStamp};
 
 #[derive(thiserror::Error, Debug)]
 pub enum ValidationError {
@@ -9,16 +10,96 @@ pub enum ValidationError {
         "dates failed to meet the following condition: trade_date <= value_date <= delivery_date"
     )]
     DateValidation,
+    #[deprecated(note = "cancelled trades are reported as `InvalidTransition`")]
     #[error("Trade contained a cancel witness")]
     IsCancelled,
+    #[deprecated(note = "unapproved trades are reported as `InvalidTransition`")]
     #[error("Trade is missing a valid approved witness")]
     NoApproved,
+    #[deprecated(note = "trades awaiting approval are reported as `InvalidTransition`")]
     #[error("Update witness was found, but no subsequent 'approve'")]
     PendingApproval,
     #[error("Trade is missing a submit witness")]
     MissingSubmit,
+    #[deprecated(note = "booked trades are reported as `InvalidTransition`")]
     #[error("Trade has already been executed and booked")]
     AlreadyExecuted,
+    #[error("Witness chain broken at index {index}: expected parent {expected:?}, found {found:?}")]
+    BrokenChain {
+        index: usize,
+        expected: Option<String>,
+        found: Option<String>,
+    },
+    #[error("Witness at index {index} belongs to trade `{found}`, expected `{expected}`")]
+    ForeignWitness {
+        index: usize,
+        expected: String,
+        found: String,
+    },
+    #[error(
+        "Witness at index {index} is timestamped {found}, before the {previous} of the witness it follows"
+    )]
+    TimestampRegression {
+        index: usize,
+        previous: DateTime<Utc>,
+        found: DateTime<Utc>,
+    },
+    #[error("Witness by `{0}` is not signed")]
+    MissingSignature(String),
+    #[error("Signature by `{0}` failed to verify")]
+    InvalidSignature(String),
+    #[error("No public key registered for `{0}`")]
+    UnknownSigner(String),
+    #[error("`{0}` already has a registered key, rotate it instead")]
+    KeyAlreadyRegistered(String),
+    #[error("Invalid key rotation: {0}")]
+    InvalidKeyRotation(String),
+    #[error("Witness signed for {signed_at} is too far from the service's time {now}")]
+    UntimelySignature {
+        signed_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    },
+    #[error("`{0}` is not a trade ID")]
+    InvalidTradeId(String),
+    #[error("Cannot {action} a trade in {from:?} state: {reason}")]
+    InvalidTransition {
+        from: TradeState,
+        action: &'static str,
+        reason: &'static str,
+    },
+    #[error("CBOR rejected by the canonical encoding rules: {0}")]
+    NonCanonical(String),
+    #[error("Stored content under `{key}` hashes to `{found}`")]
+    ContentHashMismatch { key: String, found: String },
+    #[error("Trade has no witness at index {index}, it has {len}")]
+    NoSuchWitness { index: usize, len: usize },
+    #[error("Invalid approval policy: {0}")]
+    InvalidApprovalPolicy(String),
+    #[error("Invalid routing rule `{rule}`: {reason}")]
+    InvalidRoutingRule { rule: String, reason: String },
+    #[error("Invalid notional limit: {0}")]
+    InvalidLimit(String),
+    #[error("Invalid delegation: {0}")]
+    InvalidDelegation(String),
+    #[error("Invalid holiday calendar: {0}")]
+    InvalidCalendar(String),
+    #[error(
+        "{field} {date} is not a business day in {currencies}, the next joint business day is {next}"
+    )]
+    NonBusinessDay {
+        field: &'static str,
+        date: NaiveDate,
+        currencies: String,
+        next: NaiveDate,
+    },
+    #[error("{days} business days after {date} is past the last supported date")]
+    DateOutOfRange { date: NaiveDate, days: u32 },
+    #[error("No joint business day in {currencies} within {days} days of {date}")]
+    NoBusinessDay {
+        date: NaiveDate,
+        currencies: String,
+        days: u32,
+    },
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -29,4 +110,112 @@ pub enum TradeError {
     InvalidEntity(Option<String>),
     #[error("Currency Ticker does not exist")]
     InvalidCurrency,
+    #[error("Direction is not set")]
+    MissingDirection,
+    #[error("`{0}` amount is set to zero")]
+    ZeroAmount(String),
+}
+
+/// Errors returned by the service layer. Callers can match on the variant instead of
+/// parsing messages, e.g. to map failures onto HTTP status codes.
+#[derive(thiserror::Error, Debug)]
+pub enum ServiceError {
+    #[error("Trade not found: {trade_id}")]
+    NotFound { trade_id: String },
+    #[error("Trade details not found: {details_hash}")]
+    DetailsNotFound { details_hash: String },
+    #[error("Cannot {action} a trade in {from:?} state: {reason}")]
+    InvalidTransition {
+        from: TradeState,
+        action: &'static str,
+        reason: &'static str,
+    },
+    #[error("Unauthorized approver. Expected: {expected}, Got: {got}")]
+    UnauthorizedApprover { expected: String, got: String },
+    #[error("No routing rule matches the trade details")]
+    NoMatchingRule,
+    #[error("`{approver_id}` has already approved this version of the trade")]
+    AlreadyApproved { approver_id: String },
+    #[error("`{user_id}` cannot {action} a trade they requested, submitted or last updated")]
+    SegregationOfDuties {
+        user_id: String,
+        action: &'static str,
+    },
+    #[error("A reason is required to {action} a trade")]
+    MissingReason { action: &'static str },
+    #[error(
+        "Notional limit for {scope} in {currency:?} breached: {requested} requested with {exposure} of {cap} used, {headroom} headroom"
+    )]
+    LimitBreach {
+        scope: String,
+        currency: Currency,
+        cap: u64,
+        exposure: u64,
+        requested: u64,
+        headroom: u64,
+    },
+    #[error("Witness for `{trade_id}` is timestamped {found}, before the {head} of the chain head")]
+    ClockBehind {
+        trade_id: String,
+        head: DateTime<Utc>,
+        found: DateTime<Utc>,
+    },
+    #[error("Trade `{trade_id}` was modified concurrently, reload and retry")]
+    ConcurrentModification { trade_id: String },
+    #[error(transparent)]
+    InvalidDetails(TradeError),
+    #[error(transparent)]
+    Validation(ValidationError),
+    #[error("Storage error: {0}")]
+    Storage(#[from] sled::Error),
+    #[error("Failed to decode CBOR: {0}")]
+    Codec(#[from] minicbor::decode::Error),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl From<ValidationError> for ServiceError {
+    fn from(error: ValidationError) -> Self {
+        match error {
+            ValidationError::InvalidTransition {
+                from,
+                action,
+                reason,
+            } => Self::InvalidTransition {
+                from,
+                action,
+                reason,
+            },
+            error => Self::Validation(error),
+        }
+    }
+}
+
+impl From<TradeError> for ServiceError {
+    fn from(error: TradeError) -> Self {
+        Self::InvalidDetails(error)
+    }
+}
+
+/// The context and trade layers report errors through `anyhow`. Recover the typed
+/// error underneath where there is one, so it surfaces as its own variant.
+impl From<anyhow::Error> for ServiceError {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<ValidationError>() {
+            Ok(error) => return error.into(),
+            Err(error) => error,
+        };
+        let error = match error.downcast::<TradeError>() {
+            Ok(error) => return error.into(),
+            Err(error) => error,
+        };
+        let error = match error.downcast::<sled::Error>() {
+            Ok(error) => return error.into(),
+            Err(error) => error,
+        };
+        match error.downcast::<minicbor::decode::Error>() {
+            Ok(error) => error.into(),
+            Err(error) => Self::Other(error),
+        }
+    }
 }
@@ -0,0 +1,355 @@
+//! JSON and CSV export of trade histories
+//!
+//! A [`TradeExport`] resolves everything a reader needs from a [`TradeContext`]: each
+//! witness with its actor, timestamp and note, the state the trade was in once it was
+//! applied, and every version of the trade details the chain refers to. It renders as
+//! JSON, or as flat CSV with one row per witness for spreadsheets.
+//!
+//! The layout is versioned by [`SCHEMA_VERSION`]. Fields are only added at the end of
+//! a version; renaming, removing or reordering one bumps it.
+//!
+//! ## JSON
+//!
+//! ```text
+//! {
+//!   "schema_version": 1,
+//!   "trade_id": "trade_1...",
+//!   "state": "Booked",
+//!   "steps": [
+//!     {
+//!       "index": 0,
+//!       "witness_hash": "<hex sha256>",
+//!       "timestamp": "<RFC 3339>",
+//!       "user_id": "...",
+//!       "action": "Submit",
+//!       "witness": { "type": "Submit", "details_hash": "...", ... },
+//!       "note": { "reason": "...", "tags": [{ "key": "...", "value": "..." }] },
+//!       "signature": "<hex>",
+//!       "state_after": "PendingApproval",
+//!       "details_hash": "<hex sha256>"
+//!     }
+//!   ],
+//!   "versions": [{ "details_hash": "...", "details": { "trading_entity": ..., ... } }]
+//! }
+//! ```
+//!
+//! `note` and `signature` are left out when absent. `versions` lists each version of
+//! the details once, in the order the chain introduced them. A bulk export is
+//! `{"schema_version": 1, "trades": [...]}` with trades ordered by trade_id.
+//!
+//! ## CSV
+//!
+//! One header row of [`CSV_COLUMNS`], then one row per witness. The detail columns hold
+//! the details in force after the witness, so each row stands on its own. Empty cells
+//! mean unset; dates are RFC 3339 and tags are `key=value` joined by `; `. A bulk
+//! export writes the header once.
+
+use super::context::{Note, TradeContext, TradeState, WitnessType};
+use super::keys::KeyRegistry;
+use super::store::TradeStore;
+use super::trade::{TimeStamp, TradeDetails};
+use chrono::{TimeDelta, Utc};
+use serde::Serialize;
+use std::io::Write;
+
+/// Version of the export layout, recorded in every JSON export
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Header of the CSV export, in column order
+pub const CSV_COLUMNS: [&str; 24] = [
+    "trade_id",
+    "index",
+    "timestamp",
+    "user_id",
+    "action",
+    "state_after",
+    "on_behalf_of",
+    "approvers",
+    "rejection_reason",
+    "book_strike",
+    "reason",
+    "tags",
+    "details_hash",
+    "trading_entity",
+    "counter_party",
+    "direction",
+    "notional_currency",
+    "notional_amount",
+    "underlying_currency",
+    "underlying_amount",
+    "trade_date",
+    "value_date",
+    "delivery_date",
+    "strike",
+];
+
+/// Output format of an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            _ => Err(anyhow::anyhow!("Unknown export format: {}", s)),
+        }
+    }
+}
+
+/// A trade's history with its derived states and resolved details
+#[derive(Debug, Serialize)]
+pub struct TradeExport {
+    pub schema_version: u32,
+    pub trade_id: String,
+    /// State after the last witness
+    pub state: TradeState,
+    pub steps: Vec<ExportedStep>,
+    pub versions: Vec<DetailsVersion>,
+}
+
+/// One witness of the chain and where it left the trade
+#[derive(Debug, Serialize)]
+pub struct ExportedStep {
+    pub index: usize,
+    /// Hash the next witness refers to as its parent
+    pub witness_hash: String,
+    pub timestamp: TimeStamp<Utc>,
+    pub user_id: String,
+    pub action: &'static str,
+    pub witness: WitnessType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<Note>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    pub state_after: TradeState,
+    /// Details in force after this witness, `None` before any Submit
+    pub details_hash: Option<String>,
+}
+
+/// A version of the trade details and the hash it is stored under
+#[derive(Debug, Serialize)]
+pub struct DetailsVersion {
+    pub details_hash: String,
+    pub details: TradeDetails,
+}
+
+/// Every trade in a store, as written by [`write_store_json`]
+#[derive(Serialize)]
+struct StoreExport<'a> {
+    schema_version: u32,
+    trades: &'a [TradeExport],
+}
+
+impl TradeExport {
+    /// Resolve a context against the store holding its trade details
+    pub fn from_context(
+        trade_context: &TradeContext,
+        store: &dyn TradeStore,
+    ) -> anyhow::Result<Self> {
+        let mut steps = Vec::with_capacity(trade_context.witness_set.len());
+        let mut versions: Vec<DetailsVersion> = Vec::new();
+
+        let timeline = trade_context.timeline();
+        for (entry, witness) in timeline.into_iter().zip(&trade_context.witness_set) {
+            let details_hash = trade_context.details_hash_at(entry.index).ok();
+            if let Some(hash) = &details_hash
+                && versions.iter().all(|version| &version.details_hash != hash)
+            {
+                versions.push(DetailsVersion {
+                    details_hash: hash.clone(),
+                    details: TradeDetails::load_from_store(store, hash)?,
+                });
+            }
+
+            steps.push(ExportedStep {
+                index: entry.index,
+                witness_hash: witness.serialize_with_hash()?.0,
+                timestamp: entry.timestamp,
+                user_id: entry.user_id,
+                action: entry.action,
+                witness: entry.payload,
+                note: entry.note,
+                signature: witness.signature.clone(),
+                state_after: entry.state_after,
+                details_hash,
+            });
+        }
+
+        Ok(Self {
+            schema_version: SCHEMA_VERSION,
+            trade_id: trade_context.trade_id.clone(),
+            state: trade_context.current_state(),
+            steps,
+            versions,
+        })
+    }
+
+    /// Pretty-printed JSON of the export
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// The export as CSV, header included
+    pub fn to_csv(&self) -> anyhow::Result<String> {
+        let mut out = Vec::new();
+        write_csv_row(&mut out, CSV_COLUMNS)?;
+        self.write_csv_rows(&mut out)?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    /// Write one CSV row per witness, without the header
+    pub fn write_csv_rows(&self, out: &mut dyn Write) -> anyhow::Result<()> {
+        for step in &self.steps {
+            let details = step.details_hash.as_ref().and_then(|hash| {
+                self.versions
+                    .iter()
+                    .find(|version| &version.details_hash == hash)
+            });
+
+            let (on_behalf_of, approvers, rejection_reason, book_strike) = match &step.witness {
+                WitnessType::Submit {
+                    approver_id,
+                    approval_policy,
+                    ..
+                } => {
+                    let approvers = match approval_policy {
+                        Some(policy) => policy.to_string(),
+                        None => approver_id.clone(),
+                    };
+                    (None, Some(approvers), None, None)
+                }
+                WitnessType::Approve { on_behalf_of } => (on_behalf_of.clone(), None, None, None),
+                WitnessType::Reject { reason } => (None, None, Some(reason.clone()), None),
+                WitnessType::Book { strike } => (None, None, None, Some(strike.to_string())),
+                WitnessType::Cancel | WitnessType::Update { .. } | WitnessType::SendToExecute => {
+                    (None, None, None, None)
+                }
+            };
+            let note = step.note.as_ref();
+            let tags = note.filter(|note| !note.tags.is_empty()).map(|note| {
+                note.tags
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            });
+
+            let mut row = vec![
+                Some(self.trade_id.clone()),
+                Some(step.index.to_string()),
+                Some(step.timestamp.to_datetime_utc().to_rfc3339()),
+                Some(step.user_id.clone()),
+                Some(step.action.to_string()),
+                Some(format!("{:?}", step.state_after)),
+                on_behalf_of,
+                approvers,
+                rejection_reason,
+                book_strike,
+                note.and_then(|note| note.reason.clone()),
+                tags,
+                step.details_hash.clone(),
+            ];
+            match details {
+                Some(version) => row.extend(
+                    version
+                        .details
+                        .rendered_fields()
+                        .into_iter()
+                        .map(|(_, value)| value),
+                ),
+                None => row.resize(CSV_COLUMNS.len(), None),
+            }
+
+            write_csv_row(out, row.iter().map(|cell| cell.as_deref().unwrap_or("")))?;
+        }
+        Ok(())
+    }
+}
+
+/// Export every trade in the store, ordered by trade_id. Every witness must carry a
+/// valid signature from the key `keys` holds for its signer, and every chain must keep
+/// its timestamps within `max_clock_skew` as the service does, or the export fails.
+pub fn export_store(
+    store: &dyn TradeStore,
+    keys: &dyn KeyRegistry,
+    max_clock_skew: TimeDelta,
+) -> anyhow::Result<Vec<TradeExport>> {
+    store
+        .iter_contexts()
+        .map(|entry| {
+            let (_, cbor) = entry?;
+            TradeExport::from_context(&verified_context(&cbor, keys, max_clock_skew)?, store)
+        })
+        .collect()
+}
+
+/// Write every trade in the store as one JSON document, see [`export_store`]
+pub fn write_store_json(
+    store: &dyn TradeStore,
+    keys: &dyn KeyRegistry,
+    max_clock_skew: TimeDelta,
+    out: &mut dyn Write,
+) -> anyhow::Result<()> {
+    let trades = export_store(store, keys, max_clock_skew)?;
+    let export = StoreExport {
+        schema_version: SCHEMA_VERSION,
+        trades: &trades,
+    };
+    serde_json::to_writer_pretty(&mut *out, &export)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Write every trade in the store as CSV under a single header, one trade at a time.
+/// Signatures and timestamps are checked as in [`export_store`]; rows already written
+/// for earlier trades stay in `out` when a later one fails.
+pub fn write_store_csv(
+    store: &dyn TradeStore,
+    keys: &dyn KeyRegistry,
+    max_clock_skew: TimeDelta,
+    out: &mut dyn Write,
+) -> anyhow::Result<()> {
+    write_csv_row(out, CSV_COLUMNS)?;
+    for entry in store.iter_contexts() {
+        let (_, cbor) = entry?;
+        TradeExport::from_context(&verified_context(&cbor, keys, max_clock_skew)?, store)?
+            .write_csv_rows(out)?;
+    }
+    Ok(())
+}
+
+/// Decode a stored context and check its chain and the signature on every witness
+fn verified_context(
+    cbor: &[u8],
+    keys: &dyn KeyRegistry,
+    max_clock_skew: TimeDelta,
+) -> anyhow::Result<TradeContext> {
+    let trade_context = TradeContext::from_cbor_with_skew(cbor, max_clock_skew)?;
+    trade_context.verify_signatures(keys)?;
+    Ok(trade_context)
+}
+
+/// Write a CSV record, quoting cells as RFC 4180 requires
+fn write_csv_row<'a>(
+    out: &mut dyn Write,
+    cells: impl IntoIterator<Item = &'a str>,
+) -> anyhow::Result<()> {
+    let cells: Vec<String> = cells
+        .into_iter()
+        .map(|cell| {
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.to_string()
+            }
+        })
+        .collect();
+    write!(out, "{}\r\n", cells.join(","))?;
+    Ok(())
+}
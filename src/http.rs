@@ -0,0 +1,485 @@
+//! JSON-over-HTTP API wrapping [`TradeService`]
+//!
+//! Enabled with the `http` feature. Routes:
+//!
+//! | Method | Path                      | Body                                          |
+//! |--------|---------------------------|-----------------------------------------------|
+//! | POST   | `/trades`                 | `details`, `requester_id`, approvers, signer  |
+//! | POST   | `/trades/{id}/approve`    | signer                                        |
+//! | POST   | `/trades/{id}/reject`     | `reason`, signer                              |
+//! | POST   | `/trades/{id}/update`     | `details`, signer                             |
+//! | POST   | `/trades/{id}/cancel`     | signer                                        |
+//! | POST   | `/trades/{id}/execute`    | signer                                        |
+//! | POST   | `/trades/{id}/book`       | `strike`, signer                              |
+//! | GET    | `/trades/{id}`            |                                               |
+//! | GET    | `/trades/{id}/history`    |                                               |
+//! | GET    | `/details/{hash}`         |                                               |
+//!
+//! Approvers are given either as a single `approver_id` or as an `approval_policy` of
+//! the form `{"levels": [{"approvers": [...], "required": 2}]}`. With neither, the
+//! service's routing rules choose them.
+//!
+//! The "signer" is a `user_id`, an optional `note` of the form
+//! `{"reason": "...", "tags": [{"key": "...", "value": "..."}]}` recorded on the
+//! witness, and the user's signature over it. Secret keys never reach the server, and
+//! a body carrying a `signing_key` is refused. Each POST is made in two steps:
+//!
+//! 1. POST the body without a signature to the same path plus `/prepare`, e.g.
+//!    `/trades/{id}/approve/prepare`. Every check runs and nothing is written; the
+//!    response holds the witness that would be appended, its `trade_id` and
+//!    `timestamp`, and the hex `signing_payload`, its canonical CBOR.
+//! 2. POST the same body to the path itself with the `timestamp`, the hex Ed25519
+//!    `signature` over the payload, and for a new trade the `trade_id`. The server
+//!    builds the witness again and appends it only if the signature verifies against
+//!    the key registered for `user_id`. A signature is good for
+//!    [`MAX_SIGNATURE_AGE_SECS`](crate::service::MAX_SIGNATURE_AGE_SECS) seconds.
+//!
+//! Errors are returned as `{"error": "..."}` with a status from [`status_code`]. Bodies
+//! over [`MAX_BODY_BYTES`] are refused with 413.
+
+use super::context::{ApprovalPolicy, Note, TradeContext, Witness};
+use super::error::{ServiceError, ValidationError};
+use super::keys::{DetachedSignature, WitnessSigner};
+use super::service::TradeService;
+use super::trade::{TimeStamp, TradeDetails};
+use chrono::Utc;
+use serde::Deserialize;
+use serde::de::IgnoredAny;
+use serde_json::{Value, json};
+use std::cell::RefCell;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Largest request body accepted unless [`ApiServer::with_max_body_bytes`] says otherwise
+pub const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// HTTP status a [`ServiceError`] is reported with
+pub fn status_code(error: &ServiceError) -> u16 {
+    match error {
+        ServiceError::NotFound { .. } | ServiceError::DetailsNotFound { .. } => 404,
+        ServiceError::InvalidTransition { .. }
+        | ServiceError::ConcurrentModification { .. }
+        | ServiceError::AlreadyApproved { .. }
+        | ServiceError::ClockBehind { .. } => 409,
+        ServiceError::UnauthorizedApprover { .. } | ServiceError::SegregationOfDuties { .. } => 403,
+        ServiceError::Validation(
+            ValidationError::MissingSignature(_)
+            | ValidationError::InvalidSignature(_)
+            | ValidationError::UnknownSigner(_),
+        ) => 401,
+        // Stored bytes that fail verification mean the store itself is damaged
+        ServiceError::Validation(
+            ValidationError::ContentHashMismatch { .. }
+            | ValidationError::NonCanonical(_)
+            | ValidationError::BrokenChain { .. }
+            | ValidationError::ForeignWitness { .. }
+            | ValidationError::TimestampRegression { .. },
+        ) => 500,
+        ServiceError::InvalidDetails(_)
+        | ServiceError::NoMatchingRule
+        | ServiceError::LimitBreach { .. }
+        | ServiceError::MissingReason { .. }
+        | ServiceError::Validation(_) => 422,
+        ServiceError::Storage(_) | ServiceError::Codec(_) | ServiceError::Other(_) => 500,
+    }
+}
+
+/// Failure while handling a request, before or after reaching the service
+enum ApiError {
+    BadRequest(String),
+    NoRoute,
+    /// The body is longer than the limit, in bytes
+    PayloadTooLarge(usize),
+    Service(ServiceError),
+}
+
+impl ApiError {
+    fn status(&self) -> u16 {
+        match self {
+            Self::BadRequest(_) => 400,
+            Self::NoRoute => 404,
+            Self::PayloadTooLarge(_) => 413,
+            Self::Service(e) => status_code(e),
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            Self::BadRequest(msg) => msg.clone(),
+            Self::NoRoute => "no such route".to_string(),
+            Self::PayloadTooLarge(limit) => format!("request body exceeds {} bytes", limit),
+            Self::Service(e) => e.to_string(),
+        }
+    }
+}
+
+impl From<ServiceError> for ApiError {
+    fn from(value: ServiceError) -> Self {
+        Self::Service(value)
+    }
+}
+
+/// User performing an action
+#[derive(Deserialize)]
+struct Actor {
+    user_id: String,
+    /// Reason and tags recorded on the witness
+    #[serde(default)]
+    note: Note,
+}
+
+/// Signature a client made over a prepared witness, read from the same body as the
+/// action it signs
+#[derive(Deserialize)]
+struct SignedBy {
+    timestamp: Option<TimeStamp<Utc>>,
+    signature: Option<String>,
+    /// Only for a new trade, the `trade_id` it was prepared under
+    trade_id: Option<String>,
+    /// Present only to refuse it
+    signing_key: Option<IgnoredAny>,
+}
+
+impl SignedBy {
+    fn parse(body: &str) -> Result<Self, ApiError> {
+        let signed: Self = parse(body)?;
+        if signed.signing_key.is_some() {
+            return Err(ApiError::BadRequest(
+                "secret keys are not accepted, sign the prepared witness instead".to_string(),
+            ));
+        }
+        Ok(signed)
+    }
+
+    fn signature(self) -> Result<DetachedSignature, ApiError> {
+        match (self.timestamp, self.signature) {
+            (Some(signed_at), Some(signature)) => Ok(DetachedSignature {
+                signed_at,
+                signature,
+                trade_id: self.trade_id,
+            }),
+            _ => Err(ApiError::BadRequest(
+                "expected the `timestamp` and `signature` of a prepared witness".to_string(),
+            )),
+        }
+    }
+}
+
+/// Stands in for the user while a witness is prepared: keeps the witness the service
+/// built and refuses to sign it, so nothing is written
+#[derive(Default)]
+struct Preparing {
+    witness: RefCell<Option<Witness>>,
+}
+
+impl WitnessSigner for Preparing {
+    fn sign(&self, witness: &mut Witness) -> anyhow::Result<()> {
+        *self.witness.borrow_mut() = Some(witness.clone());
+        Err(ValidationError::MissingSignature(witness.user_id.clone()).into())
+    }
+}
+
+#[derive(Deserialize)]
+struct SubmitRequest {
+    details: TradeDetails,
+    requester_id: String,
+    /// A single approver or a quorum policy, routed by the service's rules if neither
+    approver_id: Option<String>,
+    approval_policy: Option<ApprovalPolicy>,
+    #[serde(flatten)]
+    actor: Actor,
+}
+
+#[derive(Deserialize)]
+struct UpdateRequest {
+    details: TradeDetails,
+    #[serde(flatten)]
+    actor: Actor,
+}
+
+#[derive(Deserialize)]
+struct RejectRequest {
+    reason: String,
+    #[serde(flatten)]
+    actor: Actor,
+}
+
+#[derive(Deserialize)]
+struct BookRequest {
+    strike: u64,
+    #[serde(flatten)]
+    actor: Actor,
+}
+
+fn parse<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, ApiError> {
+    serde_json::from_str(body).map_err(|e| ApiError::BadRequest(e.to_string()))
+}
+
+/// JSON rendering of a trade: its ID, derived state, approval progress and witness chain
+pub fn render_context(trade_context: &TradeContext) -> Value {
+    json!({
+        "trade_id": trade_context.trade_id,
+        "state": trade_context.current_state(),
+        "approval": trade_context.approval_progress().ok(),
+        "witness_set": trade_context.witness_set,
+    })
+}
+
+/// JSON rendering of a trade's history, each witness paired with the state it led to
+pub fn render_history(trade_context: &TradeContext) -> Value {
+    let entries: Vec<Value> = trade_context
+        .timeline()
+        .into_iter()
+        .zip(&trade_context.witness_set)
+        .map(|(entry, witness)| {
+            json!({
+                "index": entry.index,
+                "action": entry.action,
+                "state": entry.state_after,
+                "approval": trade_context.approval_progress_at(entry.index).ok(),
+                "witness": witness,
+            })
+        })
+        .collect();
+
+    json!({
+        "trade_id": trade_context.trade_id,
+        "history": entries,
+    })
+}
+
+/// Blocking HTTP server dispatching requests to a shared [`TradeService`]
+pub struct ApiServer {
+    server: Server,
+    service: Arc<TradeService>,
+    /// Longest request body read, in bytes
+    max_body_bytes: usize,
+}
+
+impl ApiServer {
+    /// Bind to `addr`, e.g. `127.0.0.1:8080`. Port 0 picks a free port.
+    pub fn bind(addr: &str, service: Arc<TradeService>) -> anyhow::Result<Self> {
+        let server = Server::http(addr).map_err(|e| anyhow::anyhow!("binding {}: {}", addr, e))?;
+        Ok(Self {
+            server,
+            service,
+            max_body_bytes: MAX_BODY_BYTES,
+        })
+    }
+
+    /// Refuse request bodies longer than `max_body_bytes` with 413
+    pub fn with_max_body_bytes(mut self, max_body_bytes: usize) -> Self {
+        self.max_body_bytes = max_body_bytes;
+        self
+    }
+
+    /// Address the server is listening on
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Handle requests on the calling thread until [`ApiServer::shutdown`] is called
+    pub fn serve(&self) {
+        for request in self.server.incoming_requests() {
+            self.handle(request);
+        }
+    }
+
+    /// Stop a running [`ApiServer::serve`] loop
+    pub fn shutdown(&self) {
+        self.server.unblock();
+    }
+
+    fn handle(&self, mut request: Request) {
+        let result = match self.read_body(&mut request) {
+            Ok(body) => self.route(request.method(), request.url(), &body),
+            Err(e) => Err(e),
+        };
+
+        let (status, value) = match result {
+            Ok(ok) => ok,
+            Err(e) => (e.status(), json!({ "error": e.message() })),
+        };
+
+        let content_type =
+            Header::from_bytes("Content-Type", "application/json").expect("static header is valid");
+        let response = Response::from_string(value.to_string())
+            .with_status_code(status)
+            .with_header(content_type);
+
+        // The client may have gone away, there is no one left to report to
+        let _ = request.respond(response);
+    }
+
+    /// Read the request body, refusing it unread if its declared length is over the
+    /// limit and stopping one byte past the limit if it has none
+    fn read_body(&self, request: &mut Request) -> Result<String, ApiError> {
+        let limit = self.max_body_bytes;
+        if request.body_length().is_some_and(|len| len > limit) {
+            return Err(ApiError::PayloadTooLarge(limit));
+        }
+
+        let mut body = String::new();
+        request
+            .as_reader()
+            .take(limit as u64 + 1)
+            .read_to_string(&mut body)
+            .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+        if body.len() > limit {
+            return Err(ApiError::PayloadTooLarge(limit));
+        }
+        Ok(body)
+    }
+
+    fn route(&self, method: &Method, url: &str, body: &str) -> Result<(u16, Value), ApiError> {
+        let path = url.split('?').next().unwrap_or_default();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        match (method, segments.as_slice()) {
+            (Method::Post, ["trades", "prepare"]) => {
+                self.prepare(body, |signer| self.submit(body, signer))
+            }
+            (Method::Post, ["trades"]) => {
+                let signer = SignedBy::parse(body)?.signature()?;
+                Ok((201, render_context(&self.submit(body, &signer)?)))
+            }
+            (Method::Post, ["trades", trade_id, action, "prepare"]) => {
+                self.prepare(body, |signer| self.act(trade_id, action, body, signer))
+            }
+            (Method::Post, ["trades", trade_id, action]) => {
+                let signer = SignedBy::parse(body)?.signature()?;
+                let trade_context = self.act(trade_id, action, body, &signer)?;
+                Ok((200, render_context(&trade_context)))
+            }
+            (Method::Get, ["trades", trade_id]) => {
+                Ok((200, render_context(&self.service.get_trade(trade_id)?)))
+            }
+            (Method::Get, ["trades", trade_id, "history"]) => {
+                Ok((200, render_history(&self.service.get_trade(trade_id)?)))
+            }
+            (Method::Get, ["details", details_hash]) => {
+                let details = self.service.get_trade_details(details_hash)?;
+                let value =
+                    serde_json::to_value(details).map_err(|e| ServiceError::Other(e.into()))?;
+                Ok((200, value))
+            }
+            _ => Err(ApiError::NoRoute),
+        }
+    }
+
+    /// Run an action up to the point its witness would be signed, and return that
+    /// witness with the payload the user is to sign
+    fn prepare(
+        &self,
+        body: &str,
+        run: impl FnOnce(&dyn WitnessSigner) -> Result<TradeContext, ApiError>,
+    ) -> Result<(u16, Value), ApiError> {
+        SignedBy::parse(body)?;
+        let preparing = Preparing::default();
+        let result = run(&preparing);
+        let Some(witness) = preparing.witness.into_inner() else {
+            result?;
+            return Err(ServiceError::Other(anyhow::anyhow!("no witness was prepared")).into());
+        };
+
+        let payload = witness.signing_payload().map_err(ServiceError::from)?;
+        Ok((
+            200,
+            json!({
+                "trade_id": witness.trade_id,
+                "timestamp": witness.user_timestamp,
+                "signing_payload": hex::encode(payload),
+                "witness": witness,
+            }),
+        ))
+    }
+
+    /// Submit a new trade
+    fn submit(&self, body: &str, signer: &dyn WitnessSigner) -> Result<TradeContext, ApiError> {
+        let req: SubmitRequest = parse(body)?;
+        let policy = match (req.approver_id, req.approval_policy) {
+            (Some(approver_id), None) => Some(ApprovalPolicy::single(approver_id)),
+            (None, Some(policy)) => Some(policy),
+            (None, None) => None,
+            (Some(_), Some(_)) => {
+                return Err(ApiError::BadRequest(
+                    "expected at most one of `approver_id` or `approval_policy`".to_string(),
+                ));
+            }
+        };
+        let policy = match policy {
+            Some(policy) => policy,
+            None => self.service.route(&req.details)?,
+        };
+        Ok(self.service.submit_trade_with_note(
+            req.details,
+            req.requester_id,
+            policy,
+            req.actor.note,
+            req.actor.user_id,
+            signer,
+        )?)
+    }
+
+    /// Apply a workflow action to an existing trade
+    fn act(
+        &self,
+        trade_id: &str,
+        action: &str,
+        body: &str,
+        signer: &dyn WitnessSigner,
+    ) -> Result<TradeContext, ApiError> {
+        let trade_id = trade_id.to_string();
+        let trade_context = match action {
+            "approve" => {
+                let actor: Actor = parse(body)?;
+                self.service
+                    .approve_trade_with_note(trade_id, actor.user_id, actor.note, signer)?
+            }
+            "reject" => {
+                let req: RejectRequest = parse(body)?;
+                self.service.reject_trade_with_note(
+                    trade_id,
+                    req.actor.user_id,
+                    req.reason,
+                    req.actor.note,
+                    signer,
+                )?
+            }
+            "update" => {
+                let req: UpdateRequest = parse(body)?;
+                self.service.update_trade_with_note(
+                    trade_id,
+                    req.details,
+                    req.actor.user_id,
+                    req.actor.note,
+                    signer,
+                )?
+            }
+            "cancel" => {
+                let actor: Actor = parse(body)?;
+                self.service
+                    .cancel_trade_with_note(trade_id, actor.user_id, actor.note, signer)?
+            }
+            "execute" => {
+                let actor: Actor = parse(body)?;
+                self.service
+                    .execute_trade_with_note(trade_id, actor.user_id, actor.note, signer)?
+            }
+            "book" => {
+                let req: BookRequest = parse(body)?;
+                self.service.book_trade_with_note(
+                    trade_id,
+                    req.actor.user_id,
+                    req.strike,
+                    req.actor.note,
+                    signer,
+                )?
+            }
+            _ => return Err(ApiError::NoRoute),
+        };
+        Ok(trade_context)
+    }
+}
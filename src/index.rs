@@ -0,0 +1,74 @@
+//! Secondary indexes over trade contexts
+//!
+//! State is derived from the witness chain, so answering "which trades are pending"
+//! would otherwise mean decoding every context in the store. The service keeps a
+//! separate sled tree of empty-valued keys alongside each write:
+//!
+//! - `state \0 <TradeState> \0 <trade_id>`
+//! - `approver \0 <approver_id> \0 <trade_id>`, for every approver in the policy
+//! - `requester \0 <requester_id> \0 <trade_id>`
+//!
+//! A NUL separator is used so user IDs containing `/` or other punctuation cannot
+//! collide with each other's prefixes.
+
+use super::context::{TradeContext, TradeState};
+
+/// Name of the sled tree holding the index entries
+pub const TREE: &str = "index";
+
+const SEPARATOR: u8 = 0;
+
+fn key(kind: &str, value: &str, trade_id: Option<&str>) -> Vec<u8> {
+    let mut key = Vec::with_capacity(kind.len() + value.len() + 2);
+    key.extend_from_slice(kind.as_bytes());
+    key.push(SEPARATOR);
+    key.extend_from_slice(value.as_bytes());
+    key.push(SEPARATOR);
+    if let Some(trade_id) = trade_id {
+        key.extend_from_slice(trade_id.as_bytes());
+    }
+    key
+}
+
+/// Prefix of every entry for trades in `state`
+pub fn state_prefix(state: &TradeState) -> Vec<u8> {
+    key("state", &format!("{:?}", state), None)
+}
+
+/// Prefix of every entry for trades whose approval policy names `approver_id`
+pub fn approver_prefix(approver_id: &str) -> Vec<u8> {
+    key("approver", approver_id, None)
+}
+
+/// Prefix of every entry for trades requested by `requester_id`
+pub fn requester_prefix(requester_id: &str) -> Vec<u8> {
+    key("requester", requester_id, None)
+}
+
+/// The entry recording that `trade_id` is currently in `state`
+pub fn state_key(state: &TradeState, trade_id: &str) -> Vec<u8> {
+    key("state", &format!("{:?}", state), Some(trade_id))
+}
+
+/// Every index entry a context should have. A context without a Submit only appears
+/// under its state.
+pub fn entries(trade_context: &TradeContext) -> Vec<Vec<u8>> {
+    let trade_id = trade_context.trade_id.as_str();
+    let mut entries = vec![state_key(&trade_context.current_state(), trade_id)];
+
+    if let Ok(policy) = trade_context.approval_policy() {
+        for approver_id in policy.approvers() {
+            entries.push(key("approver", approver_id, Some(trade_id)));
+        }
+    }
+    if let Ok(requester_id) = trade_context.get_requester() {
+        entries.push(key("requester", &requester_id, Some(trade_id)));
+    }
+
+    entries
+}
+
+/// Recover the trade_id from an entry found under `prefix`
+pub fn trade_id(prefix: &[u8], entry: &[u8]) -> String {
+    String::from_utf8_lossy(&entry[prefix.len()..]).into_owned()
+}
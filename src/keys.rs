@@ -0,0 +1,323 @@
+//! Public key registry for verifying witness signatures
+//!
+//! Every witness is signed with the Ed25519 key of the user named in `user_id`.
+//! A [`KeyRegistry`] maps those user IDs to their public keys so the service can
+//! check that the actor on a witness actually produced it.
+//!
+//! A user registers once. Changing the key afterwards is a rotation: the new key
+//! takes over from a point in time, and each witness is verified against the key in
+//! force at its timestamp, so trades signed before the rotation still load.
+//!
+//! The service asks a [`WitnessSigner`] for the signature on each witness it builds. A
+//! [`SigningKey`] signs on the spot; a [`DetachedSignature`] carries a signature the
+//! user made elsewhere over a prepared witness, so their secret key never reaches the
+//! service.
+
+use super::context::Witness;
+use super::error::ValidationError;
+use super::trade::TimeStamp;
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use rand_core::OsRng;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// A public key and the time from which witnesses are verified against it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyVersion {
+    /// `None` for the key the user registered with, which covers everything before
+    /// the first rotation
+    pub valid_from: Option<TimeStamp<Utc>>,
+    pub key: VerifyingKey,
+}
+
+/// Lookup of user IDs to the Ed25519 public keys their witnesses are verified against
+pub trait KeyRegistry: Send + Sync {
+    /// Register the first public key for a user. A user who already has one is
+    /// refused with [`ValidationError::KeyAlreadyRegistered`]; use
+    /// [`rotate`](Self::rotate) to change it.
+    fn register(&self, user_id: &str, key: VerifyingKey) -> anyhow::Result<()>;
+    /// Replace a registered user's key for witnesses timestamped at or after
+    /// `valid_from`, which must be later than the previous rotation
+    fn rotate(
+        &self,
+        user_id: &str,
+        key: VerifyingKey,
+        valid_from: TimeStamp<Utc>,
+    ) -> anyhow::Result<()>;
+    /// Every key the user has held, oldest first, empty if the user is unknown
+    fn key_history(&self, user_id: &str) -> anyhow::Result<Vec<KeyVersion>>;
+
+    /// Fetch the current public key for a user, `None` if the user is unknown
+    fn get_key(&self, user_id: &str) -> anyhow::Result<Option<VerifyingKey>> {
+        Ok(self.key_history(user_id)?.last().map(|version| version.key))
+    }
+
+    /// Fetch the key a witness by the user timestamped `at` is verified against
+    fn key_at(&self, user_id: &str, at: &TimeStamp<Utc>) -> anyhow::Result<Option<VerifyingKey>> {
+        Ok(self
+            .key_history(user_id)?
+            .into_iter()
+            .rev()
+            .find(|version| {
+                version
+                    .valid_from
+                    .as_ref()
+                    .is_none_or(|from| from.to_datetime_utc() <= at.to_datetime_utc())
+            })
+            .map(|version| version.key))
+    }
+}
+
+/// Produces the signature on a witness the service has built
+pub trait WitnessSigner {
+    /// Sign `witness`, which is already stamped and linked to the chain head
+    fn sign(&self, witness: &mut Witness) -> anyhow::Result<()>;
+
+    /// When the signature was made, for one made ahead of time over a prepared
+    /// witness. The service's clock stamps the witness otherwise.
+    fn signed_at(&self) -> Option<TimeStamp<Utc>> {
+        None
+    }
+
+    /// The trade_id a Submit was prepared under, a fresh one is generated otherwise
+    fn trade_id(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl WitnessSigner for SigningKey {
+    fn sign(&self, witness: &mut Witness) -> anyhow::Result<()> {
+        witness.sign(self)
+    }
+}
+
+/// A signature made by the user over the [`signing_payload`](Witness::signing_payload)
+/// of a witness prepared for them, applied to the witness the service builds again
+/// from the same request. It only verifies if the two match byte for byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetachedSignature {
+    /// `user_timestamp` of the prepared witness
+    pub signed_at: TimeStamp<Utc>,
+    /// Hex-encoded Ed25519 signature
+    pub signature: String,
+    /// `trade_id` of a prepared Submit
+    pub trade_id: Option<String>,
+}
+
+impl WitnessSigner for DetachedSignature {
+    fn sign(&self, witness: &mut Witness) -> anyhow::Result<()> {
+        witness.signature = Some(self.signature.clone());
+        Ok(())
+    }
+
+    fn signed_at(&self) -> Option<TimeStamp<Utc>> {
+        Some(self.signed_at.clone())
+    }
+
+    fn trade_id(&self) -> Option<&str> {
+        self.trade_id.as_deref()
+    }
+}
+
+/// Generate a fresh Ed25519 signing key from the OS random number generator
+pub fn generate_signing_key() -> SigningKey {
+    SigningKey::generate(&mut OsRng)
+}
+
+/// Parse a hex-encoded 32-byte Ed25519 secret key
+pub fn signing_key_from_hex(secret: &str) -> anyhow::Result<SigningKey> {
+    let bytes: [u8; 32] = hex::decode(secret.trim())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Secret key is not 32 bytes"))?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// `history` with `key` appended from `valid_from`, checking the rotation is allowed
+fn rotated(
+    mut history: Vec<KeyVersion>,
+    user_id: &str,
+    key: VerifyingKey,
+    valid_from: TimeStamp<Utc>,
+) -> Result<Vec<KeyVersion>, ValidationError> {
+    let Some(current) = history.last() else {
+        return Err(ValidationError::UnknownSigner(user_id.to_string()));
+    };
+    if let Some(previous) = &current.valid_from
+        && previous.to_datetime_utc() >= valid_from.to_datetime_utc()
+    {
+        return Err(ValidationError::InvalidKeyRotation(format!(
+            "the key of `{}` was last rotated at {}, not before {}",
+            user_id,
+            previous.to_datetime_utc(),
+            valid_from.to_datetime_utc()
+        )));
+    }
+    history.push(KeyVersion {
+        valid_from: Some(valid_from),
+        key,
+    });
+    Ok(history)
+}
+
+/// Registry held in memory, useful for tests and short-lived services
+#[derive(Default)]
+pub struct InMemoryKeyRegistry {
+    keys: RwLock<HashMap<String, Vec<KeyVersion>>>,
+}
+
+impl InMemoryKeyRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl KeyRegistry for InMemoryKeyRegistry {
+    fn register(&self, user_id: &str, key: VerifyingKey) -> anyhow::Result<()> {
+        let mut keys = self
+            .keys
+            .write()
+            .map_err(|_| anyhow::anyhow!("key registry lock poisoned"))?;
+        if keys.contains_key(user_id) {
+            return Err(ValidationError::KeyAlreadyRegistered(user_id.to_string()).into());
+        }
+        keys.insert(
+            user_id.to_string(),
+            vec![KeyVersion {
+                valid_from: None,
+                key,
+            }],
+        );
+        Ok(())
+    }
+
+    fn rotate(
+        &self,
+        user_id: &str,
+        key: VerifyingKey,
+        valid_from: TimeStamp<Utc>,
+    ) -> anyhow::Result<()> {
+        let mut keys = self
+            .keys
+            .write()
+            .map_err(|_| anyhow::anyhow!("key registry lock poisoned"))?;
+        let history = keys.get(user_id).cloned().unwrap_or_default();
+        keys.insert(
+            user_id.to_string(),
+            rotated(history, user_id, key, valid_from)?,
+        );
+        Ok(())
+    }
+
+    fn key_history(&self, user_id: &str) -> anyhow::Result<Vec<KeyVersion>> {
+        let keys = self
+            .keys
+            .read()
+            .map_err(|_| anyhow::anyhow!("key registry lock poisoned"))?;
+        Ok(keys.get(user_id).cloned().unwrap_or_default())
+    }
+}
+
+/// Registry persisted in its own sled tree, keyed by user_id
+///
+/// A value is the 32-byte key the user registered with, followed by 40 bytes per
+/// rotation: the big-endian nanosecond timestamp it is valid from, then the key. A
+/// user who never rotated is stored exactly as before rotation existed.
+pub struct SledKeyRegistry {
+    tree: sled::Tree,
+}
+
+impl SledKeyRegistry {
+    /// Name of the sled tree holding the public keys
+    pub const TREE: &'static str = "keys";
+
+    pub fn new(db: &sled::Db) -> anyhow::Result<Self> {
+        let tree = db.open_tree(Self::TREE)?;
+        Ok(Self { tree })
+    }
+
+    fn encode(history: &[KeyVersion]) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(32 + 40 * history.len());
+        for version in history {
+            if let Some(valid_from) = &version.valid_from {
+                let nanos = valid_from
+                    .to_datetime_utc()
+                    .timestamp_nanos_opt()
+                    .ok_or_else(|| anyhow::anyhow!("Key rotation time out of range"))?;
+                bytes.extend_from_slice(&nanos.to_be_bytes());
+            }
+            bytes.extend_from_slice(version.key.as_bytes());
+        }
+        Ok(bytes)
+    }
+
+    fn decode(user_id: &str, bytes: &[u8]) -> anyhow::Result<Vec<KeyVersion>> {
+        let malformed = || anyhow::anyhow!("Stored keys for {} are malformed", user_id);
+        if bytes.len() < 32 || !(bytes.len() - 32).is_multiple_of(40) {
+            return Err(malformed());
+        }
+
+        let (first, rotations) = bytes.split_at(32);
+        let mut history = vec![KeyVersion {
+            valid_from: None,
+            key: VerifyingKey::from_bytes(&first.try_into().map_err(|_| malformed())?)?,
+        }];
+        for rotation in rotations.chunks_exact(40) {
+            let (nanos, key) = rotation.split_at(8);
+            let nanos = i64::from_be_bytes(nanos.try_into().map_err(|_| malformed())?);
+            history.push(KeyVersion {
+                valid_from: Some(DateTime::from_timestamp_nanos(nanos).into()),
+                key: VerifyingKey::from_bytes(&key.try_into().map_err(|_| malformed())?)?,
+            });
+        }
+        Ok(history)
+    }
+}
+
+impl KeyRegistry for SledKeyRegistry {
+    fn register(&self, user_id: &str, key: VerifyingKey) -> anyhow::Result<()> {
+        if self
+            .tree
+            .compare_and_swap(
+                user_id.as_bytes(),
+                None as Option<&[u8]>,
+                Some(key.as_bytes()),
+            )?
+            .is_err()
+        {
+            return Err(ValidationError::KeyAlreadyRegistered(user_id.to_string()).into());
+        }
+        Ok(())
+    }
+
+    fn rotate(
+        &self,
+        user_id: &str,
+        key: VerifyingKey,
+        valid_from: TimeStamp<Utc>,
+    ) -> anyhow::Result<()> {
+        // Replay against the stored value until no other rotation slips in between
+        loop {
+            let current = self.tree.get(user_id.as_bytes())?;
+            let history = match &current {
+                Some(bytes) => Self::decode(user_id, bytes)?,
+                None => Vec::new(),
+            };
+            let updated = Self::encode(&rotated(history, user_id, key, valid_from.clone())?)?;
+            if self
+                .tree
+                .compare_and_swap(user_id.as_bytes(), current, Some(updated))?
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    fn key_history(&self, user_id: &str) -> anyhow::Result<Vec<KeyVersion>> {
+        match self.tree.get(user_id.as_bytes())? {
+            Some(bytes) => Self::decode(user_id, &bytes),
+            None => Ok(Vec::new()),
+        }
+    }
+}
@@ -29,7 +29,15 @@
 //! Trade state is **never stored**, only **derived** by walking the witness chain:
 //! - `current_state()` replays witnesses to compute current state
 //! - `requires_approval()` determines if approval is needed
-//! - `get_expected_approver()` extracts who can approve
+//! - `get_expected_approver()` extracts who can approve; the service's
+//!   `get_expected_approvers()` also lists the delegates able to act for them
+//! - `approval_progress()` counts approvals against the Submit's approval policy
+//! - `timeline()` lists every witness with the state it left the trade in, and
+//!   `history()` wraps it in a [`timeline::History`] that displays as a whole;
+//!   `view_history()` prints it through [`timeline::TimelineFormat`]
+//! - `state_at()` and `state_at_index()` replay only part of the chain to answer what
+//!   state a trade was in at a past time or step; `TradeService::as_of` adds the
+//!   version of the details in force then
 //!
 //! This is analogous to Git determining the current working tree by replaying commits.
 //!
@@ -41,6 +49,21 @@
 //! - Persists updated contexts back to storage
 //! - Enforces business rules via state derivation
 //!
+//! Writes use optimistic concurrency control: a context is only persisted if the stored
+//! bytes still match what was loaded, inside a single sled transaction. A writer that loses
+//! the race gets `ServiceError::ConcurrentModification`, or with
+//! `TradeService::with_retries` the mutation is replayed against the fresh chain.
+//!
+//! Every service method returns [`error::ServiceError`], so callers can match on
+//! failures such as `NotFound`, `InvalidTransition` or `UnauthorizedApprover` (for example
+//! to pick an HTTP status code) without parsing error messages.
+//!
+//! With the `http` feature, the `http` module serves the same operations as a JSON API,
+//! mapping each `ServiceError` to a status code.
+//!
+//! With the `export` feature (on with `cli`), the `export` module renders trade
+//! histories, with derived states and resolved details, as JSON or flat CSV.
+//!
 //! ### Core Principles
 //!
 //! - **Immutability**: All trade data and workflow actions are immutable, content-addressable
@@ -67,14 +90,31 @@
 //! Each witness type represents an immutable action appended to the chain:
 //!
 //! - **`Submit`**: Creates initial trade request (Draft → PendingApproval)
-//!   - Contains: `details_hash`, `requester_id`, `approver_id`
+//!   - Contains: `details_hash`, `requester_id`, `approver_id`, and optionally an
+//!     `approval_policy` (e.g. two of three approvers, or ordered levels)
 //!   - Includes hash reference to immutable `TradeDetails` object
 //!
 //! - **`Approve`**: Approves current trade state (PendingApproval → Approved)
-//!   - Verifies approver matches the one specified in `Submit`
+//!   - Verifies the approver is one the `Submit`'s policy is still waiting on
+//!   - Under a quorum policy the trade stays PendingApproval until enough distinct
+//!     approvers have approved since the last `Submit` or `Update`
+//!   - With `SegregationOfDuties::four_eyes()` the requester, submitter and last
+//!     updater cannot approve (`strict()` also keeps them from executing and booking)
+//!   - An active [`delegation::Delegation`] lets a delegate approve in the approver's
+//!     place; the `Approve` is signed by the delegate and names the approver in
+//!     `on_behalf_of`. Nobody signs two Approves for the same version of the trade,
+//!     and policy members cannot stand in for each other
+//!   - `submit_routed_trade` picks the policy from `ServiceConfig` routing rules over
+//!     the trade details, see the [`config`] module
+//!   - Notional limits from the [`limits`] module are re-checked against the exposure
+//!     of other approved trades before the `Approve` is appended
 //!   - Only valid if `current_state()` returns `PendingApproval`
 //!
-//! - **`Update`**: Modifies trade details (Approved → PendingApproval)
+//! - **`Reject`**: Sends the trade back to the requester (PendingApproval → Rejected)
+//!   - Contains: the approver's `reason`
+//!   - Unlike `Cancel` it is not terminal: the requester resubmits with an `Update`
+//!
+//! - **`Update`**: Modifies trade details (Approved or Rejected → PendingApproval)
 //!   - Contains: new `details_hash` pointing to updated details
 //!   - Invalidates previous `Approve` witness - requires re-approval
 //!   - Critical: This enables the re-approval workflow
@@ -88,6 +128,20 @@
 //! - **`Cancel`**: Terminates trade (Any → Cancelled)
 //!   - Can occur at any point before `Booked`
 //!
+//! Any witness may also carry a [`context::Note`]: a free-text reason and `key=value`
+//! tags, covered by the witness hash and signature. `RequiredReasons` in the service
+//! configuration makes a reason mandatory for chosen actions, so for example every
+//! `Cancel` has to say why.
+//!
+//! ### Transition Table
+//!
+//! Which witness may be appended in which state is defined once, in
+//! `TradeState::transition`. It allows a witness type in a from-state or denies it with
+//! a reason, and every `TradeService` mutation consults it before appending. The state
+//! the witness leads to is then derived from the chain, since an Approve only approves
+//! once the policy's quorum is met. Terminal states (`Booked`, `Cancelled`) accept no
+//! further witnesses.
+//!
 //! ## Validation Rules
 //!
 //! All trades must satisfy the following temporal constraint before submission:
@@ -96,6 +150,9 @@
 //! trade_date <= value_date <= delivery_date
 //! ```
 //!
+//! With [`calendar::BusinessCalendars`] configured, the value and delivery dates must
+//! also be business days for both the notional and underlying currency.
+//!
 //! Additional validation includes:
 //! - Proper witness signatures at each stage
 //! - Re-approval after updates
@@ -109,20 +166,38 @@
 //!
 //! ### Storage Strategy
 //!
+//! The service reaches storage only through the [`store::TradeStore`] trait. [`store::SledStore`]
+//! lays objects out in sled as below; [`store::InMemoryStore`] keeps the same structure in
+//! maps, so tests and simulations can run without a database on disk.
+//!
 //! ```text
-//! Database (sled key-value store):
-//! ┌─────────────────────────┬──────────────────────────────┐
-//! │ Key                     │ Value (CBOR-encoded)         │
-//! ├─────────────────────────┼──────────────────────────────┤
-//! │ "trade_abc123"          │ TradeContext(with witnesses) │
-//! │ sha256(trade_details_v1)│ TradeDetails v1              │
-//! │ sha256(trade_details_v2)│ TradeDetails v2(after Update)│
-//! └─────────────────────────┴──────────────────────────────┘
+//! Database (sled key-value store, one named tree per kind of object):
+//! ┌──────────┬─────────────────────────┬──────────────────────────────┐
+//! │ Tree     │ Key                     │ Value (CBOR-encoded)         │
+//! ├──────────┼─────────────────────────┼──────────────────────────────┤
+//! │ contexts │ "trade_abc123"          │ TradeContext(with witnesses) │
+//! │ details  │ sha256(trade_details_v1)│ TradeDetails v1              │
+//! │ details  │ sha256(trade_details_v2)│ TradeDetails v2(after Update)│
+//! │ index    │ state/approver/requester│ (empty)                      │
+//! │ keys     │ user_id                 │ Ed25519 public key           │
+//! └──────────┴─────────────────────────┴──────────────────────────────┘
 //! ```
 //!
 //! - **TradeContext**: Stored by `trade_id` (unhashed) for easy lookup
-//! - **TradeDetails**: Stored by content hash for immutability and deduplication
+//! - **TradeDetails**: Stored by content hash for immutability and deduplication. Reads
+//!   re-hash the stored bytes and fail with `ValidationError::ContentHashMismatch` if they
+//!   no longer match their key. `TradeContext::current_details` resolves the version named
+//!   by the latest Submit or Update
 //! - **Witnesses**: Embedded in `TradeContext.witness_set` as an append-only list
+//! - **Index**: A separate `index` tree maps derived state, approver and requester to
+//!   trade IDs. It is rewritten in the same transaction as the context, backs
+//!   `list_by_state` and `list_pending_for_approver`, and can be recreated with
+//!   `TradeService::rebuild_index`
+//!
+//! Stores written before the trees were split kept contexts and details side by side in
+//! sled's default tree. `migrate::migrate_flat_layout` (or `trade-approval migrate`)
+//! moves them into place and indexes them. Contexts from before witnesses were signed
+//! are re-linked and set aside in a separate tree, since the service would refuse them.
 //!
 //! ### Benefits
 //!
@@ -142,7 +217,10 @@
 //! - **Type precision** without JSON's whitespace and base64 inflation
 //! - **Deterministic byte sequences** for consistent hashing
 //!
-//! Note: Canonical CBOR is not enforced; standard CBOR encoding is used by default.
+//! Everything that is hashed or signed goes through the [`canonical`] module, which rewrites
+//! minicbor's output under the RFC 8949 §4.2.1 deterministic rules (shortest integers,
+//! definite lengths, sorted map keys). Stored contexts and details that are not canonical
+//! are rejected on decode with `ValidationError::NonCanonical`.
 //!
 //! ## Example Workflow
 //!
@@ -151,13 +229,23 @@
 //! Note: IDs are made to be human-readable for the purposes of this example
 //!
 //! ```rust,ignore
+//! use trade_approval::keys::{generate_signing_key, KeyRegistry, SledKeyRegistry};
 //! use trade_approval::service::TradeService;
+//! use trade_approval::store::SledStore;
 //! use trade_approval::trade::{TradeDetails, Currency, Direction, TimeStamp};
 //! use std::sync::Arc;
 //!
-//! // Initialize the service with sled database
+//! // Initialize the service with a sled-backed store and a registry of public keys
 //! let db = Arc::new(sled::open("trade_db")?);
-//! let service = TradeService::new(db);
+//! let keys = Arc::new(SledKeyRegistry::new(&db)?);
+//! let store = Arc::new(SledStore::new(&db)?);
+//! let service = TradeService::new(store, keys.clone());
+//!
+//! // Every witness is signed by its actor, so each actor registers a public key
+//! let user_key = generate_signing_key();
+//! let approver_key = generate_signing_key();
+//! keys.register("user_addr_123", user_key.verifying_key())?;
+//! keys.register("approver_user456", approver_key.verifying_key())?;
 //!
 //! // 1. Build trade details using the builder pattern
 //! let trade_details = TradeDetails::new()
@@ -178,6 +266,7 @@
 //!     "requester_user123".to_string(),
 //!     "approver_user456".to_string(),
 //!     "user_addr_123".to_string(),
+//!     &user_key,
 //! )?;
 //!
 //! println!("Trade submitted: {}", trade_ctx.trade_id);
@@ -187,6 +276,7 @@
 //! let approved_ctx = service.approve_trade(
 //!     trade_ctx.trade_id.clone(),
 //!     "approver_user456".to_string(),
+//!     &approver_key,
 //! )?;
 //!
 //! println!("Current state: {:?}", approved_ctx.current_state()); // Approved
@@ -195,6 +285,7 @@
 //! let executed_ctx = service.execute_trade(
 //!     trade_ctx.trade_id.clone(),
 //!     "user_addr_123".to_string(),
+//!     &user_key,
 //! )?;
 //!
 //! // 5. Book the executed trade (creates Book witness → Booked)
@@ -202,6 +293,7 @@
 //!     trade_ctx.trade_id.clone(),
 //!     "user_addr_123".to_string(),
 //!     85000, // strike price
+//!     &user_key,
 //! )?;
 //! ```
 //!
@@ -226,6 +318,7 @@
 //!     trade_ctx.trade_id.clone(),
 //!     updated_details,
 //!     "user_addr_123".to_string(),
+//!     &user_key,
 //! )?;
 //!
 //! // State is now PendingApproval because Update invalidated previous Approve
@@ -236,6 +329,7 @@
 //! let reapproved_ctx = service.approve_trade(
 //!     trade_ctx.trade_id.clone(),
 //!     "approver_user456".to_string(),
+//!     &approver_key,
 //! )?;
 //!
 //! // Now approved again and ready for execution
@@ -271,6 +365,33 @@
 //! - **`user_id`**: The actor who created this witness
 //! - **`user_timestamp`**: When the action occurred
 //! - **`witness_type`**: The action performed with its data payload
+//! - **`parent_hash`**: SHA256 of the preceding witness (`None` for the first witness)
+//! - **`signature`**: Ed25519 signature by `user_id` over the rest of the witness
+//!
+//! ### Hash-Linked Chain
+//!
+//! Like a Git commit pointing at its parent, every witness commits to the hash of the
+//! witness before it. `TradeContext::insert_witness` links each new witness to the current
+//! head, and `TradeContext::verify_chain` walks the chain reporting the first broken link
+//! or witness dated before its parent. Contexts loaded through
+//! `TradeContext::load_from_store` have their chain verified before they are returned, so
+//! editing or removing a stored witness is detected rather than silently replayed.
+//!
+//! The service dates witnesses with its [`clock::Clock`], the system clock unless another
+//! is injected, and refuses a witness dated before the one it follows by more than its
+//! configured clock skew (none by default) as `ServiceError::ClockBehind`. Chains it loads
+//! are held to the same skew, and a stored chain that breaks it is reported as corrupt.
+//!
+//! ### Signatures
+//!
+//! Each witness also carries a hex-encoded Ed25519 **`signature`** by its `user_id`, taken
+//! over the witness' CBOR encoding with the signature field left empty. Because the parent
+//! hash is part of the signed payload, a witness is linked before it is signed. Public keys
+//! are looked up through the [`keys::KeyRegistry`] trait (in-memory and sled-backed
+//! implementations are provided), and the service refuses to append or load any witness
+//! whose signature does not verify against the key registered for its actor. A user
+//! registers once; `TradeService::rotate_key` replaces the key from then on, and each
+//! witness is verified against the key its actor held at the witness' timestamp.
 //!
 //! ### Creating a Trade: The Functional Flow
 //!
@@ -316,8 +437,23 @@
 //!
 //!  * [MIT license](https://opensource.org/licenses/MIT)
 
+pub mod calendar;
+pub mod canonical;
+pub mod clock;
+pub mod config;
 pub mod context;
+pub mod delegation;
 pub mod error;
+#[cfg(feature = "export")]
+pub mod export;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod index;
+pub mod keys;
+pub mod limits;
+pub mod migrate;
 pub mod service;
+pub mod store;
+pub mod timeline;
 pub mod trade;
 pub mod utils;
@@ -0,0 +1,104 @@
+//! Notional limits per trading entity and counterparty
+//!
+//! A [`NotionalLimit`] caps the total notional, in one currency, of the trades a
+//! trading entity or counterparty has committed to. Exposure counts every trade in
+//! one of the [`EXPOSED_STATES`] at the notional of the details currently in force.
+//! The service checks limits when a trade is submitted, updated or approved, and
+//! refuses the action with [`ServiceError::LimitBreach`] when it would take exposure
+//! past the cap.
+//!
+//! Exposure is summed from the other trades at the time of the check, not reserved,
+//! so two trades approved at the same moment can each fit on their own and together
+//! exceed a cap.
+
+use super::context::TradeState;
+use super::error::{ServiceError, ValidationError};
+use super::trade::{Currency, TradeDetails};
+
+/// States whose trades count towards exposure
+pub const EXPOSED_STATES: [TradeState; 3] = [
+    TradeState::Approved,
+    TradeState::SentToExecute,
+    TradeState::Booked,
+];
+
+/// Whose trades a limit covers
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum LimitScope {
+    TradingEntity(String),
+    CounterParty(String),
+}
+
+impl LimitScope {
+    pub fn covers(&self, details: &TradeDetails) -> bool {
+        match self {
+            Self::TradingEntity(id) => details.trading_entity() == Some(id.as_str()),
+            Self::CounterParty(id) => details.counter_party() == Some(id.as_str()),
+        }
+    }
+}
+
+impl std::fmt::Display for LimitScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TradingEntity(id) => write!(f, "trading entity `{}`", id),
+            Self::CounterParty(id) => write!(f, "counterparty `{}`", id),
+        }
+    }
+}
+
+/// A cap on the notional of a scope's exposed trades in one currency. In TOML:
+///
+/// ```toml
+/// [[limits]]
+/// counter_party = "counter_1xyz"
+/// currency = "USD"
+/// cap = 10000000
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+pub struct NotionalLimit {
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub scope: LimitScope,
+    pub currency: Currency,
+    pub cap: u64,
+}
+
+impl NotionalLimit {
+    /// Whether trades with these details count against the limit
+    pub fn applies_to(&self, details: &TradeDetails) -> bool {
+        details.notional_currency() == Some(self.currency) && self.scope.covers(details)
+    }
+
+    /// Error if the cap is zero, which would refuse every trade in scope
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if self.cap == 0 {
+            return Err(ValidationError::InvalidLimit(format!(
+                "the {:?} cap for {} is zero",
+                self.currency, self.scope
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check that adding `requested` to the current `exposure` stays within the cap
+    pub fn check(&self, exposure: u64, requested: u64) -> Result<(), ServiceError> {
+        let headroom = self.cap.saturating_sub(exposure);
+        if requested > headroom {
+            return Err(ServiceError::LimitBreach {
+                scope: self.scope.to_string(),
+                currency: self.currency,
+                cap: self.cap,
+                exposure,
+                requested,
+                headroom,
+            });
+        }
+        Ok(())
+    }
+}
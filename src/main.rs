@@ -0,0 +1,458 @@
+//! Command-line interface for operating a trade store
+//!
+//! Every command opens the sled database at `--db` and goes through [`TradeService`],
+//! so the same state checks, signatures and concurrency guards apply as for library
+//! callers. Witnesses are signed with a hex-encoded Ed25519 secret key read from
+//! `--key`; `keygen` creates one and registers its public key in the store.
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use ed25519_dalek::SigningKey;
+use std::fs::OpenOptions;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use trade_approval::{
+    config::ServiceConfig,
+    context::{ApprovalPolicy, Note, Tag, TradeState},
+    delegation::{Delegation, SledDelegationStore},
+    error::ValidationError,
+    export::{self, ExportFormat, TradeExport},
+    keys::{KeyRegistry, SledKeyRegistry, generate_signing_key, signing_key_from_hex},
+    migrate::{self, migrate_flat_layout},
+    service::TradeService,
+    store::SledStore,
+    trade::TradeDetails,
+};
+
+#[derive(Parser)]
+#[command(
+    name = "trade-approval",
+    version,
+    about = "Operate a trade approval store"
+)]
+struct Cli {
+    /// Path to the sled database
+    #[arg(long, global = true, default_value = "trade_db")]
+    db: PathBuf,
+    /// Service configuration in TOML: retries, segregation of duties, approval routing
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Identity of the user performing an action
+#[derive(clap::Args)]
+struct Actor {
+    /// User ID recorded on the witness
+    #[arg(long)]
+    user: String,
+    /// File holding the user's hex-encoded Ed25519 secret key
+    #[arg(long)]
+    key: PathBuf,
+    /// Why the action is taken, recorded on the witness
+    #[arg(long)]
+    reason: Option<String>,
+    /// A `key=value` label recorded on the witness, repeat for several
+    #[arg(long = "tag")]
+    tags: Vec<Tag>,
+}
+
+impl Actor {
+    fn note(&self) -> Note {
+        Note {
+            reason: self.reason.clone(),
+            tags: self.tags.clone(),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a signing key for a user without one and register its public key.
+    /// The secret key goes to a new file readable only by its owner.
+    Keygen {
+        #[arg(long)]
+        user: String,
+        /// Where to write the hex-encoded secret key
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Submit new trade details for approval
+    Submit {
+        /// Trade details as a JSON or TOML file
+        #[arg(long)]
+        details: PathBuf,
+        #[arg(long)]
+        requester: String,
+        /// Repeat to name several approvers. Without any, the configured routing
+        /// rules choose them.
+        #[arg(long)]
+        approver: Vec<String>,
+        /// How many of the approvers must approve, all of them by default
+        #[arg(long)]
+        quorum: Option<u32>,
+        #[command(flatten)]
+        actor: Actor,
+    },
+    /// Approve a trade that is pending approval
+    Approve {
+        trade_id: String,
+        #[command(flatten)]
+        actor: Actor,
+    },
+    /// Send a trade pending approval back to its requester, `--reason` is required
+    Reject {
+        trade_id: String,
+        #[command(flatten)]
+        actor: Actor,
+    },
+    /// Let a delegate approve on an approver's behalf, from now until `--until`
+    Delegate {
+        /// Approver handing over their approvals
+        #[arg(long)]
+        from: String,
+        #[arg(long)]
+        to: String,
+        /// End of the delegation as an RFC 3339 timestamp
+        #[arg(long)]
+        until: String,
+        /// Only cover trades with a notional amount up to this
+        #[arg(long)]
+        max_notional: Option<u64>,
+    },
+    /// Withdraw a delegation
+    Revoke {
+        #[arg(long)]
+        from: String,
+        #[arg(long)]
+        to: String,
+    },
+    /// Replace the details of a trade, requiring re-approval
+    Update {
+        trade_id: String,
+        /// Trade details as a JSON or TOML file
+        #[arg(long)]
+        details: PathBuf,
+        #[command(flatten)]
+        actor: Actor,
+    },
+    /// Cancel a trade
+    Cancel {
+        trade_id: String,
+        #[command(flatten)]
+        actor: Actor,
+    },
+    /// Send an approved trade to execution
+    Execute {
+        trade_id: String,
+        #[command(flatten)]
+        actor: Actor,
+    },
+    /// Book a trade that has been sent to execute
+    Book {
+        trade_id: String,
+        #[arg(long)]
+        strike: u64,
+        #[command(flatten)]
+        actor: Actor,
+    },
+    /// Print the witness history of a trade, with the fields each update changed
+    Show { trade_id: String },
+    /// List the detail fields that changed between two points in a trade's history
+    Diff {
+        trade_id: String,
+        /// Witness index to compare from (0 is the Submit)
+        from: usize,
+        /// Witness index to compare to
+        to: usize,
+    },
+    /// Print a trade's state and details as they stood at a point in time
+    AsOf {
+        trade_id: String,
+        /// RFC 3339 timestamp to look back to
+        at: String,
+    },
+    /// List trades, optionally only those in a given state
+    List {
+        #[arg(long)]
+        state: Option<TradeState>,
+    },
+    /// Print the trade details stored under a content hash
+    Details { hash: String },
+    /// Export a trade's history, or every trade without a trade_id, to stdout
+    Export {
+        trade_id: Option<String>,
+        /// `json` or `csv`
+        #[arg(long, default_value = "json")]
+        format: ExportFormat,
+    },
+    /// Move a store written with the flat layout into separate trees
+    Migrate,
+    /// Serve the JSON API over HTTP
+    #[cfg(feature = "http")]
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+    },
+}
+
+/// Read trade details from a JSON or TOML file. Dates are RFC 3339 strings.
+fn read_details(path: &Path) -> anyhow::Result<TradeDetails> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => Ok(toml::from_str(&contents)?),
+        _ => Ok(serde_json::from_str(&contents)?),
+    }
+}
+
+fn read_signing_key(path: &Path) -> anyhow::Result<SigningKey> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("reading key {}", path.display()))?;
+    signing_key_from_hex(&contents).with_context(|| format!("parsing key {}", path.display()))
+}
+
+/// Write a secret key to a new file only its owner can read, never over an existing one
+fn write_signing_key(path: &Path, key: &SigningKey) -> anyhow::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("creating key {}", path.display()))?;
+    file.write_all(hex::encode(key.to_bytes()).as_bytes())
+        .with_context(|| format!("writing key {}", path.display()))
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let db =
+        Arc::new(sled::open(&cli.db).with_context(|| format!("opening {}", cli.db.display()))?);
+    let keys = Arc::new(SledKeyRegistry::new(&db)?);
+    let store = Arc::new(SledStore::new(&db)?);
+    let mut service = TradeService::new(store.clone(), keys.clone())
+        .with_delegations(Arc::new(SledDelegationStore::new(&db)?));
+    if let Some(path) = &cli.config {
+        service = service.with_config(ServiceConfig::load(path)?)?;
+    }
+
+    match cli.command {
+        Command::Keygen { user, out } => {
+            if keys.get_key(&user)?.is_some() {
+                return Err(ValidationError::KeyAlreadyRegistered(user).into());
+            }
+            let key = generate_signing_key();
+            write_signing_key(&out, &key)?;
+            if let Err(e) = keys.register(&user, key.verifying_key()) {
+                // Someone registered the user in between, the key we wrote is worthless
+                let _ = std::fs::remove_file(&out);
+                return Err(e);
+            }
+            println!("{}", hex::encode(key.verifying_key().as_bytes()));
+        }
+        Command::Submit {
+            details,
+            requester,
+            approver,
+            quorum,
+            actor,
+        } => {
+            let key = read_signing_key(&actor.key)?;
+            let details = read_details(&details)?;
+            let policy = if approver.is_empty() {
+                service.route(&details)?
+            } else {
+                let required = quorum.unwrap_or(approver.len() as u32);
+                ApprovalPolicy::quorum(approver, required)
+            };
+            let ctx = service.submit_trade_with_note(
+                details,
+                requester,
+                policy,
+                actor.note(),
+                actor.user,
+                &key,
+            )?;
+            println!("{}", ctx.trade_id);
+        }
+        Command::Approve { trade_id, actor } => {
+            let key = read_signing_key(&actor.key)?;
+            let ctx = service.approve_trade_with_note(
+                trade_id,
+                actor.user.clone(),
+                actor.note(),
+                &key,
+            )?;
+            println!("{:?}", ctx.current_state());
+        }
+        Command::Reject { trade_id, actor } => {
+            let key = read_signing_key(&actor.key)?;
+            let reason = actor
+                .reason
+                .clone()
+                .context("--reason is required to reject a trade")?;
+            let note = Note {
+                reason: None,
+                tags: actor.tags,
+            };
+            let ctx = service.reject_trade_with_note(trade_id, actor.user, reason, note, &key)?;
+            println!("{:?}", ctx.current_state());
+        }
+        Command::Delegate {
+            from,
+            to,
+            until,
+            max_notional,
+        } => {
+            let until = chrono::DateTime::parse_from_rfc3339(&until)
+                .with_context(|| format!("parsing --until {}", until))?;
+            let mut delegation = Delegation::new(from, to, service.now(), until.to_utc().into());
+            delegation.max_notional = max_notional;
+            service.delegate_approvals(delegation)?;
+        }
+        Command::Revoke { from, to } => {
+            if !service.revoke_delegation(&from, &to)? {
+                anyhow::bail!("no delegation from {} to {}", from, to);
+            }
+        }
+        Command::Update {
+            trade_id,
+            details,
+            actor,
+        } => {
+            let key = read_signing_key(&actor.key)?;
+            let ctx = service.update_trade_with_note(
+                trade_id,
+                read_details(&details)?,
+                actor.user.clone(),
+                actor.note(),
+                &key,
+            )?;
+            println!("{:?}", ctx.current_state());
+        }
+        Command::Cancel { trade_id, actor } => {
+            let key = read_signing_key(&actor.key)?;
+            let ctx =
+                service.cancel_trade_with_note(trade_id, actor.user.clone(), actor.note(), &key)?;
+            println!("{:?}", ctx.current_state());
+        }
+        Command::Execute { trade_id, actor } => {
+            let key = read_signing_key(&actor.key)?;
+            let ctx = service.execute_trade_with_note(
+                trade_id,
+                actor.user.clone(),
+                actor.note(),
+                &key,
+            )?;
+            println!("{:?}", ctx.current_state());
+        }
+        Command::Book {
+            trade_id,
+            strike,
+            actor,
+        } => {
+            let key = read_signing_key(&actor.key)?;
+            let ctx = service.book_trade_with_note(
+                trade_id,
+                actor.user.clone(),
+                strike,
+                actor.note(),
+                &key,
+            )?;
+            println!("{:?}", ctx.current_state());
+        }
+        Command::Show { trade_id } => {
+            let history = service
+                .get_trade(&trade_id)?
+                .history_with_changes(store.as_ref())?;
+            println!("{}", history);
+        }
+        Command::Diff { trade_id, from, to } => {
+            for change in service.diff_versions(&trade_id, from, to)? {
+                println!("{}", change);
+            }
+        }
+        Command::AsOf { trade_id, at } => {
+            let at = chrono::DateTime::parse_from_rfc3339(&at)
+                .with_context(|| format!("parsing {}", at))?;
+            let as_of = service.as_of(&trade_id, at.to_utc().into())?;
+            println!("{:?} after {} witnesses", as_of.state, as_of.witness_count);
+            if let Some(details) = as_of.details {
+                println!("{:#?}", details);
+            }
+        }
+        Command::List { state } => {
+            let trades = match &state {
+                Some(state) => service.list_by_state(state)?,
+                None => service.list_trades()?,
+            };
+            for ctx in trades {
+                println!(
+                    "{}\t{:?}\t{}",
+                    ctx.trade_id,
+                    ctx.current_state(),
+                    ctx.witness_set.len()
+                );
+            }
+        }
+        Command::Details { hash } => {
+            println!("{:#?}", service.get_trade_details(&hash)?);
+        }
+        Command::Export { trade_id, format } => {
+            let mut out = std::io::stdout();
+            match trade_id {
+                Some(trade_id) => {
+                    let export =
+                        TradeExport::from_context(&service.get_trade(&trade_id)?, store.as_ref())?;
+                    match format {
+                        ExportFormat::Json => println!("{}", export.to_json()?),
+                        ExportFormat::Csv => print!("{}", export.to_csv()?),
+                    }
+                }
+                None => match format {
+                    ExportFormat::Json => export::write_store_json(
+                        store.as_ref(),
+                        keys.as_ref(),
+                        service.max_clock_skew(),
+                        &mut out,
+                    )?,
+                    ExportFormat::Csv => export::write_store_csv(
+                        store.as_ref(),
+                        keys.as_ref(),
+                        service.max_clock_skew(),
+                        &mut out,
+                    )?,
+                },
+            }
+        }
+        Command::Migrate => {
+            let report = migrate_flat_layout(&db, service.max_clock_skew())?;
+            println!(
+                "moved {} contexts and {} trade details",
+                report.contexts, report.details
+            );
+            for trade_id in &report.quarantined {
+                println!(
+                    "{} has no signatures, set aside in the {} tree",
+                    trade_id,
+                    migrate::LEGACY_TREE
+                );
+            }
+        }
+        #[cfg(feature = "http")]
+        Command::Serve { addr } => {
+            let server = trade_approval::http::ApiServer::bind(&addr, Arc::new(service))?;
+            println!("listening on {}", addr);
+            server.serve();
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,166 @@
+//! Migration of stores written with the original flat layout
+//!
+//! Earlier versions kept everything in sled's default tree: contexts under their
+//! bech32 `trade_id` and trade details under the hex SHA256 of their CBOR. Enumerating
+//! trades meant guessing which keys were which. Contexts and details now live in the
+//! [`TradeContext::TREE`] and [`TradeDetails::TREE`] trees, and
+//! [`migrate_flat_layout`] moves an existing store across.
+//!
+//! The very first stores also predate hash-linked, signed witnesses. Their chains
+//! cannot be re-signed without their users' keys, so the migration fills in the parent
+//! hashes and sets them aside in the [`LEGACY_TREE`] for review, rather than letting
+//! them fail verification every time the service loads them.
+
+use super::context::{TRADE_ID_HRP, TradeContext};
+use super::index;
+use super::trade::TradeDetails;
+use chrono::TimeDelta;
+use sled::transaction::{ConflictableTransactionError, TransactionError, Transactional};
+
+/// Name of the sled tree holding contexts written before witnesses were linked and
+/// signed, keyed by trade_id
+pub const LEGACY_TREE: &str = "legacy_contexts";
+
+/// How many objects a migration moved out of the default tree
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub contexts: usize,
+    pub details: usize,
+    /// Trades whose unsigned chains were re-linked into the [`LEGACY_TREE`] instead
+    pub quarantined: Vec<String>,
+}
+
+/// Details keys are the lowercase hex SHA256 of their contents
+fn is_details_key(key: &[u8]) -> bool {
+    key.len() == 64 && key.iter().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Whether a context was written before witnesses carried parent hashes and
+/// signatures: it has witnesses, and none of them has either
+fn is_unlinked(trade_context: &TradeContext) -> bool {
+    !trade_context.witness_set.is_empty()
+        && trade_context
+            .witness_set
+            .iter()
+            .all(|witness| witness.parent_hash.is_none() && witness.signature.is_none())
+}
+
+/// Rebuild an unlinked chain with each witness pointing at the hash of the one before
+fn relink(trade_context: TradeContext) -> anyhow::Result<TradeContext> {
+    let mut relinked = TradeContext::new_with(trade_context.trade_id);
+    for witness in trade_context.witness_set {
+        relinked.insert_witness(witness)?;
+    }
+    Ok(relinked)
+}
+
+/// Move contexts and trade details from the default tree into their own trees, and
+/// index the moved contexts, in a single transaction. Every object is checked before
+/// it is moved: contexts must decode with an intact witness chain, dated within
+/// `max_clock_skew` as the service would load it, and details must be canonical and
+/// hash to their key. Unlinked contexts from before witnesses were
+/// signed are re-linked and moved to the [`LEGACY_TREE`] unindexed, and reported.
+/// Keys that are none of these are left where they are, and running the migration
+/// again is a no-op.
+pub fn migrate_flat_layout(
+    db: &sled::Db,
+    max_clock_skew: TimeDelta,
+) -> anyhow::Result<MigrationReport> {
+    let mut contexts = Vec::new();
+    let mut legacy = Vec::new();
+    let mut details = Vec::new();
+
+    for entry in db.iter() {
+        let (key, value) = entry?;
+        if key.starts_with(TRADE_ID_HRP.as_bytes()) {
+            let invalid = |e: &dyn std::fmt::Display| {
+                anyhow::anyhow!(
+                    "{} is not a valid trade context: {}",
+                    String::from_utf8_lossy(&key),
+                    e
+                )
+            };
+            // Legacy bytes predate the canonical encoding rules, so only ask that
+            // they decode
+            let decoded: TradeContext = minicbor::decode(&value).map_err(|e| invalid(&e))?;
+            if is_unlinked(&decoded) {
+                let (_, cbor) = relink(decoded)?.serialize_with_hash()?;
+                legacy.push((key, cbor));
+                continue;
+            }
+
+            let trade_context = TradeContext::from_cbor_with_skew(&value, max_clock_skew)
+                .map_err(|e| invalid(&e))?;
+            contexts.push((key, value, index::entries(&trade_context)));
+        } else if is_details_key(&key) {
+            TradeDetails::from_cbor(&String::from_utf8_lossy(&key), &value)?;
+            details.push((key, value));
+        }
+    }
+
+    let default_tree: &sled::Tree = db;
+    let context_tree = db.open_tree(TradeContext::TREE)?;
+    let details_tree = db.open_tree(TradeDetails::TREE)?;
+    let index_tree = db.open_tree(index::TREE)?;
+    let legacy_tree = db.open_tree(LEGACY_TREE)?;
+
+    let trees = (
+        default_tree,
+        &context_tree,
+        &details_tree,
+        &index_tree,
+        &legacy_tree,
+    );
+    let result = trees.transaction(
+        |(tx_default, tx_contexts, tx_details, tx_index, tx_legacy)| {
+            for (key, value, entries) in &contexts {
+                if let Some(existing) = tx_contexts.insert(key, value)?
+                    && existing != *value
+                {
+                    return Err(ConflictableTransactionError::Abort((
+                        key.clone(),
+                        TradeContext::TREE,
+                    )));
+                }
+                for entry in entries {
+                    tx_index.insert(entry.as_slice(), &[] as &[u8])?;
+                }
+                tx_default.remove(key)?;
+            }
+            for (key, value) in &legacy {
+                if let Some(existing) = tx_legacy.insert(key, value.as_slice())?
+                    && existing != *value
+                {
+                    return Err(ConflictableTransactionError::Abort((
+                        key.clone(),
+                        LEGACY_TREE,
+                    )));
+                }
+                tx_default.remove(key)?;
+            }
+            for (key, value) in &details {
+                // Details are content addressed, an existing copy is the same object
+                tx_details.insert(key, value)?;
+                tx_default.remove(key)?;
+            }
+            Ok(())
+        },
+    );
+
+    match result {
+        Ok(()) => Ok(MigrationReport {
+            contexts: contexts.len(),
+            details: details.len(),
+            quarantined: legacy
+                .iter()
+                .map(|(key, _)| String::from_utf8_lossy(key).into_owned())
+                .collect(),
+        }),
+        Err(TransactionError::Abort((key, tree))) => Err(anyhow::anyhow!(
+            "{} already exists in the {} tree with different contents",
+            String::from_utf8_lossy(&key),
+            tree
+        )),
+        Err(TransactionError::Storage(e)) => Err(e.into()),
+    }
+}
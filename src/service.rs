@@ -1,253 +1,1138 @@
 //! Service layer API for trade workflow operations
-use super::context::{TradeContext, TradeState, Witness, WitnessType};
-use super::trade::{TimeStamp, TradeDetails};
-use sled::Batch;
+use super::calendar::BusinessCalendars;
+use super::clock::{Clock, SystemClock};
+use super::config::{RoutingRule, ServiceConfig};
+use super::context::{
+    ApprovalPolicy, Note, TRADE_ID_HRP, TradeContext, TradeState, Witness, WitnessType,
+};
+use super::delegation::{Delegation, DelegationStore, ExpectedApprover, InMemoryDelegationStore};
+use super::error::{ServiceError, ValidationError};
+use super::index;
+use super::keys::{KeyRegistry, WitnessSigner};
+use super::limits::{EXPOSED_STATES, LimitScope, NotionalLimit};
+use super::store::{TradeStore, WriteBatch};
+use super::trade::{Currency, FieldChange, TimeStamp, TradeDetails};
+use chrono::{TimeDelta, Utc};
+use ed25519_dalek::VerifyingKey;
 use std::sync::Arc;
 
+/// Longest a signature made over a prepared witness stays good for, in seconds
+pub const MAX_SIGNATURE_AGE_SECS: i64 = 300;
+
+/// Actions that must be taken by someone other than the trade's originators: the
+/// requester and submitter of the trade, and whoever last updated it. Nothing is
+/// guarded by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct SegregationOfDuties {
+    /// Covers rejecting as well as approving
+    pub approve: bool,
+    pub execute: bool,
+    pub book: bool,
+}
+
+impl SegregationOfDuties {
+    /// The four-eyes rule: originators cannot approve their own trade
+    pub fn four_eyes() -> Self {
+        Self {
+            approve: true,
+            ..Self::default()
+        }
+    }
+    /// Originators can neither approve, execute nor book their own trade
+    pub fn strict() -> Self {
+        Self {
+            approve: true,
+            execute: true,
+            book: true,
+        }
+    }
+    /// Whether `action` is reserved for someone other than the originators
+    pub fn guards(&self, action: &WitnessType) -> bool {
+        match action {
+            WitnessType::Approve { .. } | WitnessType::Reject { .. } => self.approve,
+            WitnessType::SendToExecute => self.execute,
+            WitnessType::Book { .. } => self.book,
+            _ => false,
+        }
+    }
+}
+
+/// Actions that must carry a [`Note`] with a reason. A Reject always states its
+/// reason, and nothing else requires one by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct RequiredReasons {
+    pub submit: bool,
+    pub approve: bool,
+    pub update: bool,
+    pub cancel: bool,
+    pub execute: bool,
+    pub book: bool,
+}
+
+impl RequiredReasons {
+    /// Whether `action` must give a reason
+    pub fn requires(&self, action: &WitnessType) -> bool {
+        match action {
+            WitnessType::Submit { .. } => self.submit,
+            WitnessType::Approve { .. } => self.approve,
+            WitnessType::Update { .. } => self.update,
+            WitnessType::Cancel => self.cancel,
+            WitnessType::SendToExecute => self.execute,
+            WitnessType::Book { .. } => self.book,
+            WitnessType::Reject { .. } => false,
+        }
+    }
+}
+
+/// A trade as it stood at a point in time, see [`TradeService::as_of`]
+#[derive(Debug, PartialEq, Eq)]
+pub struct TradeAsOf {
+    pub trade_id: String,
+    pub at: TimeStamp<Utc>,
+    pub state: TradeState,
+    /// Witnesses recorded by `at`, those the state was derived from
+    pub witness_count: usize,
+    /// Details in force at `at`, `None` before the trade was submitted
+    pub details_hash: Option<String>,
+    pub details: Option<TradeDetails>,
+}
+
 pub struct TradeService {
-    instance: Arc<sled::Db>,
-    // In future we could add a config for approval constraints
+    store: Arc<dyn TradeStore>,
+    /// Public keys used to verify the signature on every witness
+    keys: Arc<dyn KeyRegistry>,
+    /// How many times a mutation is replayed after losing a race to another writer
+    max_retries: usize,
+    /// Actions the trade's originators are not allowed to take themselves
+    duties: SegregationOfDuties,
+    /// Actions that must explain themselves with a reason
+    reasons: RequiredReasons,
+    /// Picks the approval policy for [`TradeService::submit_routed_trade`]
+    routing: Vec<RoutingRule>,
+    /// Notional caps checked on submit, update and approve
+    limits: Vec<NotionalLimit>,
+    /// Approval authority handed from absent approvers to their delegates
+    delegations: Arc<dyn DelegationStore>,
+    /// Stamps witnesses and decides which delegations are active
+    clock: Arc<dyn Clock>,
+    /// How far a witness may be timestamped before the one it follows
+    max_clock_skew: TimeDelta,
+    /// Business days value and delivery dates must fall on, unchecked if `None`
+    calendars: Option<BusinessCalendars>,
 }
 
 impl TradeService {
-    pub fn new(instance: Arc<sled::Db>) -> Self {
-        Self { instance }
+    pub fn new(store: Arc<dyn TradeStore>, keys: Arc<dyn KeyRegistry>) -> Self {
+        Self {
+            store,
+            keys,
+            max_retries: 0,
+            duties: SegregationOfDuties::default(),
+            reasons: RequiredReasons::default(),
+            routing: Vec::new(),
+            limits: Vec::new(),
+            delegations: Arc::new(InMemoryDelegationStore::new()),
+            clock: Arc::new(SystemClock),
+            max_clock_skew: TimeDelta::zero(),
+            calendars: None,
+        }
+    }
+
+    /// Apply every setting in `config`, replacing those set before. A config that fails
+    /// [`ServiceConfig::validate`] is refused.
+    pub fn with_config(self, config: ServiceConfig) -> Result<Self, ValidationError> {
+        config.validate()?;
+        let mut service = self
+            .with_retries(config.max_retries)
+            .with_segregation_of_duties(config.segregation_of_duties)
+            .with_required_reasons(config.required_reasons);
+        service.routing = config.rules;
+        service.calendars = config.calendars;
+        let max_clock_skew = i64::try_from(config.max_clock_skew_ms).unwrap_or(i64::MAX);
+        Ok(service
+            .with_limits(config.limits)
+            .with_max_clock_skew(TimeDelta::milliseconds(max_clock_skew)))
+    }
+
+    /// Automatically replay a mutation up to `max_retries` times when it fails with
+    /// [`ServiceError::ConcurrentModification`]. Each replay reloads the context, so
+    /// state checks are made against the witness chain that won the race.
+    pub fn with_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Reject the actions `duties` guards when they are taken by one of the trade's
+    /// originators, with [`ServiceError::SegregationOfDuties`]
+    pub fn with_segregation_of_duties(mut self, duties: SegregationOfDuties) -> Self {
+        self.duties = duties;
+        self
+    }
+
+    /// Reject the actions `reasons` covers with [`ServiceError::MissingReason`] unless
+    /// their note gives a reason
+    pub fn with_required_reasons(mut self, reasons: RequiredReasons) -> Self {
+        self.reasons = reasons;
+        self
+    }
+
+    /// Refuse submits, updates and approvals that would take a trading entity or
+    /// counterparty past one of `limits`, with [`ServiceError::LimitBreach`]
+    pub fn with_limits(mut self, limits: Vec<NotionalLimit>) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Keep delegations in `delegations` instead of in memory
+    pub fn with_delegations(mut self, delegations: Arc<dyn DelegationStore>) -> Self {
+        self.delegations = delegations;
+        self
+    }
+
+    /// Refuse submits and updates whose value or delivery date is not a joint business
+    /// day of the trade's currencies, with [`ValidationError::NonBusinessDay`]
+    pub fn with_calendars(mut self, calendars: BusinessCalendars) -> Self {
+        self.calendars = Some(calendars);
+        self
+    }
+
+    /// Take the time from `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Accept witnesses timestamped up to `max_clock_skew` before the witness they
+    /// follow, for services on several hosts whose clocks disagree. By default a chain's
+    /// timestamps may never go backwards.
+    pub fn with_max_clock_skew(mut self, max_clock_skew: TimeDelta) -> Self {
+        self.max_clock_skew = max_clock_skew;
+        self
+    }
+
+    /// How far a witness may be timestamped before the one it follows, see
+    /// [`with_max_clock_skew`](Self::with_max_clock_skew)
+    pub fn max_clock_skew(&self) -> TimeDelta {
+        self.max_clock_skew
+    }
+
+    /// The current time on the service's clock, as witnesses and delegations see it
+    pub fn now(&self) -> TimeStamp<Utc> {
+        self.clock.now()
+    }
+
+    /// Load trade context from database, verifying the signature on every witness.
+    /// The raw bytes are returned alongside so the write can be guarded against them.
+    fn load_trade_context(&self, trade_id: &str) -> Result<(TradeContext, Vec<u8>), ServiceError> {
+        let bytes = self
+            .store
+            .get_context(trade_id)?
+            .ok_or_else(|| ServiceError::NotFound {
+                trade_id: trade_id.to_string(),
+            })?;
+
+        let trade_context = TradeContext::from_cbor_with_skew(&bytes, self.max_clock_skew)?;
+        trade_context.verify_signatures(self.keys.as_ref())?;
+
+        Ok((trade_context, bytes))
+    }
+
+    /// Check the witness is allowed by the transition table, gives any reason required
+    /// of it and respects the segregation of duties, link it to the chain head, sign
+    /// it, and only append it once the signature verifies against the key registered
+    /// for its `user_id`
+    fn append_witness(
+        &self,
+        trade_context: &mut TradeContext,
+        mut witness: Witness,
+        signer: &dyn WitnessSigner,
+    ) -> Result<(), ServiceError> {
+        trade_context
+            .current_state()
+            .transition(&witness.witness_type)?;
+
+        if self.reasons.requires(&witness.witness_type)
+            && !witness.note.as_ref().is_some_and(Note::has_reason)
+        {
+            return Err(ServiceError::MissingReason {
+                action: witness.witness_type.name(),
+            });
+        }
+
+        if self.duties.guards(&witness.witness_type) {
+            // A delegate is held to the duties of the approver they act for as well
+            let originators = trade_context.originators();
+            let acting_for = witness.approval_of().unwrap_or(&witness.user_id);
+            if let Some(user_id) = [witness.user_id.as_str(), acting_for]
+                .into_iter()
+                .find(|user_id| originators.iter().any(|o| o == user_id))
+            {
+                return Err(ServiceError::SegregationOfDuties {
+                    user_id: user_id.to_string(),
+                    action: witness.witness_type.name(),
+                });
+            }
+        }
+
+        // A signature made over a prepared witness covers the time it was prepared at
+        if let Some(signed_at) = signer.signed_at() {
+            self.check_signed_at(&signed_at)?;
+            witness.user_timestamp = signed_at;
+        }
+
+        // A regression here is the clock running behind the chain, not a damaged store
+        if let Some(previous) = trade_context.witness_set.last()
+            && let Err(ValidationError::TimestampRegression {
+                previous, found, ..
+            }) = witness.check_follows(
+                previous,
+                trade_context.witness_set.len(),
+                self.max_clock_skew,
+            )
+        {
+            return Err(ServiceError::ClockBehind {
+                trade_id: witness.trade_id.clone(),
+                head: previous,
+                found,
+            });
+        }
+
+        witness.parent_hash = trade_context.head_hash()?;
+        signer.sign(&mut witness)?;
+
+        let key = self
+            .keys
+            .key_at(&witness.user_id, &witness.user_timestamp)?
+            .ok_or_else(|| ValidationError::UnknownSigner(witness.user_id.clone()))?;
+        witness.verify_signature(&key)?;
+
+        trade_context.insert_witness(witness)?;
+        Ok(())
+    }
+
+    /// Refuse a signature made for a time past the service's clock, beyond the skew
+    /// allowed, or more than [`MAX_SIGNATURE_AGE_SECS`] before it
+    fn check_signed_at(&self, signed_at: &TimeStamp<Utc>) -> Result<(), ValidationError> {
+        let now = self.clock.now().to_datetime_utc();
+        let signed_at = signed_at.to_datetime_utc();
+        let age = now - signed_at;
+        if age > TimeDelta::seconds(MAX_SIGNATURE_AGE_SECS) || -age > self.max_clock_skew {
+            return Err(ValidationError::UntimelySignature { signed_at, now });
+        }
+        Ok(())
     }
 
-    /// Load trade context from database
-    fn load_trade_context(&self, trade_id: &str) -> anyhow::Result<TradeContext> {
-        TradeContext::load_from_db(&self.instance, trade_id)
+    /// A new context for a Submit, under the trade_id it was prepared with if any
+    fn new_context(&self, signer: &dyn WitnessSigner) -> Result<TradeContext, ValidationError> {
+        let Some(trade_id) = signer.trade_id() else {
+            return Ok(TradeContext::new());
+        };
+        match bech32::decode(trade_id) {
+            Ok((hrp, data)) if hrp.as_str() == TRADE_ID_HRP && data.len() == 16 => {
+                Ok(TradeContext::new_with(trade_id.to_string()))
+            }
+            _ => Err(ValidationError::InvalidTradeId(trade_id.to_string())),
+        }
+    }
+
+    /// Persist the context (and any new trade details) atomically, provided the
+    /// stored context still matches the bytes it was loaded from. `previous` is `None`
+    /// for a brand new trade, in which case the trade_id must not exist yet. The index
+    /// entries of the previous context are swapped for the new ones in the same write.
+    fn commit(
+        &self,
+        trade_context: &TradeContext,
+        previous: Option<&[u8]>,
+        details: Option<(&str, &[u8])>,
+    ) -> Result<(), ServiceError> {
+        let (_, context_cbor) = trade_context.serialize_with_hash()?;
+        let trade_id = trade_context.trade_id.as_str();
+
+        let stale = match previous {
+            Some(bytes) => index::entries(&TradeContext::from_cbor_with_skew(
+                bytes,
+                self.max_clock_skew,
+            )?),
+            None => Vec::new(),
+        };
+
+        let mut batch = WriteBatch::new()
+            .put_context(trade_id, &context_cbor)
+            .remove_index(stale)
+            .insert_index(index::entries(trade_context));
+        if let Some((details_hash, details_cbor)) = details {
+            batch = batch.put_details(details_hash, details_cbor);
+        }
+
+        if !self.store.compare_and_swap(trade_id, previous, &batch)? {
+            return Err(ServiceError::ConcurrentModification {
+                trade_id: trade_id.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Run a mutation, replaying it while it loses races and retries remain
+    fn with_retry<T>(
+        &self,
+        mut op: impl FnMut() -> Result<T, ServiceError>,
+    ) -> Result<T, ServiceError> {
+        let mut attempt = 0;
+        loop {
+            match op() {
+                Err(ServiceError::ConcurrentModification { .. }) if attempt < self.max_retries => {
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
     }
 
-    /// Submit a new trade for approval
+    /// Submit a new trade for approval by a single approver
     pub fn submit_trade(
         &self,
         trade_details: TradeDetails,
         requester_id: String,
         approver_id: String,
         user_id: String,
-    ) -> anyhow::Result<TradeContext> {
+        signer: &dyn WitnessSigner,
+    ) -> Result<TradeContext, ServiceError> {
+        self.submit_trade_with_policy(
+            trade_details,
+            requester_id,
+            ApprovalPolicy::single(approver_id),
+            user_id,
+            signer,
+        )
+    }
+
+    /// Submit a new trade that stays PendingApproval until `policy` is satisfied, e.g.
+    /// any two of three approvers. A single-approver policy is recorded exactly as
+    /// [`TradeService::submit_trade`] records it.
+    pub fn submit_trade_with_policy(
+        &self,
+        trade_details: TradeDetails,
+        requester_id: String,
+        policy: ApprovalPolicy,
+        user_id: String,
+        signer: &dyn WitnessSigner,
+    ) -> Result<TradeContext, ServiceError> {
+        self.submit_trade_with_note(
+            trade_details,
+            requester_id,
+            policy,
+            Note::default(),
+            user_id,
+            signer,
+        )
+    }
+
+    /// Submit a new trade under `policy`, recording `note` on the Submit
+    pub fn submit_trade_with_note(
+        &self,
+        trade_details: TradeDetails,
+        requester_id: String,
+        policy: ApprovalPolicy,
+        note: Note,
+        user_id: String,
+        signer: &dyn WitnessSigner,
+    ) -> Result<TradeContext, ServiceError> {
+        policy.validate()?;
+        let approver_id = policy
+            .approvers()
+            .next()
+            .expect("a valid policy names an approver")
+            .to_string();
+        let approval_policy = (policy != ApprovalPolicy::single(&approver_id)).then_some(policy);
+
         // Validate and serialise trade details
         let (details_hash, details_cbor) = trade_details.validate_and_finalise()?;
+        self.check_business_days(&trade_details)?;
+        self.check_limits(&trade_details, None)?;
 
         // Create new trade context
-        let mut trade_context = TradeContext::new();
+        let mut trade_context = self.new_context(signer)?;
 
         // Create Submit witness
         let witness = Witness::new(
             trade_context.trade_id.clone(),
             user_id,
-            TimeStamp::new(),
+            self.clock.now(),
             WitnessType::Submit {
                 details_hash: details_hash.clone(),
                 requester_id,
                 approver_id,
+                approval_policy,
             },
-        );
-
-        // Add witness to context
-        trade_context.insert_witness(witness);
-
-        // Batch insert: trade details and trade context
-        let mut batch = Batch::default();
-        // insert trade details
-        batch.insert(details_hash.as_bytes(), details_cbor);
-        // insert context with witness
-        batch.insert(
-            trade_context.trade_id.as_bytes(),
-            minicbor::to_vec(&trade_context)?,
-        );
-        self.instance.apply_batch(batch)?;
+        )
+        .with_note(note);
+
+        // Sign and add witness to context
+        self.append_witness(&mut trade_context, witness, signer)?;
+
+        // Insert trade details and trade context, the trade_id must still be unused
+        self.commit(&trade_context, None, Some((&details_hash, &details_cbor)))?;
 
         Ok(trade_context)
     }
 
-    /// Approve a trade that is in PendingApproval state
+    /// Submit a new trade with the approval policy of the first routing rule its details
+    /// match, failing with [`ServiceError::NoMatchingRule`] if none do
+    pub fn submit_routed_trade(
+        &self,
+        trade_details: TradeDetails,
+        requester_id: String,
+        user_id: String,
+        signer: &dyn WitnessSigner,
+    ) -> Result<TradeContext, ServiceError> {
+        let policy = self.route(&trade_details)?;
+        self.submit_trade_with_policy(trade_details, requester_id, policy, user_id, signer)
+    }
+
+    /// The approval policy of the first routing rule `trade_details` match
+    pub fn route(&self, trade_details: &TradeDetails) -> Result<ApprovalPolicy, ServiceError> {
+        Ok(self
+            .routing
+            .iter()
+            .find(|rule| rule.matches(trade_details))
+            .ok_or(ServiceError::NoMatchingRule)?
+            .policy())
+    }
+
+    /// Approve a trade that is in PendingApproval state. The approver must be one the
+    /// policy is still waiting on, or an active delegate of one, and each approver
+    /// counts once per Submit or Update. A delegate's Approve names the approver it was
+    /// made for in `on_behalf_of`. Nobody signs more than one Approve per Submit or
+    /// Update, and members of the policy cannot act as delegates, so a quorum always
+    /// takes as many people as it requires.
     pub fn approve_trade(
         &self,
         trade_id: String,
         approver_id: String,
-    ) -> anyhow::Result<TradeContext> {
-        // Load from DB
-        let mut trade_context = self.load_trade_context(&trade_id)?;
+        signer: &dyn WitnessSigner,
+    ) -> Result<TradeContext, ServiceError> {
+        self.approve_trade_with_note(trade_id, approver_id, Note::default(), signer)
+    }
 
-        // Verify it's in a state that needs approval
-        if !trade_context.requires_approval() {
-            return Err(anyhow::anyhow!(
-                "Trade does not require approval. Current state: {:?}",
-                trade_context.current_state()
-            ));
-        }
+    /// Approve a trade, recording `note` on the Approve, e.g. conditions attached to
+    /// the approval
+    pub fn approve_trade_with_note(
+        &self,
+        trade_id: String,
+        approver_id: String,
+        note: Note,
+        signer: &dyn WitnessSigner,
+    ) -> Result<TradeContext, ServiceError> {
+        self.with_retry(|| {
+            // Load from DB
+            let (mut trade_context, previous) = self.load_trade_context(&trade_id)?;
+
+            // Verify it's in a state that needs approval before checking who approves
+            trade_context
+                .current_state()
+                .transition(&WitnessType::Approve { on_behalf_of: None })?;
+
+            // Verify approver_id is one the latest Submit's policy is waiting on, in
+            // their own right or through a delegation
+            let progress = trade_context.approval_progress()?;
+            let trade_details = self.get_trade_details(&trade_context.current_details_hash()?)?;
+            let on_behalf_of = if progress.awaiting.contains(&approver_id) {
+                None
+            } else if progress.approved_by.contains(&approver_id)
+                || trade_context
+                    .approval_signers()
+                    .contains(&approver_id.as_str())
+            {
+                // Each person casts one vote, whoever they approve for
+                return Err(ServiceError::AlreadyApproved {
+                    approver_id: approver_id.clone(),
+                });
+            } else if let Some(delegator) =
+                self.delegator_for(&progress.awaiting, &approver_id, &trade_details)?
+                // A policy member only ever counts for themselves
+                && !trade_context
+                    .approval_policy()?
+                    .approvers()
+                    .any(|id| id == approver_id)
+            {
+                Some(delegator)
+            } else {
+                return Err(ServiceError::UnauthorizedApprover {
+                    expected: progress.awaiting.join(", "),
+                    got: approver_id.clone(),
+                });
+            };
 
-        // Verify approver_id matches the one in latest Submit
-        let expected_approver = trade_context.get_expected_approver()?;
-        if approver_id != expected_approver {
-            return Err(anyhow::anyhow!(
-                "Unauthorized approver. Expected: {}, Got: {}",
-                expected_approver,
-                approver_id
-            ));
+            // Verify the trade still fits within its limits once it counts as exposure
+            self.check_limits(&trade_details, Some(&trade_id))?;
+
+            // Add Approve witness
+            let witness = Witness::new(
+                trade_id.clone(),
+                approver_id.clone(),
+                self.clock.now(),
+                WitnessType::Approve { on_behalf_of },
+            )
+            .with_note(note.clone());
+
+            self.append_witness(&mut trade_context, witness, signer)?;
+
+            // Save back to DB
+            self.commit(&trade_context, Some(previous.as_slice()), None)?;
+
+            Ok(trade_context)
+        })
+    }
+
+    /// Send a trade in PendingApproval state back to its requester, who can resubmit
+    /// it with `update_trade` or cancel it. Only an approver the policy is still
+    /// waiting on may reject.
+    pub fn reject_trade(
+        &self,
+        trade_id: String,
+        approver_id: String,
+        reason: String,
+        signer: &dyn WitnessSigner,
+    ) -> Result<TradeContext, ServiceError> {
+        self.reject_trade_with_note(trade_id, approver_id, reason, Note::default(), signer)
+    }
+
+    /// Reject a trade, recording `note` on the Reject alongside its reason. A blank
+    /// reason is refused with [`ServiceError::MissingReason`].
+    pub fn reject_trade_with_note(
+        &self,
+        trade_id: String,
+        approver_id: String,
+        reason: String,
+        note: Note,
+        signer: &dyn WitnessSigner,
+    ) -> Result<TradeContext, ServiceError> {
+        let reject = WitnessType::Reject {
+            reason: reason.clone(),
+        };
+        if reason.trim().is_empty() {
+            return Err(ServiceError::MissingReason {
+                action: reject.name(),
+            });
         }
 
-        // Add Approve witness
-        let witness = Witness::new(
-            trade_id.clone(),
-            approver_id,
-            TimeStamp::new(),
-            WitnessType::Approve,
-        );
+        self.with_retry(|| {
+            // Load from DB
+            let (mut trade_context, previous) = self.load_trade_context(&trade_id)?;
 
-        trade_context.insert_witness(witness);
+            // Verify it's in a state that needs approval before checking who rejects
+            trade_context.current_state().transition(&reject)?;
 
-        // Save back to DB
-        trade_context.save_to_db(&self.instance)?;
+            let progress = trade_context.approval_progress()?;
+            if !progress.awaiting.contains(&approver_id) {
+                return Err(ServiceError::UnauthorizedApprover {
+                    expected: progress.awaiting.join(", "),
+                    got: approver_id.clone(),
+                });
+            }
 
-        Ok(trade_context)
+            // Add Reject witness
+            let witness = Witness::new(
+                trade_id.clone(),
+                approver_id.clone(),
+                self.clock.now(),
+                reject.clone(),
+            )
+            .with_note(note.clone());
+
+            self.append_witness(&mut trade_context, witness, signer)?;
+
+            // Save back to DB
+            self.commit(&trade_context, Some(previous.as_slice()), None)?;
+
+            Ok(trade_context)
+        })
     }
 
-    /// Update trade details (requires re-approval)
+    /// Update trade details (requires re-approval). Allowed while PendingApproval,
+    /// Approved or Rejected, i.e. before the trade is sent to execute
     pub fn update_trade(
         &self,
         trade_id: String,
         trade_details: TradeDetails,
         user_id: String,
-    ) -> anyhow::Result<TradeContext> {
-        // Load existing trade context
-        let mut trade_context = self.load_trade_context(&trade_id)?;
-
-        // Verify trade is in a state that allows updates Updates should only
-        // be possible before execution/booking
-        match trade_context.current_state() {
-            TradeState::Booked => {
-                return Err(anyhow::anyhow!(
-                    "Cannot update trade it has already been booked"
-                ));
-            }
-            TradeState::Cancelled => {
-                return Err(anyhow::anyhow!("Cannot update a cancelled trade"));
-            }
-            TradeState::SentToExecute => {
-                return Err(anyhow::anyhow!(
-                    "Cannot update a trade that has been sent to execute"
-                ));
-            }
-            _ => {
-                // Draft, PendingApproval, Approved are all valid states for updates
-            }
-        }
+        signer: &dyn WitnessSigner,
+    ) -> Result<TradeContext, ServiceError> {
+        self.update_trade_with_note(trade_id, trade_details, user_id, Note::default(), signer)
+    }
 
+    /// Update trade details, recording `note` on the Update
+    pub fn update_trade_with_note(
+        &self,
+        trade_id: String,
+        trade_details: TradeDetails,
+        user_id: String,
+        note: Note,
+        signer: &dyn WitnessSigner,
+    ) -> Result<TradeContext, ServiceError> {
         // Validate and serialise new trade details
         let (details_hash, details_cbor) = trade_details.validate_and_finalise()?;
+        self.check_business_days(&trade_details)?;
 
-        // Create Update witness
-        let witness = Witness::new(
-            trade_id.clone(),
-            user_id,
-            TimeStamp::new(),
-            WitnessType::Update {
+        self.with_retry(|| {
+            // Load existing trade context
+            let (mut trade_context, previous) = self.load_trade_context(&trade_id)?;
+
+            // Verify the trade can still be updated before weighing it against limits
+            let update = WitnessType::Update {
                 details_hash: details_hash.clone(),
-            },
-        );
+            };
+            trade_context.current_state().transition(&update)?;
 
-        // Add witness to context
-        trade_context.insert_witness(witness);
+            // Verify the new details fit within their limits, replacing the old ones
+            self.check_limits(&trade_details, Some(&trade_id))?;
 
-        // Batch insert: new trade details and updated trade context
-        let mut batch = Batch::default();
-        batch.insert(details_hash.as_bytes(), details_cbor);
-        batch.insert(
-            trade_context.trade_id.as_bytes(),
-            minicbor::to_vec(&trade_context)?,
-        );
-        self.instance.apply_batch(batch)?;
+            // Create Update witness
+            let witness = Witness::new(trade_id.clone(), user_id.clone(), self.clock.now(), update)
+                .with_note(note.clone());
 
-        Ok(trade_context)
+            // Sign and add witness to context
+            self.append_witness(&mut trade_context, witness, signer)?;
+
+            // Insert new trade details and updated trade context together
+            self.commit(
+                &trade_context,
+                Some(previous.as_slice()),
+                Some((&details_hash, &details_cbor)),
+            )?;
+
+            Ok(trade_context)
+        })
     }
 
-    /// Cancel a trade
-    pub fn cancel_trade(&self, trade_id: String, user_id: String) -> anyhow::Result<TradeContext> {
-        // Load existing trade context
-        let mut trade_context = self.load_trade_context(&trade_id)?;
+    /// Cancel a trade at any point before it is booked
+    pub fn cancel_trade(
+        &self,
+        trade_id: String,
+        user_id: String,
+        signer: &dyn WitnessSigner,
+    ) -> Result<TradeContext, ServiceError> {
+        self.cancel_trade_with_note(trade_id, user_id, Note::default(), signer)
+    }
 
-        // Verify trade is not already booked business rules: Cancel can
-        // occur at any point before Booked
-        if matches!(trade_context.current_state(), TradeState::Booked) {
-            return Err(anyhow::anyhow!(
-                "Cannot cancel a trade that has already been booked"
-            ));
-        }
+    /// Cancel a trade, recording `note` on the Cancel, e.g. why it was abandoned
+    pub fn cancel_trade_with_note(
+        &self,
+        trade_id: String,
+        user_id: String,
+        note: Note,
+        signer: &dyn WitnessSigner,
+    ) -> Result<TradeContext, ServiceError> {
+        self.with_retry(|| {
+            // Load existing trade context
+            let (mut trade_context, previous) = self.load_trade_context(&trade_id)?;
 
-        // Create Cancel witness
-        let witness = Witness::new(
-            trade_id.clone(),
-            user_id,
-            TimeStamp::new(),
-            WitnessType::Cancel,
-        );
+            // Create Cancel witness
+            let witness = Witness::new(
+                trade_id.clone(),
+                user_id.clone(),
+                self.clock.now(),
+                WitnessType::Cancel,
+            )
+            .with_note(note.clone());
 
-        // Add witness to context
-        trade_context.insert_witness(witness);
+            // Sign and add witness to context
+            self.append_witness(&mut trade_context, witness, signer)?;
 
-        // Save to DB
-        trade_context.save_to_db(&self.instance)?;
+            // Save to DB
+            self.commit(&trade_context, Some(previous.as_slice()), None)?;
 
-        Ok(trade_context)
+            Ok(trade_context)
+        })
     }
 
     /// Send approved trade to execution
-    pub fn execute_trade(&self, trade_id: String, user_id: String) -> anyhow::Result<TradeContext> {
-        // Load existing trade context
-        let mut trade_context = self.load_trade_context(&trade_id)?;
+    pub fn execute_trade(
+        &self,
+        trade_id: String,
+        user_id: String,
+        signer: &dyn WitnessSigner,
+    ) -> Result<TradeContext, ServiceError> {
+        self.execute_trade_with_note(trade_id, user_id, Note::default(), signer)
+    }
 
-        // Verify trade is approved before execution
-        if trade_context.current_state() != TradeState::Approved {
-            return Err(anyhow::anyhow!(
-                "Trade must be approved before execution. Current state: {:?}",
-                trade_context.current_state()
-            ));
-        }
+    /// Send approved trade to execution, recording `note` on the SendToExecute
+    pub fn execute_trade_with_note(
+        &self,
+        trade_id: String,
+        user_id: String,
+        note: Note,
+        signer: &dyn WitnessSigner,
+    ) -> Result<TradeContext, ServiceError> {
+        self.with_retry(|| {
+            // Load existing trade context
+            let (mut trade_context, previous) = self.load_trade_context(&trade_id)?;
 
-        // Create SendToExecute witness
-        let witness = Witness::new(
-            trade_id.clone(),
-            user_id,
-            TimeStamp::new(),
-            WitnessType::SendToExecute,
-        );
+            // Create SendToExecute witness
+            let witness = Witness::new(
+                trade_id.clone(),
+                user_id.clone(),
+                self.clock.now(),
+                WitnessType::SendToExecute,
+            )
+            .with_note(note.clone());
 
-        // Add witness to context
-        trade_context.insert_witness(witness);
+            // Sign and add witness to context
+            self.append_witness(&mut trade_context, witness, signer)?;
 
-        // Save to DB
-        trade_context.save_to_db(&self.instance)?;
+            // Save to DB
+            self.commit(&trade_context, Some(previous.as_slice()), None)?;
 
-        Ok(trade_context)
+            Ok(trade_context)
+        })
     }
 
-    /// Book an executed trade
+    /// Book a trade that has been sent to execute
     pub fn book_trade(
         &self,
         trade_id: String,
         user_id: String,
         strike: u64,
-    ) -> anyhow::Result<TradeContext> {
-        // Load existing trade context
-        let mut trade_context = self.load_trade_context(&trade_id)?;
+        signer: &dyn WitnessSigner,
+    ) -> Result<TradeContext, ServiceError> {
+        self.book_trade_with_note(trade_id, user_id, strike, Note::default(), signer)
+    }
 
-        // Create Book witness
-        let witness = Witness::new(
-            trade_id.clone(),
-            user_id,
-            TimeStamp::new(),
-            WitnessType::Book { strike },
-        );
+    /// Book a trade, recording `note` on the Book
+    pub fn book_trade_with_note(
+        &self,
+        trade_id: String,
+        user_id: String,
+        strike: u64,
+        note: Note,
+        signer: &dyn WitnessSigner,
+    ) -> Result<TradeContext, ServiceError> {
+        self.with_retry(|| {
+            // Load existing trade context
+            let (mut trade_context, previous) = self.load_trade_context(&trade_id)?;
+
+            // Create Book witness
+            let witness = Witness::new(
+                trade_id.clone(),
+                user_id.clone(),
+                self.clock.now(),
+                WitnessType::Book { strike },
+            )
+            .with_note(note.clone());
+
+            // Sign and add witness to context
+            self.append_witness(&mut trade_context, witness, signer)?;
 
-        // Add witness to context
-        trade_context.insert_witness(witness);
+            // Save to DB
+            self.commit(&trade_context, Some(previous.as_slice()), None)?;
 
-        // Save to DB
-        trade_context.save_to_db(&self.instance)?;
+            Ok(trade_context)
+        })
+    }
 
+    /// Fetch a trade, verifying its witness chain and signatures
+    pub fn get_trade(&self, trade_id: &str) -> Result<TradeContext, ServiceError> {
+        let (trade_context, _) = self.load_trade_context(trade_id)?;
         Ok(trade_context)
     }
+
+    /// Fetch every trade in the store
+    pub fn list_trades(&self) -> Result<Vec<TradeContext>, ServiceError> {
+        self.store
+            .iter_contexts()
+            .map(|entry| {
+                let (_, bytes) = entry?;
+                let trade_context = TradeContext::from_cbor_with_skew(&bytes, self.max_clock_skew)?;
+                trade_context.verify_signatures(self.keys.as_ref())?;
+                Ok(trade_context)
+            })
+            .collect()
+    }
+
+    /// Fetch every trade currently in `state`, using the secondary index
+    pub fn list_by_state(&self, state: &TradeState) -> Result<Vec<TradeContext>, ServiceError> {
+        self.list_indexed(&index::state_prefix(state), |_| Ok(true))
+    }
+
+    /// Have `user_id` sign with `key` from now on, by the service's clock. Witnesses
+    /// they signed before keep verifying against the key they were signed with.
+    pub fn rotate_key(&self, user_id: &str, key: VerifyingKey) -> Result<(), ServiceError> {
+        self.keys.rotate(user_id, key, self.clock.now())?;
+        Ok(())
+    }
+
+    /// Hand `delegation.delegator`'s approvals to `delegation.delegate`, replacing any
+    /// earlier delegation between the two
+    pub fn delegate_approvals(&self, delegation: Delegation) -> Result<(), ServiceError> {
+        delegation.validate()?;
+        self.delegations.put(&delegation)?;
+        Ok(())
+    }
+
+    /// Withdraw the delegation from `delegator` to `delegate`, returning whether there
+    /// was one
+    pub fn revoke_delegation(&self, delegator: &str, delegate: &str) -> Result<bool, ServiceError> {
+        Ok(self.delegations.revoke(delegator, delegate)?)
+    }
+
+    /// The approvers a pending trade is waiting on, each with the delegates who can
+    /// currently approve it for them. Empty once the trade needs no more approvals.
+    pub fn get_expected_approvers(
+        &self,
+        trade_id: &str,
+    ) -> Result<Vec<ExpectedApprover>, ServiceError> {
+        let trade_context = self.get_trade(trade_id)?;
+        if !trade_context.requires_approval() {
+            return Ok(Vec::new());
+        }
+
+        let notional = self
+            .get_trade_details(&trade_context.current_details_hash()?)?
+            .notional_amount();
+        let now = self.clock.now();
+
+        trade_context
+            .approval_progress()?
+            .awaiting
+            .into_iter()
+            .map(|approver_id| {
+                let delegates = self
+                    .delegations
+                    .delegations_from(&approver_id)?
+                    .into_iter()
+                    .filter(|delegation| delegation.covers(notional, &now))
+                    .map(|delegation| delegation.delegate)
+                    .collect();
+                Ok(ExpectedApprover {
+                    approver_id,
+                    delegates,
+                })
+            })
+            .collect()
+    }
+
+    /// The first of `awaiting` with an active delegation to `delegate` that covers
+    /// `trade_details`
+    fn delegator_for(
+        &self,
+        awaiting: &[String],
+        delegate: &str,
+        trade_details: &TradeDetails,
+    ) -> Result<Option<String>, ServiceError> {
+        let now = self.clock.now();
+
+        for delegator in awaiting {
+            let delegations = self.delegations.delegations_from(delegator)?;
+            if delegations.iter().any(|delegation| {
+                delegation.delegate == delegate
+                    && delegation.covers(trade_details.notional_amount(), &now)
+            }) {
+                return Ok(Some(delegator.clone()));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Check settlement dates against the calendars, if there are any
+    fn check_business_days(&self, trade_details: &TradeDetails) -> Result<(), ServiceError> {
+        if let Some(calendars) = &self.calendars {
+            calendars.validate(trade_details)?;
+        }
+        Ok(())
+    }
+
+    /// Total notional in `currency` of the trades `scope` covers that are in one of the
+    /// [`EXPOSED_STATES`]
+    pub fn exposure(&self, scope: &LimitScope, currency: Currency) -> Result<u64, ServiceError> {
+        let limit = NotionalLimit {
+            scope: scope.clone(),
+            currency,
+            cap: u64::MAX,
+        };
+        let exposed = self.exposed_details(None)?;
+
+        Ok(sum_notional(&limit, &exposed))
+    }
+
+    /// Check `trade_details` fit within every limit that applies to them, on top of the
+    /// exposure of the other trades. `trade_id` is left out of the exposure, as the
+    /// details being checked replace whatever it counted for before.
+    fn check_limits(
+        &self,
+        trade_details: &TradeDetails,
+        trade_id: Option<&str>,
+    ) -> Result<(), ServiceError> {
+        let applicable: Vec<_> = self
+            .limits
+            .iter()
+            .filter(|limit| limit.applies_to(trade_details))
+            .collect();
+        if applicable.is_empty() {
+            return Ok(());
+        }
+
+        let exposed = self.exposed_details(trade_id)?;
+        for limit in applicable {
+            limit.check(
+                sum_notional(limit, &exposed),
+                trade_details.notional_amount(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Current details of every trade in an exposed state, except `excluded`
+    fn exposed_details(&self, excluded: Option<&str>) -> Result<Vec<TradeDetails>, ServiceError> {
+        let mut exposed = Vec::new();
+
+        for state in &EXPOSED_STATES {
+            for trade_context in self.list_by_state(state)? {
+                if excluded != Some(trade_context.trade_id.as_str()) {
+                    exposed.push(self.get_trade_details(&trade_context.current_details_hash()?)?);
+                }
+            }
+        }
+
+        Ok(exposed)
+    }
+
+    /// Fetch every trade requested by `requester_id`, using the secondary index
+    pub fn list_by_requester(&self, requester_id: &str) -> Result<Vec<TradeContext>, ServiceError> {
+        self.list_indexed(&index::requester_prefix(requester_id), |_| Ok(true))
+    }
+
+    /// Fetch the trades waiting on `approver_id` to approve them, using the secondary
+    /// index. Trades where the approver has already approved, or whose policy has not
+    /// reached their level yet, are left out.
+    pub fn list_pending_for_approver(
+        &self,
+        approver_id: &str,
+    ) -> Result<Vec<TradeContext>, ServiceError> {
+        let mut trades = self.list_indexed(&index::approver_prefix(approver_id), |trade_id| {
+            let pending = index::state_key(&TradeState::PendingApproval, trade_id);
+            Ok(self.store.index_contains(&pending)?)
+        })?;
+        trades.retain(|trade_context| {
+            trade_context
+                .approval_progress()
+                .is_ok_and(|progress| progress.awaiting.iter().any(|id| id == approver_id))
+        });
+
+        Ok(trades)
+    }
+
+    /// Load every trade with an index entry under `prefix` that passes `filter`
+    fn list_indexed(
+        &self,
+        prefix: &[u8],
+        filter: impl Fn(&str) -> Result<bool, ServiceError>,
+    ) -> Result<Vec<TradeContext>, ServiceError> {
+        let mut trades = Vec::new();
+
+        for entry in self.store.scan_index(prefix)? {
+            let trade_id = index::trade_id(prefix, &entry);
+            if filter(&trade_id)? {
+                trades.push(self.get_trade(&trade_id)?);
+            }
+        }
+
+        Ok(trades)
+    }
+
+    /// Drop and recreate the secondary index from the stored contexts, returning how
+    /// many trades were indexed. Use it for stores written before the index existed or
+    /// after restoring a backup. Writes made while the rebuild runs may be missed, so
+    /// run it while the store is idle.
+    pub fn rebuild_index(&self) -> Result<usize, ServiceError> {
+        let trades = self.list_trades()?;
+        let entries = trades.iter().flat_map(index::entries).collect();
+        self.store.replace_index(entries)?;
+
+        Ok(trades.len())
+    }
+
+    /// Fetch a version of trade details by its content hash. The stored bytes are
+    /// re-hashed, so a blob that no longer matches its key is reported as
+    /// [`ValidationError::ContentHashMismatch`] rather than returned.
+    pub fn get_trade_details(&self, details_hash: &str) -> Result<TradeDetails, ServiceError> {
+        let bytes =
+            self.store
+                .get_details(details_hash)?
+                .ok_or_else(|| ServiceError::DetailsNotFound {
+                    details_hash: details_hash.to_string(),
+                })?;
+
+        Ok(TradeDetails::from_cbor(details_hash, &bytes)?)
+    }
+
+    /// Compare the details in force after the witness at `from_idx` with those in force
+    /// after the witness at `to_idx`. Indices are positions in the witness chain, so
+    /// `diff_versions(id, 0, n - 1)` shows everything changed since submission.
+    pub fn diff_versions(
+        &self,
+        trade_id: &str,
+        from_idx: usize,
+        to_idx: usize,
+    ) -> Result<Vec<FieldChange>, ServiceError> {
+        let trade_context = self.get_trade(trade_id)?;
+        let from = self.get_trade_details(&trade_context.details_hash_at(from_idx)?)?;
+        let to = self.get_trade_details(&trade_context.details_hash_at(to_idx)?)?;
+
+        Ok(from.diff(&to))
+    }
+
+    /// Reconstruct a trade as it stood at `at`: its state then, and the version of its
+    /// details in force. Trades with nothing recorded by then come back as a Draft
+    /// without details.
+    pub fn as_of(&self, trade_id: &str, at: TimeStamp<Utc>) -> Result<TradeAsOf, ServiceError> {
+        let trade_context = self.get_trade(trade_id)?;
+        let witness_count = trade_context.witnesses_at(&at);
+
+        let details_hash = match witness_count {
+            0 => None,
+            count => Some(trade_context.details_hash_at(count - 1)?),
+        };
+        let details = details_hash
+            .as_deref()
+            .map(|hash| self.get_trade_details(hash))
+            .transpose()?;
+
+        Ok(TradeAsOf {
+            trade_id: trade_context.trade_id.clone(),
+            state: trade_context.state_at(at.clone()),
+            at,
+            witness_count,
+            details_hash,
+            details,
+        })
+    }
+
+    /// Fetch the details currently in force for a trade, i.e. those referenced by its
+    /// latest Submit or Update
+    pub fn get_current_details(&self, trade_id: &str) -> Result<TradeDetails, ServiceError> {
+        let trade_context = self.get_trade(trade_id)?;
+        self.get_trade_details(&trade_context.current_details_hash()?)
+    }
+}
+
+/// Notional of the trades in `exposed` that count against `limit`
+fn sum_notional(limit: &NotionalLimit, exposed: &[TradeDetails]) -> u64 {
+    exposed
+        .iter()
+        .filter(|details| limit.applies_to(details))
+        .fold(0, |total, details| {
+            total.saturating_add(details.notional_amount())
+        })
 }
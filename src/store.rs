@@ -0,0 +1,326 @@
+//! Storage backends for trade contexts, trade details and the secondary index
+//!
+//! [`TradeService`](crate::service::TradeService) only talks to a [`TradeStore`], so
+//! the same workflow runs over sled on disk ([`SledStore`]) or plain maps in memory
+//! ([`InMemoryStore`]). Stores deal in CBOR bytes rather than decoded objects: the
+//! service compares the bytes it loaded against what is stored to detect concurrent
+//! writers, so a backend never needs to understand the encoding.
+
+use super::context::TradeContext;
+use super::index;
+use super::trade::TradeDetails;
+use sled::Transactional;
+use sled::transaction::{
+    ConflictableTransactionError, TransactionError, TransactionalTree, UnabortableTransactionError,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::RwLock;
+
+/// A set of writes applied all-or-nothing. Index removals are applied before inserts,
+/// so an entry that is both removed and inserted ends up present.
+#[derive(Debug, Default, Clone)]
+pub struct WriteBatch {
+    pub contexts: Vec<(String, Vec<u8>)>,
+    pub details: Vec<(String, Vec<u8>)>,
+    pub index_inserts: Vec<Vec<u8>>,
+    pub index_removals: Vec<Vec<u8>>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn put_context(mut self, trade_id: &str, cbor: &[u8]) -> Self {
+        self.contexts.push((trade_id.to_string(), cbor.to_vec()));
+        self
+    }
+    pub fn put_details(mut self, details_hash: &str, cbor: &[u8]) -> Self {
+        self.details.push((details_hash.to_string(), cbor.to_vec()));
+        self
+    }
+    pub fn insert_index(mut self, entries: impl IntoIterator<Item = Vec<u8>>) -> Self {
+        self.index_inserts.extend(entries);
+        self
+    }
+    pub fn remove_index(mut self, entries: impl IntoIterator<Item = Vec<u8>>) -> Self {
+        self.index_removals.extend(entries);
+        self
+    }
+}
+
+/// Persistence used by the service. Implementations must be safe to share between
+/// threads, and [`TradeStore::compare_and_swap`] must be atomic with respect to every
+/// other write.
+pub trait TradeStore: Send + Sync {
+    /// CBOR of the context stored under `trade_id`
+    fn get_context(&self, trade_id: &str) -> anyhow::Result<Option<Vec<u8>>>;
+    /// Store a context unconditionally
+    fn put_context(&self, trade_id: &str, cbor: &[u8]) -> anyhow::Result<()>;
+    /// CBOR of the trade details stored under `details_hash`
+    fn get_details(&self, details_hash: &str) -> anyhow::Result<Option<Vec<u8>>>;
+    /// Store trade details under their content hash
+    fn put_details(&self, details_hash: &str, cbor: &[u8]) -> anyhow::Result<()>;
+    /// Apply every write in `batch` atomically
+    fn write_batch(&self, batch: &WriteBatch) -> anyhow::Result<()>;
+    /// Apply `batch` atomically, but only if the context stored under `trade_id` is
+    /// still `expected` (`None` meaning absent). Returns `false` without writing
+    /// anything when it is not.
+    fn compare_and_swap(
+        &self,
+        trade_id: &str,
+        expected: Option<&[u8]>,
+        batch: &WriteBatch,
+    ) -> anyhow::Result<bool>;
+    /// Every stored context as `(trade_id, cbor)`, ordered by trade_id
+    fn iter_contexts(&self) -> Box<dyn Iterator<Item = anyhow::Result<(String, Vec<u8>)>> + '_>;
+    /// Index entries starting with `prefix`, in key order
+    fn scan_index(&self, prefix: &[u8]) -> anyhow::Result<Vec<Vec<u8>>>;
+    /// Whether an index entry exists
+    fn index_contains(&self, entry: &[u8]) -> anyhow::Result<bool>;
+    /// Replace the whole index with `entries`
+    fn replace_index(&self, entries: Vec<Vec<u8>>) -> anyhow::Result<()>;
+}
+
+/// Store held in memory, useful for tests and simulations
+#[derive(Default)]
+pub struct InMemoryStore {
+    inner: RwLock<MemoryTrees>,
+}
+
+#[derive(Default)]
+struct MemoryTrees {
+    contexts: BTreeMap<String, Vec<u8>>,
+    details: BTreeMap<String, Vec<u8>>,
+    index: BTreeSet<Vec<u8>>,
+}
+
+impl MemoryTrees {
+    fn apply(&mut self, batch: &WriteBatch) {
+        for (trade_id, cbor) in &batch.contexts {
+            self.contexts.insert(trade_id.clone(), cbor.clone());
+        }
+        for (details_hash, cbor) in &batch.details {
+            self.details.insert(details_hash.clone(), cbor.clone());
+        }
+        for entry in &batch.index_removals {
+            self.index.remove(entry);
+        }
+        for entry in &batch.index_inserts {
+            self.index.insert(entry.clone());
+        }
+    }
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self) -> anyhow::Result<std::sync::RwLockReadGuard<'_, MemoryTrees>> {
+        self.inner
+            .read()
+            .map_err(|_| anyhow::anyhow!("trade store lock poisoned"))
+    }
+
+    fn write(&self) -> anyhow::Result<std::sync::RwLockWriteGuard<'_, MemoryTrees>> {
+        self.inner
+            .write()
+            .map_err(|_| anyhow::anyhow!("trade store lock poisoned"))
+    }
+}
+
+impl TradeStore for InMemoryStore {
+    fn get_context(&self, trade_id: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.read()?.contexts.get(trade_id).cloned())
+    }
+
+    fn put_context(&self, trade_id: &str, cbor: &[u8]) -> anyhow::Result<()> {
+        self.write()?
+            .contexts
+            .insert(trade_id.to_string(), cbor.to_vec());
+        Ok(())
+    }
+
+    fn get_details(&self, details_hash: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.read()?.details.get(details_hash).cloned())
+    }
+
+    fn put_details(&self, details_hash: &str, cbor: &[u8]) -> anyhow::Result<()> {
+        self.write()?
+            .details
+            .insert(details_hash.to_string(), cbor.to_vec());
+        Ok(())
+    }
+
+    fn write_batch(&self, batch: &WriteBatch) -> anyhow::Result<()> {
+        self.write()?.apply(batch);
+        Ok(())
+    }
+
+    fn compare_and_swap(
+        &self,
+        trade_id: &str,
+        expected: Option<&[u8]>,
+        batch: &WriteBatch,
+    ) -> anyhow::Result<bool> {
+        let mut trees = self.write()?;
+        if trees.contexts.get(trade_id).map(Vec::as_slice) != expected {
+            return Ok(false);
+        }
+        trees.apply(batch);
+        Ok(true)
+    }
+
+    fn iter_contexts(&self) -> Box<dyn Iterator<Item = anyhow::Result<(String, Vec<u8>)>> + '_> {
+        // Snapshot so the lock is not held while the caller iterates
+        match self.read() {
+            Ok(trees) => {
+                let contexts: Vec<_> = trees.contexts.clone().into_iter().map(Ok).collect();
+                Box::new(contexts.into_iter())
+            }
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        }
+    }
+
+    fn scan_index(&self, prefix: &[u8]) -> anyhow::Result<Vec<Vec<u8>>> {
+        Ok(self
+            .read()?
+            .index
+            .range(prefix.to_vec()..)
+            .take_while(|entry| entry.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+
+    fn index_contains(&self, entry: &[u8]) -> anyhow::Result<bool> {
+        Ok(self.read()?.index.contains(entry))
+    }
+
+    fn replace_index(&self, entries: Vec<Vec<u8>>) -> anyhow::Result<()> {
+        self.write()?.index = entries.into_iter().collect();
+        Ok(())
+    }
+}
+
+/// Store backed by sled, with contexts, details and index entries in the named trees
+/// [`TradeContext::TREE`], [`TradeDetails::TREE`] and [`index::TREE`]
+pub struct SledStore {
+    contexts: sled::Tree,
+    details: sled::Tree,
+    index: sled::Tree,
+}
+
+impl SledStore {
+    pub fn new(db: &sled::Db) -> anyhow::Result<Self> {
+        Ok(Self {
+            contexts: db.open_tree(TradeContext::TREE)?,
+            details: db.open_tree(TradeDetails::TREE)?,
+            index: db.open_tree(index::TREE)?,
+        })
+    }
+
+    /// Run `check` then apply `batch` in one transaction across the three trees.
+    /// Returns `false` if `check` aborted it.
+    fn transact(
+        &self,
+        check: impl Fn(&TransactionalTree) -> Result<bool, UnabortableTransactionError>,
+        batch: &WriteBatch,
+    ) -> anyhow::Result<bool> {
+        let trees = (&self.contexts, &self.details, &self.index);
+        let result = trees.transaction(|(tx_contexts, tx_details, tx_index)| {
+            if !check(tx_contexts)? {
+                return Err(ConflictableTransactionError::Abort(()));
+            }
+            for (trade_id, cbor) in &batch.contexts {
+                tx_contexts.insert(trade_id.as_bytes(), cbor.as_slice())?;
+            }
+            for (details_hash, cbor) in &batch.details {
+                tx_details.insert(details_hash.as_bytes(), cbor.as_slice())?;
+            }
+            for entry in &batch.index_removals {
+                tx_index.remove(entry.as_slice())?;
+            }
+            for entry in &batch.index_inserts {
+                tx_index.insert(entry.as_slice(), &[] as &[u8])?;
+            }
+            Ok(())
+        });
+
+        match result {
+            Ok(()) => Ok(true),
+            Err(TransactionError::Abort(())) => Ok(false),
+            Err(TransactionError::Storage(e)) => Err(e.into()),
+        }
+    }
+}
+
+impl TradeStore for SledStore {
+    fn get_context(&self, trade_id: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.contexts.get(trade_id.as_bytes())?.map(|v| v.to_vec()))
+    }
+
+    fn put_context(&self, trade_id: &str, cbor: &[u8]) -> anyhow::Result<()> {
+        self.contexts.insert(trade_id.as_bytes(), cbor)?;
+        Ok(())
+    }
+
+    fn get_details(&self, details_hash: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self
+            .details
+            .get(details_hash.as_bytes())?
+            .map(|v| v.to_vec()))
+    }
+
+    fn put_details(&self, details_hash: &str, cbor: &[u8]) -> anyhow::Result<()> {
+        self.details.insert(details_hash.as_bytes(), cbor)?;
+        Ok(())
+    }
+
+    fn write_batch(&self, batch: &WriteBatch) -> anyhow::Result<()> {
+        self.transact(|_| Ok(true), batch)?;
+        Ok(())
+    }
+
+    fn compare_and_swap(
+        &self,
+        trade_id: &str,
+        expected: Option<&[u8]>,
+        batch: &WriteBatch,
+    ) -> anyhow::Result<bool> {
+        self.transact(
+            |tx_contexts| Ok(tx_contexts.get(trade_id.as_bytes())?.as_deref() == expected),
+            batch,
+        )
+    }
+
+    fn iter_contexts(&self) -> Box<dyn Iterator<Item = anyhow::Result<(String, Vec<u8>)>> + '_> {
+        Box::new(self.contexts.iter().map(|entry| {
+            let (key, value) = entry?;
+            Ok((String::from_utf8_lossy(&key).into_owned(), value.to_vec()))
+        }))
+    }
+
+    fn scan_index(&self, prefix: &[u8]) -> anyhow::Result<Vec<Vec<u8>>> {
+        self.index
+            .scan_prefix(prefix)
+            .keys()
+            .map(|entry| Ok(entry?.to_vec()))
+            .collect()
+    }
+
+    fn index_contains(&self, entry: &[u8]) -> anyhow::Result<bool> {
+        Ok(self.index.contains_key(entry)?)
+    }
+
+    fn replace_index(&self, entries: Vec<Vec<u8>>) -> anyhow::Result<()> {
+        let mut batch = sled::Batch::default();
+        for entry in self.index.iter().keys() {
+            batch.remove(entry?);
+        }
+        for entry in entries {
+            batch.insert(entry, &[] as &[u8]);
+        }
+        self.index.apply_batch(batch)?;
+        Ok(())
+    }
+}
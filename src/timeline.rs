@@ -0,0 +1,229 @@
+//! Structured trade history
+//!
+//! [`TradeContext::timeline`](crate::context::TradeContext::timeline) turns a witness
+//! chain into [`TimelineEntry`] values: who did what and when, with the state each
+//! witness left the trade in. Callers can assert on or serialise the entries directly;
+//! a [`History`] gathers them with the trade they belong to. `view_history` and the
+//! [`Display`](std::fmt::Display) impls render them as text, with the ID truncation
+//! and time zone chosen by a [`TimelineFormat`].
+
+use super::context::{ApprovalProgress, Note, TradeState, WitnessType};
+use super::trade::{FieldChange, TimeStamp};
+use chrono::{Local, Utc};
+
+/// One witness of a trade's history and where it left the trade
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TimelineEntry {
+    /// Position of the witness in the chain, 0 being the Submit
+    pub index: usize,
+    pub timestamp: TimeStamp<Utc>,
+    /// Signer of the witness
+    pub user_id: String,
+    pub action: &'static str,
+    pub payload: WitnessType,
+    pub note: Option<Note>,
+    /// Approvals counted so far, for an Approve that left the policy incomplete
+    pub progress: Option<ApprovalProgress>,
+    pub state_after: TradeState,
+    /// Fields an Update changed, only filled in by `timeline_with_changes`
+    pub changes: Vec<FieldChange>,
+}
+
+/// A trade's whole timeline, from
+/// [`TradeContext::history`](crate::context::TradeContext::history)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct History {
+    pub trade_id: String,
+    pub entries: Vec<TimelineEntry>,
+    /// State after the last entry
+    pub state: TradeState,
+}
+
+/// How a timeline is rendered as text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelineFormat {
+    /// Longest an ID is shown before being cut short with `...`, `None` to show IDs
+    /// in full
+    pub max_id_len: Option<usize>,
+    /// Show timestamps in the local time zone rather than UTC
+    pub local_time: bool,
+}
+
+impl Default for TimelineFormat {
+    fn default() -> Self {
+        Self {
+            max_id_len: Some(12),
+            local_time: false,
+        }
+    }
+}
+
+impl TimelineFormat {
+    /// Show IDs in full
+    pub fn full_ids(mut self) -> Self {
+        self.max_id_len = None;
+        self
+    }
+
+    /// Cut IDs longer than `max_id_len` short
+    pub fn truncate_ids(mut self, max_id_len: usize) -> Self {
+        self.max_id_len = Some(max_id_len);
+        self
+    }
+
+    /// Show timestamps in the local time zone
+    pub fn local_time(mut self) -> Self {
+        self.local_time = true;
+        self
+    }
+
+    fn id(&self, id: &str) -> String {
+        match self.max_id_len {
+            Some(max_len) if id.chars().count() > max_len => {
+                format!("{}...", id.chars().take(max_len).collect::<String>())
+            }
+            _ => id.to_string(),
+        }
+    }
+
+    fn timestamp(&self, timestamp: &TimeStamp<Utc>) -> String {
+        let date = timestamp.to_datetime_utc();
+        if self.local_time {
+            date.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S %:z")
+                .to_string()
+        } else {
+            date.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+        }
+    }
+}
+
+impl TimelineEntry {
+    /// The entry as text in the given format: a heading line, then indented lines for
+    /// the payload, the note and any changed fields
+    pub fn display<'a>(&'a self, format: &'a TimelineFormat) -> impl std::fmt::Display + 'a {
+        DisplayEntry {
+            entry: self,
+            format,
+        }
+    }
+
+    /// The payload worth showing next to the action, empty when there is none
+    fn summary(&self, format: &TimelineFormat) -> String {
+        match &self.payload {
+            WitnessType::Submit {
+                requester_id,
+                approver_id,
+                approval_policy,
+                ..
+            } => {
+                let approvers = match approval_policy {
+                    Some(policy) => format!("approvers: {}", policy),
+                    None => format!("approver: {}", format.id(approver_id)),
+                };
+                format!("requester: {}, {}", format.id(requester_id), approvers)
+            }
+            WitnessType::Approve { on_behalf_of } => {
+                let mut parts = Vec::new();
+                if let Some(delegator) = on_behalf_of {
+                    parts.push(format!("on behalf of: {}", format.id(delegator)));
+                }
+                if let Some(progress) = &self.progress {
+                    parts.push(format!(
+                        "approvals: {}/{}",
+                        progress.approved_by.len(),
+                        progress.required
+                    ));
+                }
+                parts.join(", ")
+            }
+            WitnessType::Update { details_hash } => {
+                format!(
+                    "new hash: {}...",
+                    &details_hash[..8.min(details_hash.len())]
+                )
+            }
+            WitnessType::Reject { reason } => format!("reason: {}", reason),
+            WitnessType::Book { strike } => format!("strike: {}", strike),
+            WitnessType::Cancel | WitnessType::SendToExecute => String::new(),
+        }
+    }
+}
+
+impl History {
+    /// The history as text in the given format: a heading with the trade_id, each
+    /// entry as [`TimelineEntry::display`] renders it, then the current state
+    pub fn display<'a>(&'a self, format: &'a TimelineFormat) -> impl std::fmt::Display + 'a {
+        DisplayHistory {
+            history: self,
+            format,
+        }
+    }
+}
+
+impl std::fmt::Display for History {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.display(&TimelineFormat::default()).fmt(f)
+    }
+}
+
+struct DisplayHistory<'a> {
+    history: &'a History,
+    format: &'a TimelineFormat,
+}
+
+impl std::fmt::Display for DisplayHistory<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { history, format } = self;
+        writeln!(f, "Trade Timeline: {}", history.trade_id)?;
+
+        if history.entries.is_empty() {
+            return write!(f, "\n  No witnesses recorded yet (Draft state)");
+        }
+
+        for entry in &history.entries {
+            write!(f, "\n{}", entry.display(format))?;
+        }
+        write!(f, "\n\nCurrent State: {:?}", history.state)
+    }
+}
+
+impl std::fmt::Display for TimelineEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.display(&TimelineFormat::default()).fmt(f)
+    }
+}
+
+struct DisplayEntry<'a> {
+    entry: &'a TimelineEntry,
+    format: &'a TimelineFormat,
+}
+
+impl std::fmt::Display for DisplayEntry<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { entry, format } = self;
+        write!(
+            f,
+            "{:>2}. {} │ {:<17} │ {} → {:?}",
+            entry.index + 1,
+            format.timestamp(&entry.timestamp),
+            entry.action,
+            format.id(&entry.user_id),
+            entry.state_after
+        )?;
+
+        let summary = entry.summary(format);
+        if !summary.is_empty() {
+            write!(f, "\n     {}", summary)?;
+        }
+        if let Some(note) = &entry.note {
+            write!(f, "\n     {}", note)?;
+        }
+        for change in &entry.changes {
+            write!(f, "\n       {}", change)?;
+        }
+        Ok(())
+    }
+}
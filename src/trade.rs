@@ -1,10 +1,15 @@
 //! Core trade details and witness types
+use super::canonical::{decode_canonical, to_canonical_vec};
 use super::error::{TradeError, ValidationError};
+use super::store::TradeStore;
 use bech32::Bech32;
 use chrono::{DateTime, TimeZone, Utc};
 use uuid7::uuid7;
 
-#[derive(minicbor::Encode, minicbor::Decode, Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[derive(
+    minicbor::Encode, minicbor::Decode, Debug, Clone, Copy, Eq, Ord, PartialEq, PartialOrd,
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Currency {
     #[n(0)]
     USD,
@@ -14,7 +19,10 @@ pub enum Currency {
     EUR,
 }
 
-#[derive(minicbor::Encode, minicbor::Decode, Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[derive(
+    minicbor::Encode, minicbor::Decode, Debug, Clone, Copy, Eq, Ord, PartialEq, PartialOrd,
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Direction {
     #[n(0)]
     Buy,
@@ -22,9 +30,35 @@ pub enum Direction {
     Sell,
 }
 
+impl std::str::FromStr for Currency {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "USD" => Ok(Self::USD),
+            "GBP" => Ok(Self::GBP),
+            "EUR" => Ok(Self::EUR),
+            _ => Err(TradeError::InvalidCurrency.into()),
+        }
+    }
+}
+
+impl std::str::FromStr for Direction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Buy" => Ok(Self::Buy),
+            "Sell" => Ok(Self::Sell),
+            _ => Err(anyhow::anyhow!("Unknown direction: {}", s)),
+        }
+    }
+}
+
 // Also used for constructing drafts
 // Key is the hash of this struct encoded into CBOR
 #[derive(minicbor::Encode, minicbor::Decode, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TradeDetails {
     // No ID field, as the ID *is* the hash of this struct
     #[n(0)]
@@ -54,6 +88,29 @@ pub struct TradeDetails {
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct TimeStamp<T: TimeZone>(DateTime<T>);
 
+/// A field whose value differs between two versions of trade details. Values are
+/// rendered as strings, `None` meaning the field is unset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl std::fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let render = |value: &Option<String>| value.clone().unwrap_or_else(|| "unset".into());
+        write!(
+            f,
+            "{}: {} → {}",
+            self.field,
+            render(&self.old),
+            render(&self.new)
+        )
+    }
+}
+
 impl TimeStamp<Utc> {
     pub fn new() -> Self {
         Self(Utc::now())
@@ -69,6 +126,9 @@ impl TimeStamp<Utc> {
 }
 
 impl TradeDetails {
+    /// Name of the sled tree holding every version of trade details, keyed by content hash
+    pub const TREE: &'static str = "details";
+
     /// Construct a new builder object, this becomes the basis for a draft
     pub fn new() -> Self {
         Self::default()
@@ -94,6 +154,16 @@ impl TradeDetails {
 
         self
     }
+    /// Use an existing trading entity ID rather than generating a new one
+    pub fn set_trade_entity(mut self, entity_id: &str) -> Self {
+        self.trading_entity = Some(entity_id.to_string());
+        self
+    }
+    /// Use an existing counter party ID rather than generating a new one
+    pub fn set_counter_party(mut self, entity_id: &str) -> Self {
+        self.counter_party = Some(entity_id.to_string());
+        self
+    }
     pub fn set_direction(mut self, direction: Direction) -> Self {
         self.direction = Some(direction);
         self
@@ -130,6 +200,74 @@ impl TradeDetails {
         self.strike = Some(rate);
         self
     }
+    pub fn trading_entity(&self) -> Option<&str> {
+        self.trading_entity.as_deref()
+    }
+    pub fn counter_party(&self) -> Option<&str> {
+        self.counter_party.as_deref()
+    }
+    pub fn direction(&self) -> Option<Direction> {
+        self.direction
+    }
+    pub fn notional_currency(&self) -> Option<Currency> {
+        self.notional_currency
+    }
+    pub fn notional_amount(&self) -> u64 {
+        self.notional_amount
+    }
+    pub fn underlying_currency(&self) -> Option<Currency> {
+        self.underlying_currency
+    }
+    pub fn underlying_amount(&self) -> u64 {
+        self.underlying_amount
+    }
+    pub fn trade_date(&self) -> Option<&TimeStamp<Utc>> {
+        self.trade_date.as_ref()
+    }
+    pub fn value_date(&self) -> Option<&TimeStamp<Utc>> {
+        self.value_date.as_ref()
+    }
+    pub fn delivery_date(&self) -> Option<&TimeStamp<Utc>> {
+        self.delivery_date.as_ref()
+    }
+    pub fn strike(&self) -> Option<u64> {
+        self.strike
+    }
+    /// Every field rendered for comparison, in declaration order
+    pub(crate) fn rendered_fields(&self) -> [(&'static str, Option<String>); 11] {
+        let date =
+            |d: &Option<TimeStamp<Utc>>| d.as_ref().map(|d| d.to_datetime_utc().to_rfc3339());
+        let currency = |c: &Option<Currency>| c.as_ref().map(|c| format!("{:?}", c));
+
+        [
+            ("trading_entity", self.trading_entity.clone()),
+            ("counter_party", self.counter_party.clone()),
+            (
+                "direction",
+                self.direction.as_ref().map(|d| format!("{:?}", d)),
+            ),
+            ("notional_currency", currency(&self.notional_currency)),
+            ("notional_amount", Some(self.notional_amount.to_string())),
+            ("underlying_currency", currency(&self.underlying_currency)),
+            (
+                "underlying_amount",
+                Some(self.underlying_amount.to_string()),
+            ),
+            ("trade_date", date(&self.trade_date)),
+            ("value_date", date(&self.value_date)),
+            ("delivery_date", date(&self.delivery_date)),
+            ("strike", self.strike.map(|s| s.to_string())),
+        ]
+    }
+    /// Fields that differ between `self` (the old version) and `other` (the new one)
+    pub fn diff(&self, other: &Self) -> Vec<FieldChange> {
+        self.rendered_fields()
+            .into_iter()
+            .zip(other.rendered_fields())
+            .filter(|((_, old), (_, new))| old != new)
+            .map(|((field, old), (_, new))| FieldChange { field, old, new })
+            .collect()
+    }
     /// Checks if the predicate `a <= b <= c` is true as referenced in the exercise doc
     pub fn validate_dates(&self) -> bool {
         let a = self.trade_date.as_ref();
@@ -147,7 +285,7 @@ impl TradeDetails {
             _ => false,
         }
     }
-    // Checks fields, and performs validation. returns a hash of the trade and its contetents serialised into cbor
+    // Checks fields, and performs validation. returns a hash of the trade and its contetents serialised into canonical cbor
     pub fn validate_and_finalise(&self) -> anyhow::Result<(String, Vec<u8>)> {
         if self.trading_entity.is_none() {
             return Err(TradeError::InvalidEntity(self.trading_entity.clone()).into());
@@ -156,19 +294,19 @@ impl TradeDetails {
             return Err(TradeError::InvalidEntity(self.counter_party.clone()).into());
         }
         if self.direction.is_none() {
-            return Err(anyhow::Error::msg("Direction is not set"));
+            return Err(TradeError::MissingDirection.into());
         }
         if self.notional_currency.is_none() {
             return Err(TradeError::InvalidCurrency.into());
         }
         if self.notional_amount == 0 {
-            return Err(anyhow::Error::msg("Notional amount is set to zero"));
+            return Err(TradeError::ZeroAmount("Notional".into()).into());
         }
         if self.underlying_currency.is_none() {
             return Err(TradeError::InvalidCurrency.into());
         }
         if self.underlying_amount == 0 {
-            return Err(anyhow::Error::msg("underlying amount is set to zero"));
+            return Err(TradeError::ZeroAmount("Underlying".into()).into());
         }
 
         if self.trade_date.is_none() {
@@ -190,11 +328,33 @@ impl TradeDetails {
             return Err(ValidationError::DateValidation.into());
         }
 
-        let contents = minicbor::to_vec(self)?;
+        let contents = to_canonical_vec(self)?;
         let hash = sha256::digest(&contents);
 
         Ok((hash, contents))
     }
+    /// Decode details stored under `details_hash`, rejecting bytes that do not hash to
+    /// that key or are not canonical
+    pub fn from_cbor(details_hash: &str, bytes: &[u8]) -> anyhow::Result<Self> {
+        let found = sha256::digest(bytes);
+        if found != details_hash {
+            return Err(ValidationError::ContentHashMismatch {
+                key: details_hash.to_string(),
+                found,
+            }
+            .into());
+        }
+
+        decode_canonical(bytes)
+    }
+    /// Load a version of the details from a store by its content hash, verifying it
+    pub fn load_from_store(store: &dyn TradeStore, details_hash: &str) -> anyhow::Result<Self> {
+        let bytes = store
+            .get_details(details_hash)?
+            .ok_or_else(|| anyhow::anyhow!("Trade details not found: {}", details_hash))?;
+
+        Self::from_cbor(details_hash, &bytes)
+    }
 }
 impl<T: TimeZone> From<DateTime<T>> for TimeStamp<T> {
     fn from(value: DateTime<T>) -> Self {
@@ -223,6 +383,21 @@ impl<'b, C> minicbor::Decode<'b, C> for TimeStamp<Utc> {
         Ok(TimeStamp(DateTime::from_timestamp_nanos(nsecs)))
     }
 }
+/// Timestamps are rendered as RFC 3339 strings outside of CBOR
+#[cfg(feature = "serde")]
+impl serde::Serialize for TimeStamp<Utc> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_rfc3339())
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for TimeStamp<Utc> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let date = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        let date = DateTime::parse_from_rfc3339(&date).map_err(serde::de::Error::custom)?;
+        Ok(TimeStamp(date.with_timezone(&Utc)))
+    }
+}
 
 #[cfg(test)]
 mod tests {
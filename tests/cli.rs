@@ -0,0 +1,162 @@
+//! End-to-end tests for the `trade-approval` binary
+//!
+//! These drive the compiled binary against a temporary sled database, the same way
+//! an operator would from a shell.
+#![cfg(feature = "cli")]
+
+use std::path::Path;
+use std::process::{Command, Output};
+use tempfile::tempdir;
+use trade_approval::trade::{Currency, Direction, TimeStamp, TradeDetails};
+
+/// Run the binary against the given database and return its output
+fn run(db: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_trade-approval"))
+        .arg("--db")
+        .arg(db)
+        .args(args)
+        .output()
+        .expect("failed to run trade-approval binary")
+}
+
+/// Run the binary, assert it succeeded and return trimmed stdout
+fn run_ok(db: &Path, args: &[&str]) -> String {
+    let output = run(db, args);
+    assert!(
+        output.status.success(),
+        "{:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+const DETAILS_JSON: &str = r#"{
+    "trading_entity": "entity_1abc",
+    "counter_party": "counter_1xyz",
+    "direction": "Buy",
+    "notional_currency": "USD",
+    "notional_amount": 1000000,
+    "underlying_currency": "EUR",
+    "underlying_amount": 850000,
+    "trade_date": "2025-06-02T00:00:00Z",
+    "value_date": "2025-06-04T00:00:00Z",
+    "delivery_date": "2025-06-04T00:00:00Z"
+}"#;
+
+#[test]
+fn submit_approve_and_inspect() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let db = temp_dir.path().join("cli.db");
+    let details = temp_dir.path().join("details.json");
+    let user_key = temp_dir.path().join("user.key");
+    let approver_key = temp_dir.path().join("approver.key");
+    std::fs::write(&details, DETAILS_JSON)?;
+
+    let user_key = user_key.to_str().unwrap();
+    let approver_key = approver_key.to_str().unwrap();
+    let details = details.to_str().unwrap();
+
+    run_ok(&db, &["keygen", "--user", "user_1", "--out", user_key]);
+    run_ok(&db, &["keygen", "--user", "user_2", "--out", approver_key]);
+
+    let trade_id = run_ok(
+        &db,
+        &[
+            "submit",
+            "--details",
+            details,
+            "--requester",
+            "user_1",
+            "--approver",
+            "user_2",
+            "--user",
+            "user_1",
+            "--key",
+            user_key,
+        ],
+    );
+    assert!(trade_id.starts_with("trade_"));
+
+    // The requester holds a key, but not the approver's
+    let forged = run(
+        &db,
+        &["approve", &trade_id, "--user", "user_2", "--key", user_key],
+    );
+    assert!(!forged.status.success());
+
+    let state = run_ok(
+        &db,
+        &[
+            "approve",
+            &trade_id,
+            "--user",
+            "user_2",
+            "--key",
+            approver_key,
+        ],
+    );
+    assert_eq!(state, "Approved");
+
+    let approved = run_ok(&db, &["list", "--state", "Approved"]);
+    assert!(approved.contains(&trade_id));
+    let pending = run_ok(&db, &["list", "--state", "PendingApproval"]);
+    assert!(!pending.contains(&trade_id));
+
+    let history = run_ok(&db, &["show", &trade_id]);
+    assert!(history.contains("Approve"));
+
+    // The details are stored under the hash of the same details built in code
+    let (hash, _) = TradeDetails::new()
+        .set_trade_entity("entity_1abc")
+        .set_counter_party("counter_1xyz")
+        .set_direction(Direction::Buy)
+        .set_notional_currency(Currency::USD)
+        .set_notional_amount(1_000_000)
+        .set_underlying_currency(Currency::EUR)
+        .set_underlying_amount(850_000)
+        .set_trade_date(TimeStamp::new_with(2025, 6, 2, 0, 0, 0))
+        .set_value_date(TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+        .set_delivery_date(TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+        .validate_and_finalise()?;
+    let stored = run_ok(&db, &["details", &hash]);
+    assert!(stored.contains("1000000"));
+
+    let exported = run_ok(&db, &["export", &trade_id]);
+    assert!(exported.contains(&format!("\"details_hash\": \"{}\"", hash)));
+    let csv = run_ok(&db, &["export", "--format", "csv"]);
+    assert_eq!(csv.lines().count(), 3);
+    assert!(csv.starts_with("trade_id,index,timestamp"));
+
+    Ok(())
+}
+
+#[test]
+fn keygen_never_replaces_a_key() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let db = temp_dir.path().join("cli.db");
+    let key = temp_dir.path().join("user.key");
+    let other = temp_dir.path().join("other.key");
+    let key_path = key.to_str().unwrap();
+    let other_path = other.to_str().unwrap();
+
+    run_ok(&db, &["keygen", "--user", "user_1", "--out", key_path]);
+    let secret = std::fs::read_to_string(&key)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&key)?.permissions().mode() & 0o777, 0o600);
+    }
+
+    // A registered user keeps their key and no new one is written
+    let again = run(&db, &["keygen", "--user", "user_1", "--out", other_path]);
+    assert!(!again.status.success());
+    assert!(!other.exists());
+
+    // Nor is an existing key file overwritten for another user
+    let clobber = run(&db, &["keygen", "--user", "user_2", "--out", key_path]);
+    assert!(!clobber.status.success());
+    assert_eq!(std::fs::read_to_string(&key)?, secret);
+
+    Ok(())
+}
@@ -0,0 +1,231 @@
+//! Integration tests for the `export` feature
+//!
+//! Trades are driven through a [`TradeService`] over an [`InMemoryStore`] and the
+//! exports are checked against the documented schema.
+#![cfg(feature = "export")]
+
+use ed25519_dalek::SigningKey;
+use serde_json::Value;
+use std::sync::Arc;
+use trade_approval::{
+    context::{Note, TradeState},
+    export::{self, CSV_COLUMNS, SCHEMA_VERSION, TradeExport},
+    keys::{self, InMemoryKeyRegistry, KeyRegistry},
+    service::TradeService,
+    store::InMemoryStore,
+    trade::{Currency, Direction, TimeStamp, TradeDetails},
+};
+
+struct Desk {
+    service: TradeService,
+    store: Arc<InMemoryStore>,
+    registry: Arc<InMemoryKeyRegistry>,
+    requester_key: SigningKey,
+    approver_key: SigningKey,
+}
+
+fn desk() -> anyhow::Result<Desk> {
+    let store = Arc::new(InMemoryStore::new());
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let requester_key = keys::generate_signing_key();
+    let approver_key = keys::generate_signing_key();
+    registry.register("requester", requester_key.verifying_key())?;
+    registry.register("approver", approver_key.verifying_key())?;
+
+    Ok(Desk {
+        service: TradeService::new(store.clone(), registry.clone()),
+        store,
+        registry,
+        requester_key,
+        approver_key,
+    })
+}
+
+fn details(notional_amount: u64) -> TradeDetails {
+    TradeDetails::new()
+        .set_trade_entity("entity_1abc")
+        .set_counter_party("counter_1xyz")
+        .set_direction(Direction::Buy)
+        .set_notional_currency(Currency::USD)
+        .set_notional_amount(notional_amount)
+        .set_underlying_currency(Currency::EUR)
+        .set_underlying_amount(850_000)
+        .set_trade_date(TimeStamp::new_with(2025, 6, 2, 0, 0, 0))
+        .set_value_date(TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+        .set_delivery_date(TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+}
+
+/// Submit, update and approve a trade, returning its trade_id
+fn updated_and_approved(desk: &Desk) -> anyhow::Result<String> {
+    let ctx = desk.service.submit_trade(
+        details(1_000_000),
+        "requester".into(),
+        "approver".into(),
+        "requester".into(),
+        &desk.requester_key,
+    )?;
+    desk.service.update_trade_with_note(
+        ctx.trade_id.clone(),
+        details(2_000_000),
+        "requester".into(),
+        Note::reason("client asked for \"double\", same terms").tag("ticket", "OPS-7"),
+        &desk.requester_key,
+    )?;
+    desk.service
+        .approve_trade(ctx.trade_id.clone(), "approver".into(), &desk.approver_key)?;
+    Ok(ctx.trade_id)
+}
+
+#[test]
+fn json_export_records_each_step_and_version() -> anyhow::Result<()> {
+    let desk = desk()?;
+    let trade_id = updated_and_approved(&desk)?;
+    let ctx = desk.service.get_trade(&trade_id)?;
+
+    let export = TradeExport::from_context(&ctx, desk.store.as_ref())?;
+    let json: Value = serde_json::from_str(&export.to_json()?)?;
+
+    assert_eq!(json["schema_version"], SCHEMA_VERSION);
+    assert_eq!(json["trade_id"], trade_id.as_str());
+    assert_eq!(json["state"], "Approved");
+
+    let steps = json["steps"].as_array().unwrap();
+    let states: Vec<&str> = steps
+        .iter()
+        .map(|step| step["state_after"].as_str().unwrap())
+        .collect();
+    assert_eq!(states, ["PendingApproval", "PendingApproval", "Approved"]);
+    assert_eq!(steps[1]["action"], "Update");
+    assert_eq!(steps[1]["witness"]["type"], "Update");
+    assert_eq!(steps[1]["note"]["tags"][0]["key"], "ticket");
+    assert!(steps[0].get("note").is_none());
+
+    // Each step's witness_hash is the parent of the next
+    let (first_hash, _) = ctx.witness_set[0].serialize_with_hash()?;
+    assert_eq!(steps[0]["witness_hash"], first_hash.as_str());
+
+    // The Approve leaves the updated details in force, and each version appears once
+    let versions = json["versions"].as_array().unwrap();
+    assert_eq!(versions.len(), 2);
+    assert_eq!(steps[2]["details_hash"], versions[1]["details_hash"]);
+    assert_eq!(versions[1]["details"]["notional_amount"], 2_000_000);
+
+    Ok(())
+}
+
+#[test]
+fn csv_export_has_one_quoted_row_per_witness() -> anyhow::Result<()> {
+    let desk = desk()?;
+    let trade_id = updated_and_approved(&desk)?;
+    let ctx = desk.service.get_trade(&trade_id)?;
+
+    let csv = TradeExport::from_context(&ctx, desk.store.as_ref())?.to_csv()?;
+    let lines: Vec<&str> = csv.split_terminator("\r\n").collect();
+
+    assert_eq!(lines.len(), 1 + ctx.witness_set.len());
+    assert_eq!(lines[0], CSV_COLUMNS.join(","));
+
+    // The reason holds a comma and quotes, so it is quoted with the quotes doubled
+    assert!(lines[2].contains(",\"client asked for \"\"double\"\", same terms\",ticket=OPS-7,"));
+    assert!(lines[2].starts_with(&format!("{},1,", trade_id)));
+    assert!(lines[3].contains(",Approve,Approved,"));
+    assert!(lines[3].contains(",2000000,"));
+
+    Ok(())
+}
+
+#[test]
+fn bulk_export_covers_every_trade() -> anyhow::Result<()> {
+    let desk = desk()?;
+    let first = updated_and_approved(&desk)?;
+    let second = desk
+        .service
+        .submit_trade(
+            details(500_000),
+            "requester".into(),
+            "approver".into(),
+            "requester".into(),
+            &desk.requester_key,
+        )?
+        .trade_id;
+
+    let exports = export::export_store(
+        desk.store.as_ref(),
+        desk.registry.as_ref(),
+        chrono::TimeDelta::zero(),
+    )?;
+    let mut trade_ids: Vec<&str> = exports.iter().map(|e| e.trade_id.as_str()).collect();
+    let mut expected = vec![first.as_str(), second.as_str()];
+    expected.sort();
+    trade_ids.sort();
+    assert_eq!(trade_ids, expected);
+    assert!(
+        exports
+            .iter()
+            .any(|export| export.state == TradeState::PendingApproval)
+    );
+
+    let mut json = Vec::new();
+    export::write_store_json(
+        desk.store.as_ref(),
+        desk.registry.as_ref(),
+        chrono::TimeDelta::zero(),
+        &mut json,
+    )?;
+    let json: Value = serde_json::from_slice(&json)?;
+    assert_eq!(json["schema_version"], SCHEMA_VERSION);
+    assert_eq!(json["trades"].as_array().unwrap().len(), 2);
+
+    let mut csv = Vec::new();
+    export::write_store_csv(
+        desk.store.as_ref(),
+        desk.registry.as_ref(),
+        chrono::TimeDelta::zero(),
+        &mut csv,
+    )?;
+    let csv = String::from_utf8(csv)?;
+    // One header, then three witnesses for the first trade and one for the second
+    assert_eq!(csv.split_terminator("\r\n").count(), 1 + 3 + 1);
+    assert_eq!(csv.matches("trade_id,index,").count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn bulk_export_refuses_unverified_signatures() -> anyhow::Result<()> {
+    let desk = desk()?;
+    updated_and_approved(&desk)?;
+
+    // The approver's witness does not verify against the key this registry holds
+    let impostor = InMemoryKeyRegistry::new();
+    impostor.register("requester", desk.requester_key.verifying_key())?;
+    impostor.register("approver", keys::generate_signing_key().verifying_key())?;
+
+    assert!(
+        export::export_store(desk.store.as_ref(), &impostor, chrono::TimeDelta::zero()).is_err()
+    );
+    assert!(
+        export::write_store_json(
+            desk.store.as_ref(),
+            &impostor,
+            chrono::TimeDelta::zero(),
+            &mut Vec::new()
+        )
+        .is_err()
+    );
+    assert!(
+        export::write_store_csv(
+            desk.store.as_ref(),
+            &impostor,
+            chrono::TimeDelta::zero(),
+            &mut Vec::new()
+        )
+        .is_err()
+    );
+
+    // An unregistered signer fails the same way
+    let empty = InMemoryKeyRegistry::new();
+    assert!(export::export_store(desk.store.as_ref(), &empty, chrono::TimeDelta::zero()).is_err());
+
+    Ok(())
+}
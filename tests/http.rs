@@ -0,0 +1,351 @@
+//! Integration tests for the `http` feature
+//!
+//! Each test starts an [`ApiServer`] on a free localhost port over a temporary sled
+//! database and talks to it with plain HTTP/1.1 requests.
+#![cfg(feature = "http")]
+
+use ed25519_dalek::{Signer, SigningKey};
+use serde_json::{Value, json};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use tempfile::{TempDir, tempdir};
+use trade_approval::{
+    error::{ServiceError, ValidationError},
+    http::{self, ApiServer},
+    keys::{self, InMemoryKeyRegistry, KeyRegistry},
+    service::TradeService,
+    store::SledStore,
+};
+
+/// Send a request and return the status code and decoded JSON body
+fn request(addr: SocketAddr, method: &str, path: &str, body: Option<&Value>) -> (u16, Value) {
+    let body = body.map(Value::to_string).unwrap_or_default();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        addr,
+        body.len(),
+        body
+    )
+    .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let status = response
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .expect("response has a status line");
+    let (_, payload) = response
+        .split_once("\r\n\r\n")
+        .expect("response has a body");
+    (status, serde_json::from_str(payload).unwrap())
+}
+
+/// Server running on its own thread, plus the users registered with it
+struct Harness {
+    addr: SocketAddr,
+    user_key: SigningKey,
+    approver_key: SigningKey,
+    _temp_dir: TempDir,
+}
+
+fn start() -> anyhow::Result<Harness> {
+    start_with_body_limit(http::MAX_BODY_BYTES)
+}
+
+fn start_with_body_limit(max_body_bytes: usize) -> anyhow::Result<Harness> {
+    let temp_dir = tempdir()?;
+    let db = Arc::new(sled::open(temp_dir.path().join("http.db"))?);
+
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let user_key = keys::generate_signing_key();
+    let approver_key = keys::generate_signing_key();
+    registry.register("user_1", user_key.verifying_key())?;
+    registry.register("user_2", approver_key.verifying_key())?;
+
+    let service = Arc::new(TradeService::new(Arc::new(SledStore::new(&db)?), registry));
+    let server = ApiServer::bind("127.0.0.1:0", service)?.with_max_body_bytes(max_body_bytes);
+    let addr = server.local_addr().expect("bound to an IP address");
+    std::thread::spawn(move || server.serve());
+
+    Ok(Harness {
+        addr,
+        user_key,
+        approver_key,
+        _temp_dir: temp_dir,
+    })
+}
+
+fn details() -> Value {
+    json!({
+        "trading_entity": "entity_1abc",
+        "counter_party": "counter_1xyz",
+        "direction": "Buy",
+        "notional_currency": "USD",
+        "notional_amount": 1_000_000,
+        "underlying_currency": "EUR",
+        "underlying_amount": 850_000,
+        "trade_date": "2025-06-02T00:00:00Z",
+        "value_date": "2025-06-04T00:00:00Z",
+        "delivery_date": "2025-06-04T00:00:00Z",
+    })
+}
+
+fn actor(user_id: &str) -> Value {
+    json!({ "user_id": user_id })
+}
+
+/// POST `body` to `path` as a client holding `key` does: prepare the witness, sign its
+/// payload, then send the body again with the signature
+fn signed_request(addr: SocketAddr, path: &str, body: &Value, key: &SigningKey) -> (u16, Value) {
+    let (status, prepared) = request(addr, "POST", &format!("{}/prepare", path), Some(body));
+    if status != 200 {
+        return (status, prepared);
+    }
+
+    let payload = hex::decode(prepared["signing_payload"].as_str().unwrap()).unwrap();
+    let mut body = body.clone();
+    body["timestamp"] = prepared["timestamp"].clone();
+    body["signature"] = json!(hex::encode(key.sign(&payload).to_bytes()));
+    if path == "/trades" {
+        body["trade_id"] = prepared["trade_id"].clone();
+    }
+    request(addr, "POST", path, Some(&body))
+}
+
+/// Submit a trade as user_1 for approval by user_2, returning its ID
+fn submit(harness: &Harness) -> String {
+    let mut body = actor("user_1");
+    body["details"] = details();
+    body["requester_id"] = json!("user_1");
+    body["approver_id"] = json!("user_2");
+
+    let (status, trade) = signed_request(harness.addr, "/trades", &body, &harness.user_key);
+    assert_eq!(status, 201, "{}", trade);
+    assert_eq!(trade["state"], "PendingApproval");
+    trade["trade_id"].as_str().unwrap().to_string()
+}
+
+/// Full happy path: submit, approve, read back the trade, history and details
+#[test]
+fn submit_approve_and_read_back() -> anyhow::Result<()> {
+    let harness = start()?;
+    let trade_id = submit(&harness);
+
+    let (status, trade) = signed_request(
+        harness.addr,
+        &format!("/trades/{}/approve", trade_id),
+        &actor("user_2"),
+        &harness.approver_key,
+    );
+    assert_eq!(status, 200, "{}", trade);
+    assert_eq!(trade["state"], "Approved");
+
+    let (status, trade) = request(harness.addr, "GET", &format!("/trades/{}", trade_id), None);
+    assert_eq!(status, 200);
+    assert_eq!(trade["witness_set"].as_array().unwrap().len(), 2);
+    assert_eq!(trade["witness_set"][1]["witness_type"]["type"], "Approve");
+
+    let (status, history) = request(
+        harness.addr,
+        "GET",
+        &format!("/trades/{}/history", trade_id),
+        None,
+    );
+    assert_eq!(status, 200);
+    let history = history["history"].as_array().unwrap();
+    assert_eq!(history[0]["state"], "PendingApproval");
+    assert_eq!(history[1]["action"], "Approve");
+    assert_eq!(history[1]["state"], "Approved");
+
+    let details_hash = trade["witness_set"][0]["witness_type"]["details_hash"]
+        .as_str()
+        .unwrap();
+    let (status, stored) = request(
+        harness.addr,
+        "GET",
+        &format!("/details/{}", details_hash),
+        None,
+    );
+    assert_eq!(status, 200);
+    assert_eq!(stored["notional_amount"], 1_000_000);
+    assert_eq!(stored["trade_date"], "2025-06-02T00:00:00+00:00");
+
+    Ok(())
+}
+
+/// Service errors come back as JSON with a matching status code
+#[test]
+fn errors_map_to_status_codes() -> anyhow::Result<()> {
+    let harness = start()?;
+    let trade_id = submit(&harness);
+
+    // Unknown trade
+    let (status, body) = request(harness.addr, "GET", "/trades/trade_missing", None);
+    assert_eq!(status, 404);
+    assert!(body["error"].is_string());
+
+    // Only the approver named on the Submit may approve
+    let (status, _) = signed_request(
+        harness.addr,
+        &format!("/trades/{}/approve", trade_id),
+        &actor("user_1"),
+        &harness.user_key,
+    );
+    assert_eq!(status, 403);
+
+    // Signing as user_2 with user_1's key fails signature verification
+    let (status, _) = signed_request(
+        harness.addr,
+        &format!("/trades/{}/approve", trade_id),
+        &actor("user_2"),
+        &harness.user_key,
+    );
+    assert_eq!(status, 401);
+
+    // Booking is not allowed from PendingApproval
+    let mut book = actor("user_1");
+    book["strike"] = json!(1_200);
+    let (status, _) = signed_request(
+        harness.addr,
+        &format!("/trades/{}/book", trade_id),
+        &book,
+        &harness.user_key,
+    );
+    assert_eq!(status, 409);
+
+    // Invalid details are rejected before anything is written
+    let mut update = actor("user_1");
+    update["details"] = details();
+    update["details"]["notional_amount"] = json!(0);
+    let (status, _) = signed_request(
+        harness.addr,
+        &format!("/trades/{}/update", trade_id),
+        &update,
+        &harness.user_key,
+    );
+    assert_eq!(status, 422);
+
+    // Malformed bodies and unknown routes
+    let (status, _) = request(harness.addr, "POST", "/trades", Some(&json!({})));
+    assert_eq!(status, 400);
+    let (status, _) = request(harness.addr, "GET", "/nowhere", None);
+    assert_eq!(status, 404);
+
+    // A damaged stored chain is the server's fault, not the client's
+    let broken = ServiceError::from(ValidationError::BrokenChain {
+        index: 1,
+        expected: None,
+        found: None,
+    });
+    assert_eq!(http::status_code(&broken), 500);
+    let out_of_order = ServiceError::from(ValidationError::TimestampRegression {
+        index: 1,
+        previous: chrono::Utc::now(),
+        found: chrono::Utc::now() - chrono::TimeDelta::minutes(1),
+    });
+    assert_eq!(http::status_code(&out_of_order), 500);
+
+    // A clock behind the chain head is refused, but nothing is damaged
+    let behind = ServiceError::ClockBehind {
+        trade_id: "trade_1".to_string(),
+        head: chrono::Utc::now(),
+        found: chrono::Utc::now() - chrono::TimeDelta::minutes(1),
+    };
+    assert_eq!(http::status_code(&behind), 409);
+
+    Ok(())
+}
+
+/// Bodies over the limit are refused without reaching the service
+#[test]
+fn oversized_bodies_are_refused() -> anyhow::Result<()> {
+    let harness = start_with_body_limit(256)?;
+
+    let mut body = actor("user_1");
+    body["details"] = details();
+    body["requester_id"] = json!("user_1");
+    body["approver_id"] = json!("user_2");
+    let (status, response) = request(harness.addr, "POST", "/trades/prepare", Some(&body));
+    assert_eq!(status, 413);
+    assert!(response["error"].as_str().unwrap().contains("256 bytes"));
+
+    // Small bodies still get through
+    let (status, _) = request(harness.addr, "POST", "/trades", Some(&json!({})));
+    assert_eq!(status, 400);
+
+    Ok(())
+}
+
+/// Secret keys are refused, preparing writes nothing, and a signature only covers the
+/// witness it was made over
+#[test]
+fn witnesses_are_signed_by_the_client() -> anyhow::Result<()> {
+    let harness = start()?;
+    let trade_id = submit(&harness);
+    let approve = format!("/trades/{}/approve", trade_id);
+    let witness_count = |addr| {
+        let (_, trade) = request(addr, "GET", &format!("/trades/{}", trade_id), None);
+        trade["witness_set"].as_array().unwrap().len()
+    };
+
+    // The old way of sending the secret key is refused outright
+    let mut with_key = actor("user_2");
+    with_key["signing_key"] = json!(hex::encode(harness.approver_key.to_bytes()));
+    let (status, body) = request(harness.addr, "POST", &approve, Some(&with_key));
+    assert_eq!(status, 400);
+    assert!(body["error"].as_str().unwrap().contains("secret keys"));
+    let (status, _) = request(
+        harness.addr,
+        "POST",
+        &format!("{}/prepare", approve),
+        Some(&with_key),
+    );
+    assert_eq!(status, 400);
+
+    // Preparing returns the witness and its payload without appending it
+    let mut body = actor("user_2");
+    body["note"] = json!({ "reason": "within limits" });
+    let (status, prepared) = request(
+        harness.addr,
+        "POST",
+        &format!("{}/prepare", approve),
+        Some(&body),
+    );
+    assert_eq!(status, 200, "{}", prepared);
+    assert_eq!(prepared["trade_id"], trade_id.as_str());
+    assert_eq!(prepared["witness"]["user_id"], "user_2");
+    assert_eq!(witness_count(harness.addr), 1);
+
+    // Without a signature nothing is appended either
+    let (status, _) = request(harness.addr, "POST", &approve, Some(&body));
+    assert_eq!(status, 400);
+
+    let payload = hex::decode(prepared["signing_payload"].as_str().unwrap())?;
+    let mut signed = body.clone();
+    signed["timestamp"] = prepared["timestamp"].clone();
+    signed["signature"] = json!(hex::encode(harness.approver_key.sign(&payload).to_bytes()));
+
+    // The signature does not carry over to a different note
+    let mut altered = signed.clone();
+    altered["note"] = json!({ "reason": "limits ignored" });
+    let (status, _) = request(harness.addr, "POST", &approve, Some(&altered));
+    assert_eq!(status, 401);
+    assert_eq!(witness_count(harness.addr), 1);
+
+    let (status, trade) = request(harness.addr, "POST", &approve, Some(&signed));
+    assert_eq!(status, 200, "{}", trade);
+    assert_eq!(trade["state"], "Approved");
+    assert_eq!(
+        trade["witness_set"][1]["note"]["reason"],
+        json!("within limits")
+    );
+
+    Ok(())
+}
@@ -3,7 +3,14 @@
 use anyhow::Context;
 use sled::open;
 use std::sync::Arc;
-use trade_approval::{context, service::TradeService, trade, utils};
+use trade_approval::{
+    context,
+    error::{ServiceError, ValidationError},
+    keys::{self, InMemoryKeyRegistry, KeyRegistry},
+    service::TradeService,
+    store::{InMemoryStore, SledStore, TradeStore},
+    trade, utils,
+};
 
 use tempfile::tempdir; // Use for test db cleanup.
 
@@ -20,12 +27,17 @@ fn submit_and_approve_trade() -> anyhow::Result<()> {
     // reset the db for each test run
     db.clear()?;
 
-    // create a new service instance
-    let service = TradeService::new(db);
+    // create a new service instance, witnesses are verified against the registry
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let service = TradeService::new(Arc::new(SledStore::new(&db)?), registry.clone());
 
     let requester_id = utils::new_uuid_to_bech32("user_")?;
     let approver_id = utils::new_uuid_to_bech32("user_")?;
     let user_id = utils::new_uuid_to_bech32("user_")?;
+    let approver_key = keys::generate_signing_key();
+    let user_key = keys::generate_signing_key();
+    registry.register(&approver_id, approver_key.verifying_key())?;
+    registry.register(&user_id, user_key.verifying_key())?;
     let trade_entity = utils::new_uuid_to_bech32("user_")?;
     let counter_party = utils::new_uuid_to_bech32("user_")?;
     // keeping it the same time for now
@@ -44,7 +56,13 @@ fn submit_and_approve_trade() -> anyhow::Result<()> {
         .set_value_date(timestamp);
 
     let ctx = service
-        .submit_trade(trade_details, requester_id, approver_id.clone(), user_id)
+        .submit_trade(
+            trade_details,
+            requester_id,
+            approver_id.clone(),
+            user_id,
+            &user_key,
+        )
         .context("Trade Failed on Submit: ")?;
 
     assert_eq!(ctx.current_state(), context::TradeState::PendingApproval);
@@ -52,7 +70,7 @@ fn submit_and_approve_trade() -> anyhow::Result<()> {
     // with our trade submitted we can move onto the next step, approval
 
     let ctx = service
-        .approve_trade(ctx.trade_id.clone(), approver_id)
+        .approve_trade(ctx.trade_id.clone(), approver_id, &approver_key)
         .context("Trade Failed on Approval: ")?;
 
     assert_eq!(ctx.current_state(), context::TradeState::Approved);
@@ -73,11 +91,16 @@ fn update_trade() -> anyhow::Result<()> {
     db.clear()?;
 
     // same as before
-    let service = TradeService::new(db);
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let service = TradeService::new(Arc::new(SledStore::new(&db)?), registry.clone());
 
     let requester_id = utils::new_uuid_to_bech32("user_")?;
     let approver_id = utils::new_uuid_to_bech32("user_")?;
     let user_id = utils::new_uuid_to_bech32("user_")?;
+    let approver_key = keys::generate_signing_key();
+    let user_key = keys::generate_signing_key();
+    registry.register(&approver_id, approver_key.verifying_key())?;
+    registry.register(&user_id, user_key.verifying_key())?;
     let trade_entity = utils::new_uuid_to_bech32("user_")?;
     let counter_party = utils::new_uuid_to_bech32("user_")?;
     // keeping it the same time for now
@@ -101,13 +124,14 @@ fn update_trade() -> anyhow::Result<()> {
             requester_id,
             approver_id.clone(),
             user_id.clone(),
+            &user_key,
         )
         .context("Trade Failed on Submit: ")?;
 
     assert_eq!(ctx.current_state(), context::TradeState::PendingApproval);
 
     let ctx = service
-        .approve_trade(ctx.trade_id.clone(), approver_id)
+        .approve_trade(ctx.trade_id.clone(), approver_id, &approver_key)
         .context("Trade Failed on Approval: ")?;
 
     assert_eq!(ctx.current_state(), context::TradeState::Approved);
@@ -124,7 +148,7 @@ fn update_trade() -> anyhow::Result<()> {
         .set_delivery_date(timestamp.clone())
         .set_value_date(timestamp);
 
-    let ctx = service.update_trade(ctx.trade_id, trade_details_update, user_id)?;
+    let ctx = service.update_trade(ctx.trade_id, trade_details_update, user_id, &user_key)?;
 
     assert_eq!(ctx.current_state(), context::TradeState::PendingApproval);
 
@@ -144,11 +168,16 @@ fn submit_execute_and_book() -> anyhow::Result<()> {
     // reset the db for each test run
     db.clear()?;
 
-    let service = TradeService::new(db);
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let service = TradeService::new(Arc::new(SledStore::new(&db)?), registry.clone());
 
     let requester_id = utils::new_uuid_to_bech32("user_")?;
     let approver_id = utils::new_uuid_to_bech32("user_")?;
     let user_id = utils::new_uuid_to_bech32("user_")?;
+    let approver_key = keys::generate_signing_key();
+    let user_key = keys::generate_signing_key();
+    registry.register(&approver_id, approver_key.verifying_key())?;
+    registry.register(&user_id, user_key.verifying_key())?;
     let trade_entity = utils::new_uuid_to_bech32("user_")?;
     let counter_party = utils::new_uuid_to_bech32("user_")?;
     let timestamp = trade::TimeStamp::new();
@@ -171,6 +200,7 @@ fn submit_execute_and_book() -> anyhow::Result<()> {
             requester_id,
             approver_id.clone(),
             user_id.clone(),
+            &user_key,
         )
         .context("Trade Failed on Submit: ")?;
 
@@ -179,16 +209,16 @@ fn submit_execute_and_book() -> anyhow::Result<()> {
     // with our trade submitted we can move onto the next step, approval
 
     let ctx = service
-        .approve_trade(ctx.trade_id.clone(), approver_id)
+        .approve_trade(ctx.trade_id.clone(), approver_id, &approver_key)
         .context("Trade Failed on Approval: ")?;
 
     assert_eq!(ctx.current_state(), context::TradeState::Approved);
 
-    let ctx = service.execute_trade(ctx.trade_id, user_id.clone())?;
+    let ctx = service.execute_trade(ctx.trade_id, user_id.clone(), &user_key)?;
 
     assert_eq!(ctx.current_state(), context::TradeState::SentToExecute);
 
-    let ctx = service.book_trade(ctx.trade_id, user_id, 1_000_040)?;
+    let ctx = service.book_trade(ctx.trade_id, user_id, 1_000_040, &user_key)?;
     assert_eq!(ctx.current_state(), context::TradeState::Booked);
 
     Ok(())
@@ -207,11 +237,16 @@ fn view_history() -> anyhow::Result<()> {
     // reset the db for each test run
     db.clear()?;
 
-    let service = TradeService::new(db);
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let service = TradeService::new(Arc::new(SledStore::new(&db)?), registry.clone());
 
     let requester_id = utils::new_uuid_to_bech32("user_")?;
     let approver_id = utils::new_uuid_to_bech32("user_")?;
     let user_id = utils::new_uuid_to_bech32("user_")?;
+    let approver_key = keys::generate_signing_key();
+    let user_key = keys::generate_signing_key();
+    registry.register(&approver_id, approver_key.verifying_key())?;
+    registry.register(&user_id, user_key.verifying_key())?;
     let trade_entity = utils::new_uuid_to_bech32("user_")?;
     let counter_party = utils::new_uuid_to_bech32("user_")?;
     let timestamp = trade::TimeStamp::new();
@@ -234,10 +269,11 @@ fn view_history() -> anyhow::Result<()> {
             requester_id,
             approver_id.clone(),
             user_id.clone(),
+            &user_key,
         )
         .context("Trade Failed on Submit: ")?;
     let ctx = service
-        .approve_trade(ctx.trade_id.clone(), approver_id.clone())
+        .approve_trade(ctx.trade_id.clone(), approver_id.clone(), &approver_key)
         .context("Trade Failed on Approval: ")?;
 
     let trade_details = trade::TradeDetails::new()
@@ -251,14 +287,1901 @@ fn view_history() -> anyhow::Result<()> {
         .set_trade_date(timestamp.clone())
         .set_delivery_date(timestamp.clone())
         .set_value_date(timestamp);
-    let ctx = service.update_trade(ctx.trade_id.clone(), trade_details, user_id.clone())?;
-    let ctx = service.approve_trade(ctx.trade_id.clone(), approver_id)?;
-    let ctx = service.execute_trade(ctx.trade_id, user_id.clone())?;
-    let ctx = service.book_trade(ctx.trade_id, user_id, 25_000)?;
+    let ctx = service.update_trade(
+        ctx.trade_id.clone(),
+        trade_details,
+        user_id.clone(),
+        &user_key,
+    )?;
+    let ctx = service.approve_trade(ctx.trade_id.clone(), approver_id, &approver_key)?;
+    let ctx = service.execute_trade(ctx.trade_id, user_id.clone(), &user_key)?;
+    let ctx = service.book_trade(ctx.trade_id, user_id, 25_000, &user_key)?;
 
     assert!(!ctx.witness_set.is_empty());
 
+    let store = SledStore::new(&db)?;
+    let timeline = ctx.timeline_with_changes(&store)?;
+    let steps: Vec<(&str, context::TradeState)> = timeline
+        .iter()
+        .map(|entry| (entry.action, entry.state_after.clone()))
+        .collect();
+    assert_eq!(
+        steps,
+        [
+            ("Submit", context::TradeState::PendingApproval),
+            ("Approve", context::TradeState::Approved),
+            ("Update", context::TradeState::PendingApproval),
+            ("Approve", context::TradeState::Approved),
+            ("SendToExecute", context::TradeState::SentToExecute),
+            ("Book", context::TradeState::Booked),
+        ]
+    );
+    assert!(
+        timeline[2]
+            .changes
+            .iter()
+            .any(|change| change.field == "notional_amount")
+    );
+    assert!(timeline[5].to_string().contains("strike: 25000"));
+
     ctx.view_history();
 
     Ok(())
 }
+
+#[test]
+fn approve_with_wrong_key_is_rejected() -> anyhow::Result<()> {
+    // Sled uses file-based locking to prevent concurrent access, so only one test
+    // can hold the lock at a time. As is good practice in testing create separate
+    // databases for each test. The db is created on temp for simplified cleanup.
+    let temp_dir = tempdir()?;
+    let db_path = temp_dir.path().join("approve_with_wrong_key.db");
+    let db = open(db_path)?;
+    let db = Arc::new(db);
+
+    // reset the db for each test run
+    db.clear()?;
+
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let service = TradeService::new(Arc::new(SledStore::new(&db)?), registry.clone());
+
+    let requester_id = utils::new_uuid_to_bech32("user_")?;
+    let approver_id = utils::new_uuid_to_bech32("user_")?;
+    let user_id = utils::new_uuid_to_bech32("user_")?;
+    let approver_key = keys::generate_signing_key();
+    let user_key = keys::generate_signing_key();
+    registry.register(&approver_id, approver_key.verifying_key())?;
+    registry.register(&user_id, user_key.verifying_key())?;
+    let trade_entity = utils::new_uuid_to_bech32("user_")?;
+    let counter_party = utils::new_uuid_to_bech32("user_")?;
+    let timestamp = trade::TimeStamp::new();
+
+    let trade_details = trade::TradeDetails::new()
+        .new_trade_entity(trade_entity.as_ref())
+        .new_counter_party(counter_party.as_ref())
+        .set_notional_currency(trade::Currency::USD)
+        .set_direction(trade::Direction::Buy)
+        .set_notional_amount(20_000)
+        .set_underlying_amount(15_000)
+        .set_underlying_currency(trade::Currency::GBP)
+        .set_trade_date(timestamp.clone())
+        .set_delivery_date(timestamp.clone())
+        .set_value_date(timestamp);
+
+    let ctx = service
+        .submit_trade(
+            trade_details,
+            requester_id,
+            approver_id.clone(),
+            user_id.clone(),
+            &user_key,
+        )
+        .context("Trade Failed on Submit: ")?;
+
+    // The submitter knows the approver's ID but can only sign with their own key
+    let forged = service.approve_trade(ctx.trade_id.clone(), approver_id.clone(), &user_key);
+    assert!(matches!(
+        forged,
+        Err(ServiceError::Validation(ValidationError::InvalidSignature(
+            _
+        )))
+    ));
+
+    // An unregistered user cannot sign witnesses at all
+    let stranger_id = utils::new_uuid_to_bech32("user_")?;
+    let stranger_key = keys::generate_signing_key();
+    let cancelled = service.cancel_trade(ctx.trade_id.clone(), stranger_id, &stranger_key);
+    assert!(matches!(
+        cancelled,
+        Err(ServiceError::Validation(ValidationError::UnknownSigner(_)))
+    ));
+
+    let ctx = service.approve_trade(ctx.trade_id, approver_id, &approver_key)?;
+    assert_eq!(ctx.current_state(), context::TradeState::Approved);
+
+    Ok(())
+}
+
+#[test]
+fn concurrent_writers_do_not_lose_witnesses() -> anyhow::Result<()> {
+    // Sled uses file-based locking to prevent concurrent access, so only one test
+    // can hold the lock at a time. As is good practice in testing create separate
+    // databases for each test. The db is created on temp for simplified cleanup.
+    let temp_dir = tempdir()?;
+    let db_path = temp_dir.path().join("concurrent_writers.db");
+    let db = open(db_path)?;
+    let db = Arc::new(db);
+
+    // reset the db for each test run
+    db.clear()?;
+
+    // retry generously so every writer eventually lands its witness
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let service = Arc::new(
+        TradeService::new(Arc::new(SledStore::new(&db)?), registry.clone()).with_retries(64),
+    );
+
+    let requester_id = utils::new_uuid_to_bech32("user_")?;
+    let approver_id = utils::new_uuid_to_bech32("user_")?;
+    let user_id = utils::new_uuid_to_bech32("user_")?;
+    let user_key = keys::generate_signing_key();
+    registry.register(&user_id, user_key.verifying_key())?;
+    let trade_entity = utils::new_uuid_to_bech32("user_")?;
+    let counter_party = utils::new_uuid_to_bech32("user_")?;
+    let timestamp = trade::TimeStamp::new();
+
+    let trade_details = trade::TradeDetails::new()
+        .new_trade_entity(trade_entity.as_ref())
+        .new_counter_party(counter_party.as_ref())
+        .set_notional_currency(trade::Currency::USD)
+        .set_direction(trade::Direction::Buy)
+        .set_notional_amount(20_000)
+        .set_underlying_amount(15_000)
+        .set_underlying_currency(trade::Currency::GBP)
+        .set_trade_date(timestamp.clone())
+        .set_delivery_date(timestamp.clone())
+        .set_value_date(timestamp.clone());
+
+    let ctx = service
+        .submit_trade(
+            trade_details,
+            requester_id,
+            approver_id,
+            user_id.clone(),
+            &user_key,
+        )
+        .context("Trade Failed on Submit: ")?;
+
+    // Update is accepted while PendingApproval and leaves the trade there, so every
+    // writer is allowed to append
+    let writers: u64 = 8;
+    let handles: Vec<_> = (0..writers)
+        .map(|n| {
+            let service = service.clone();
+            let trade_id = ctx.trade_id.clone();
+            let user_id = user_id.clone();
+            let user_key = user_key.clone();
+            let trade_details = trade::TradeDetails::new()
+                .new_trade_entity(trade_entity.as_ref())
+                .new_counter_party(counter_party.as_ref())
+                .set_notional_currency(trade::Currency::USD)
+                .set_direction(trade::Direction::Buy)
+                .set_notional_amount(20_000 + n)
+                .set_underlying_amount(15_000)
+                .set_underlying_currency(trade::Currency::GBP)
+                .set_trade_date(timestamp.clone())
+                .set_delivery_date(timestamp.clone())
+                .set_value_date(timestamp.clone());
+            std::thread::spawn(move || {
+                service.update_trade(trade_id, trade_details, user_id, &user_key)
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().expect("writer thread panicked")?;
+    }
+
+    // Without the compare-and-swap guard some of these would overwrite each other
+    let ctx = context::TradeContext::load_from_store(&SledStore::new(&db)?, &ctx.trade_id)?;
+    assert_eq!(ctx.witness_set.len() as u64, 1 + writers);
+    assert!(ctx.verify_chain().is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn book_requires_sent_to_execute() -> anyhow::Result<()> {
+    // Sled uses file-based locking to prevent concurrent access, so only one test
+    // can hold the lock at a time. As is good practice in testing create separate
+    // databases for each test. The db is created on temp for simplified cleanup.
+    let temp_dir = tempdir()?;
+    let db_path = temp_dir.path().join("book_requires_sent_to_execute.db");
+    let db = open(db_path)?;
+    let db = Arc::new(db);
+
+    // reset the db for each test run
+    db.clear()?;
+
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let service = TradeService::new(Arc::new(SledStore::new(&db)?), registry.clone());
+
+    let requester_id = utils::new_uuid_to_bech32("user_")?;
+    let approver_id = utils::new_uuid_to_bech32("user_")?;
+    let user_id = utils::new_uuid_to_bech32("user_")?;
+    let approver_key = keys::generate_signing_key();
+    let user_key = keys::generate_signing_key();
+    registry.register(&approver_id, approver_key.verifying_key())?;
+    registry.register(&user_id, user_key.verifying_key())?;
+    let trade_entity = utils::new_uuid_to_bech32("user_")?;
+    let counter_party = utils::new_uuid_to_bech32("user_")?;
+    let timestamp = trade::TimeStamp::new();
+
+    let trade_details = trade::TradeDetails::new()
+        .new_trade_entity(trade_entity.as_ref())
+        .new_counter_party(counter_party.as_ref())
+        .set_notional_currency(trade::Currency::USD)
+        .set_direction(trade::Direction::Buy)
+        .set_notional_amount(20_000)
+        .set_underlying_amount(15_000)
+        .set_underlying_currency(trade::Currency::GBP)
+        .set_trade_date(timestamp.clone())
+        .set_delivery_date(timestamp.clone())
+        .set_value_date(timestamp);
+
+    let ctx = service
+        .submit_trade(
+            trade_details,
+            requester_id,
+            approver_id.clone(),
+            user_id.clone(),
+            &user_key,
+        )
+        .context("Trade Failed on Submit: ")?;
+
+    // Neither a pending nor an approved trade can skip straight to booking
+    let booked = service.book_trade(ctx.trade_id.clone(), user_id.clone(), 1_000, &user_key);
+    assert!(matches!(
+        booked,
+        Err(ServiceError::InvalidTransition {
+            from: context::TradeState::PendingApproval,
+            action: "Book",
+            ..
+        })
+    ));
+
+    let ctx = service.approve_trade(ctx.trade_id, approver_id, &approver_key)?;
+    let booked = service.book_trade(ctx.trade_id.clone(), user_id.clone(), 1_000, &user_key);
+    assert!(matches!(
+        booked,
+        Err(ServiceError::InvalidTransition {
+            from: context::TradeState::Approved,
+            action: "Book",
+            ..
+        })
+    ));
+
+    let ctx = service.execute_trade(ctx.trade_id, user_id.clone(), &user_key)?;
+    let ctx = service.book_trade(ctx.trade_id, user_id, 1_000, &user_key)?;
+    assert_eq!(ctx.current_state(), context::TradeState::Booked);
+
+    Ok(())
+}
+
+#[test]
+fn service_errors_are_typed() -> anyhow::Result<()> {
+    // Sled uses file-based locking to prevent concurrent access, so only one test
+    // can hold the lock at a time. As is good practice in testing create separate
+    // databases for each test. The db is created on temp for simplified cleanup.
+    let temp_dir = tempdir()?;
+    let db_path = temp_dir.path().join("service_errors_are_typed.db");
+    let db = open(db_path)?;
+    let db = Arc::new(db);
+
+    // reset the db for each test run
+    db.clear()?;
+
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let service = TradeService::new(Arc::new(SledStore::new(&db)?), registry.clone());
+
+    let requester_id = utils::new_uuid_to_bech32("user_")?;
+    let approver_id = utils::new_uuid_to_bech32("user_")?;
+    let user_id = utils::new_uuid_to_bech32("user_")?;
+    let approver_key = keys::generate_signing_key();
+    let user_key = keys::generate_signing_key();
+    registry.register(&approver_id, approver_key.verifying_key())?;
+    registry.register(&user_id, user_key.verifying_key())?;
+    let trade_entity = utils::new_uuid_to_bech32("user_")?;
+    let counter_party = utils::new_uuid_to_bech32("user_")?;
+    let timestamp = trade::TimeStamp::new();
+
+    let trade_details = trade::TradeDetails::new()
+        .new_trade_entity(trade_entity.as_ref())
+        .new_counter_party(counter_party.as_ref())
+        .set_notional_currency(trade::Currency::USD)
+        .set_direction(trade::Direction::Buy)
+        .set_notional_amount(20_000)
+        .set_underlying_amount(15_000)
+        .set_underlying_currency(trade::Currency::GBP)
+        .set_trade_date(timestamp.clone())
+        .set_delivery_date(timestamp.clone())
+        .set_value_date(timestamp.clone());
+
+    // Unknown trade
+    let missing = utils::new_uuid_to_bech32("trade_")?;
+    let result = service.execute_trade(missing.clone(), user_id.clone(), &user_key);
+    assert!(matches!(result, Err(ServiceError::NotFound { trade_id }) if trade_id == missing));
+
+    // Details that fail validation
+    let invalid_details = trade::TradeDetails::new()
+        .new_trade_entity(trade_entity.as_ref())
+        .new_counter_party(counter_party.as_ref())
+        .set_notional_currency(trade::Currency::USD)
+        .set_notional_amount(20_000)
+        .set_underlying_amount(15_000)
+        .set_underlying_currency(trade::Currency::GBP)
+        .set_trade_date(timestamp.clone())
+        .set_delivery_date(timestamp.clone())
+        .set_value_date(timestamp);
+    let result = service.submit_trade(
+        invalid_details,
+        requester_id.clone(),
+        approver_id.clone(),
+        user_id.clone(),
+        &user_key,
+    );
+    assert!(matches!(result, Err(ServiceError::InvalidDetails(_))));
+
+    let ctx = service
+        .submit_trade(
+            trade_details,
+            requester_id,
+            approver_id.clone(),
+            user_id.clone(),
+            &user_key,
+        )
+        .context("Trade Failed on Submit: ")?;
+
+    // Someone other than the named approver
+    let result = service.approve_trade(ctx.trade_id.clone(), user_id.clone(), &user_key);
+    match result {
+        Err(ServiceError::UnauthorizedApprover { expected, got }) => {
+            assert_eq!(expected, approver_id);
+            assert_eq!(got, user_id);
+        }
+        other => panic!("expected UnauthorizedApprover, got {:?}", other.map(|_| ())),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn index_follows_state_and_approver() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let db_path = temp_dir.path().join("index_follows_state_and_approver.db");
+    let db = open(db_path)?;
+    let db = Arc::new(db);
+
+    // reset the db for each test run
+    db.clear()?;
+
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let service = TradeService::new(Arc::new(SledStore::new(&db)?), registry.clone());
+
+    let requester_id = utils::new_uuid_to_bech32("user_")?;
+    let approver_id = utils::new_uuid_to_bech32("user_")?;
+    let other_approver_id = utils::new_uuid_to_bech32("user_")?;
+    let approver_key = keys::generate_signing_key();
+    let requester_key = keys::generate_signing_key();
+    registry.register(&approver_id, approver_key.verifying_key())?;
+    registry.register(&requester_id, requester_key.verifying_key())?;
+    let timestamp = trade::TimeStamp::new();
+
+    let trade_details = || {
+        trade::TradeDetails::new()
+            .new_trade_entity("entity_")
+            .new_counter_party("counter_")
+            .set_notional_currency(trade::Currency::USD)
+            .set_direction(trade::Direction::Buy)
+            .set_notional_amount(20_000)
+            .set_underlying_amount(15_000)
+            .set_underlying_currency(trade::Currency::GBP)
+            .set_trade_date(timestamp.clone())
+            .set_delivery_date(timestamp.clone())
+            .set_value_date(timestamp.clone())
+    };
+
+    let first = service.submit_trade(
+        trade_details(),
+        requester_id.clone(),
+        approver_id.clone(),
+        requester_id.clone(),
+        &requester_key,
+    )?;
+    let second = service.submit_trade(
+        trade_details(),
+        requester_id.clone(),
+        approver_id.clone(),
+        requester_id.clone(),
+        &requester_key,
+    )?;
+    let third = service.submit_trade(
+        trade_details(),
+        requester_id.clone(),
+        other_approver_id.clone(),
+        requester_id.clone(),
+        &requester_key,
+    )?;
+
+    let ids = |trades: Vec<context::TradeContext>| {
+        let mut ids: Vec<String> = trades.into_iter().map(|ctx| ctx.trade_id).collect();
+        ids.sort();
+        ids
+    };
+    let mut expected = vec![first.trade_id.clone(), second.trade_id.clone()];
+    expected.sort();
+
+    assert_eq!(
+        ids(service.list_pending_for_approver(&approver_id)?),
+        expected
+    );
+    assert_eq!(
+        ids(service.list_pending_for_approver(&other_approver_id)?),
+        vec![third.trade_id.clone()]
+    );
+    assert_eq!(service.list_by_requester(&requester_id)?.len(), 3);
+
+    // Approving moves the trade between state entries and off the approver's queue
+    service.approve_trade(first.trade_id.clone(), approver_id.clone(), &approver_key)?;
+
+    assert_eq!(
+        ids(service.list_pending_for_approver(&approver_id)?),
+        vec![second.trade_id.clone()]
+    );
+    assert_eq!(
+        ids(service.list_by_state(&context::TradeState::Approved)?),
+        vec![first.trade_id.clone()]
+    );
+    assert_eq!(
+        service
+            .list_by_state(&context::TradeState::PendingApproval)?
+            .len(),
+        2
+    );
+
+    // A lost index is recovered from the stored contexts
+    db.open_tree(trade_approval::index::TREE)?.clear()?;
+    assert!(service.list_pending_for_approver(&approver_id)?.is_empty());
+    assert_eq!(service.rebuild_index()?, 3);
+    assert_eq!(
+        ids(service.list_pending_for_approver(&approver_id)?),
+        vec![second.trade_id]
+    );
+    assert_eq!(
+        ids(service.list_by_state(&context::TradeState::Approved)?),
+        vec![first.trade_id]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn migrate_flat_layout_into_trees() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let db_path = temp_dir.path().join("migrate_flat_layout_into_trees.db");
+    let db = open(db_path)?;
+    let db = Arc::new(db);
+
+    // reset the db for each test run
+    db.clear()?;
+
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let service = TradeService::new(Arc::new(SledStore::new(&db)?), registry.clone());
+
+    let requester_id = utils::new_uuid_to_bech32("user_")?;
+    let approver_id = utils::new_uuid_to_bech32("user_")?;
+    let requester_key = keys::generate_signing_key();
+    registry.register(&requester_id, requester_key.verifying_key())?;
+    let timestamp = trade::TimeStamp::new();
+
+    let (details_hash, details_cbor) = trade::TradeDetails::new()
+        .new_trade_entity("entity_")
+        .new_counter_party("counter_")
+        .set_notional_currency(trade::Currency::USD)
+        .set_direction(trade::Direction::Buy)
+        .set_notional_amount(20_000)
+        .set_underlying_amount(15_000)
+        .set_underlying_currency(trade::Currency::GBP)
+        .set_trade_date(timestamp.clone())
+        .set_delivery_date(timestamp.clone())
+        .set_value_date(timestamp.clone())
+        .validate_and_finalise()?;
+
+    let mut ctx = context::TradeContext::new();
+    let mut witness = context::Witness::new(
+        ctx.trade_id.clone(),
+        requester_id.clone(),
+        timestamp,
+        context::WitnessType::Submit {
+            details_hash: details_hash.clone(),
+            requester_id: requester_id.clone(),
+            approver_id: approver_id.clone(),
+            approval_policy: None,
+        },
+    );
+    witness.parent_hash = ctx.head_hash()?;
+    witness.sign(&requester_key)?;
+    ctx.insert_witness(witness)?;
+    let (_, context_cbor) = ctx.serialize_with_hash()?;
+
+    // Write both objects the way the flat layout did, into the default tree
+    db.insert(ctx.trade_id.as_bytes(), context_cbor)?;
+    db.insert(details_hash.as_bytes(), details_cbor)?;
+    db.insert("unrelated", "left alone")?;
+    assert!(matches!(
+        service.get_trade(&ctx.trade_id),
+        Err(ServiceError::NotFound { .. })
+    ));
+
+    let report = trade_approval::migrate::migrate_flat_layout(&db, chrono::TimeDelta::zero())?;
+    assert_eq!(report.contexts, 1);
+    assert_eq!(report.details, 1);
+
+    assert!(db.get(ctx.trade_id.as_bytes())?.is_none());
+    assert!(db.get(details_hash.as_bytes())?.is_none());
+    assert!(db.get("unrelated")?.is_some());
+
+    let migrated = service.get_trade(&ctx.trade_id)?;
+    assert_eq!(migrated.witness_set, ctx.witness_set);
+    assert!(service.get_trade_details(&details_hash).is_ok());
+    assert_eq!(service.list_pending_for_approver(&approver_id)?.len(), 1);
+
+    // Nothing left to move the second time round
+    let report = trade_approval::migrate::migrate_flat_layout(&db, chrono::TimeDelta::zero())?;
+    assert_eq!(report, trade_approval::migrate::MigrationReport::default());
+
+    Ok(())
+}
+
+/// A witness chain as the original release encoded it, before witnesses carried
+/// parent hashes or signatures and when Approve had no fields
+#[derive(minicbor::Encode)]
+struct BaselineContext {
+    #[n(0)]
+    trade_id: String,
+    #[n(1)]
+    witness_set: Vec<BaselineWitness>,
+}
+
+#[derive(minicbor::Encode)]
+struct BaselineWitness {
+    #[n(0)]
+    trade_id: String,
+    #[n(1)]
+    user_id: String,
+    #[n(2)]
+    user_timestamp: trade::TimeStamp<chrono::Utc>,
+    #[n(3)]
+    witness_type: BaselineWitnessType,
+}
+
+#[derive(minicbor::Encode)]
+enum BaselineWitnessType {
+    #[n(0)]
+    Submit {
+        #[n(0)]
+        details_hash: String,
+        #[n(1)]
+        requester_id: String,
+        #[n(2)]
+        approver_id: String,
+    },
+    #[n(1)]
+    Approve,
+    #[n(3)]
+    Update {
+        #[n(0)]
+        details_hash: String,
+    },
+}
+
+#[test]
+fn migrate_sets_unsigned_legacy_chains_aside() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let db = Arc::new(open(temp_dir.path().join("migrate_legacy_chains.db"))?);
+    let service = TradeService::new(
+        Arc::new(SledStore::new(&db)?),
+        Arc::new(InMemoryKeyRegistry::new()),
+    );
+
+    let (details_hash, details_cbor) = trade::TradeDetails::new()
+        .new_trade_entity("entity_")
+        .new_counter_party("counter_")
+        .set_notional_currency(trade::Currency::USD)
+        .set_direction(trade::Direction::Buy)
+        .set_notional_amount(20_000)
+        .set_underlying_amount(15_000)
+        .set_underlying_currency(trade::Currency::GBP)
+        .set_trade_date(trade::TimeStamp::new_with(2025, 6, 2, 0, 0, 0))
+        .set_delivery_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+        .set_value_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+        .validate_and_finalise()?;
+
+    // Submit, Approve and Update, encoded the way the original release wrote them
+    let trade_id = utils::new_uuid_to_bech32(context::TRADE_ID_HRP)?;
+    let witness = |user_id: &str, hour, witness_type| BaselineWitness {
+        trade_id: trade_id.clone(),
+        user_id: user_id.to_string(),
+        user_timestamp: trade::TimeStamp::new_with(2025, 6, 2, hour, 0, 0),
+        witness_type,
+    };
+    let legacy = BaselineContext {
+        trade_id: trade_id.clone(),
+        witness_set: vec![
+            witness(
+                "user_requester",
+                9,
+                BaselineWitnessType::Submit {
+                    details_hash: details_hash.clone(),
+                    requester_id: "user_requester".to_string(),
+                    approver_id: "user_approver".to_string(),
+                },
+            ),
+            witness("user_approver", 10, BaselineWitnessType::Approve),
+            witness(
+                "user_requester",
+                11,
+                BaselineWitnessType::Update {
+                    details_hash: details_hash.clone(),
+                },
+            ),
+        ],
+    };
+    db.insert(trade_id.as_bytes(), minicbor::to_vec(&legacy)?)?;
+    db.insert(details_hash.as_bytes(), details_cbor)?;
+
+    // The chain cannot be signed after the fact, so it is set aside rather than
+    // failing the migration or every later load
+    let report = trade_approval::migrate::migrate_flat_layout(&db, chrono::TimeDelta::zero())?;
+    assert_eq!(report.contexts, 0);
+    assert_eq!(report.details, 1);
+    assert_eq!(report.quarantined, vec![trade_id.clone()]);
+
+    assert!(db.get(trade_id.as_bytes())?.is_none());
+    assert!(matches!(
+        service.get_trade(&trade_id),
+        Err(ServiceError::NotFound { .. })
+    ));
+    assert!(service.get_trade_details(&details_hash).is_ok());
+
+    // Set aside with its parent hashes filled in, so the links verify on decode
+    let bytes = db
+        .open_tree(trade_approval::migrate::LEGACY_TREE)?
+        .get(trade_id.as_bytes())?
+        .context("legacy context is kept")?;
+    let relinked = context::TradeContext::from_cbor(&bytes)?;
+    assert_eq!(relinked.witness_set.len(), 3);
+    assert_eq!(
+        relinked.witness_set[1].parent_hash,
+        Some(relinked.witness_set[0].serialize_with_hash()?.0)
+    );
+    assert_eq!(
+        relinked.current_state(),
+        context::TradeState::PendingApproval
+    );
+
+    let report = trade_approval::migrate::migrate_flat_layout(&db, chrono::TimeDelta::zero())?;
+    assert_eq!(report, trade_approval::migrate::MigrationReport::default());
+
+    Ok(())
+}
+
+#[test]
+fn in_memory_store_runs_the_workflow() -> anyhow::Result<()> {
+    // No tempdir needed, the whole store lives in memory
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let service = Arc::new(
+        TradeService::new(Arc::new(InMemoryStore::new()), registry.clone()).with_retries(64),
+    );
+
+    let requester_id = utils::new_uuid_to_bech32("user_")?;
+    let approver_id = utils::new_uuid_to_bech32("user_")?;
+    let requester_key = keys::generate_signing_key();
+    let approver_key = keys::generate_signing_key();
+    registry.register(&requester_id, requester_key.verifying_key())?;
+    registry.register(&approver_id, approver_key.verifying_key())?;
+    let timestamp = trade::TimeStamp::new();
+
+    let trade_details = |amount: u64| {
+        trade::TradeDetails::new()
+            .new_trade_entity("entity_")
+            .new_counter_party("counter_")
+            .set_notional_currency(trade::Currency::USD)
+            .set_direction(trade::Direction::Buy)
+            .set_notional_amount(amount)
+            .set_underlying_amount(15_000)
+            .set_underlying_currency(trade::Currency::GBP)
+            .set_trade_date(timestamp.clone())
+            .set_delivery_date(timestamp.clone())
+            .set_value_date(timestamp.clone())
+    };
+
+    let ctx = service.submit_trade(
+        trade_details(20_000),
+        requester_id.clone(),
+        approver_id.clone(),
+        requester_id.clone(),
+        &requester_key,
+    )?;
+
+    // Concurrent writers are serialised by the in-memory compare-and-swap too
+    let writers = 4;
+    let handles: Vec<_> = (0..writers)
+        .map(|n| {
+            let service = service.clone();
+            let trade_id = ctx.trade_id.clone();
+            let requester_id = requester_id.clone();
+            let requester_key = requester_key.clone();
+            let details = trade_details(30_000 + n);
+            std::thread::spawn(move || {
+                service.update_trade(trade_id, details, requester_id, &requester_key)
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().expect("writer thread panicked")?;
+    }
+
+    let ctx = service.approve_trade(ctx.trade_id, approver_id.clone(), &approver_key)?;
+    let ctx = service.execute_trade(ctx.trade_id, requester_id.clone(), &requester_key)?;
+    let ctx = service.book_trade(ctx.trade_id, requester_id, 1_000, &requester_key)?;
+
+    assert_eq!(ctx.current_state(), context::TradeState::Booked);
+    assert_eq!(ctx.witness_set.len() as u64, 4 + writers);
+    assert_eq!(
+        service.list_by_state(&context::TradeState::Booked)?.len(),
+        1
+    );
+    assert!(service.list_pending_for_approver(&approver_id)?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn details_are_verified_against_their_hash() -> anyhow::Result<()> {
+    let store = Arc::new(InMemoryStore::new());
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let service = TradeService::new(store.clone(), registry.clone());
+
+    let requester_id = utils::new_uuid_to_bech32("user_")?;
+    let approver_id = utils::new_uuid_to_bech32("user_")?;
+    let requester_key = keys::generate_signing_key();
+    registry.register(&requester_id, requester_key.verifying_key())?;
+
+    let trade_details = |amount: u64| {
+        trade::TradeDetails::new()
+            .set_trade_entity("entity_1abc")
+            .set_counter_party("counter_1xyz")
+            .set_notional_currency(trade::Currency::USD)
+            .set_direction(trade::Direction::Buy)
+            .set_notional_amount(amount)
+            .set_underlying_amount(15_000)
+            .set_underlying_currency(trade::Currency::GBP)
+            .set_trade_date(trade::TimeStamp::new_with(2025, 6, 2, 0, 0, 0))
+            .set_value_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+            .set_delivery_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+    };
+
+    let ctx = service.submit_trade(
+        trade_details(20_000),
+        requester_id.clone(),
+        approver_id,
+        requester_id.clone(),
+        &requester_key,
+    )?;
+    let original_hash = ctx.current_details_hash()?;
+
+    // The latest Update decides which details are in force
+    let ctx = service.update_trade(
+        ctx.trade_id,
+        trade_details(25_000),
+        requester_id,
+        &requester_key,
+    )?;
+    assert_ne!(ctx.current_details_hash()?, original_hash);
+    assert_eq!(ctx.current_details(store.as_ref())?, trade_details(25_000));
+    assert_eq!(
+        service.get_current_details(&ctx.trade_id)?,
+        trade_details(25_000)
+    );
+    assert_eq!(
+        service.get_trade_details(&original_hash)?,
+        trade_details(20_000)
+    );
+
+    // Swap the bytes behind the original hash for some other valid details
+    let (_, other_cbor) = trade_details(99_000).validate_and_finalise()?;
+    store.put_details(&original_hash, &other_cbor)?;
+
+    let result = service.get_trade_details(&original_hash);
+    assert!(matches!(
+        result,
+        Err(ServiceError::Validation(ValidationError::ContentHashMismatch { ref key, .. }))
+            if *key == original_hash
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn diff_versions_reports_changed_fields() -> anyhow::Result<()> {
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let service = TradeService::new(Arc::new(InMemoryStore::new()), registry.clone());
+
+    let requester_id = utils::new_uuid_to_bech32("user_")?;
+    let approver_id = utils::new_uuid_to_bech32("user_")?;
+    let requester_key = keys::generate_signing_key();
+    let approver_key = keys::generate_signing_key();
+    registry.register(&requester_id, requester_key.verifying_key())?;
+    registry.register(&approver_id, approver_key.verifying_key())?;
+
+    let trade_details = || {
+        trade::TradeDetails::new()
+            .set_trade_entity("entity_1abc")
+            .set_counter_party("counter_1xyz")
+            .set_notional_currency(trade::Currency::USD)
+            .set_direction(trade::Direction::Buy)
+            .set_notional_amount(20_000)
+            .set_underlying_amount(15_000)
+            .set_underlying_currency(trade::Currency::GBP)
+            .set_trade_date(trade::TimeStamp::new_with(2025, 6, 2, 0, 0, 0))
+            .set_value_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+            .set_delivery_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+    };
+
+    // 0: Submit, 1: Approve, 2: Update (amount), 3: Update (value date)
+    let ctx = service.submit_trade(
+        trade_details(),
+        requester_id.clone(),
+        approver_id.clone(),
+        requester_id.clone(),
+        &requester_key,
+    )?;
+    let ctx = service.approve_trade(ctx.trade_id, approver_id, &approver_key)?;
+    let ctx = service.update_trade(
+        ctx.trade_id,
+        trade_details().set_notional_amount(25_000),
+        requester_id.clone(),
+        &requester_key,
+    )?;
+    let ctx = service.update_trade(
+        ctx.trade_id,
+        trade_details()
+            .set_notional_amount(25_000)
+            .set_value_date(trade::TimeStamp::new_with(2025, 6, 3, 0, 0, 0)),
+        requester_id,
+        &requester_key,
+    )?;
+
+    // An Approve carries no details, so nothing changed across it
+    assert!(service.diff_versions(&ctx.trade_id, 0, 1)?.is_empty());
+
+    let changes = service.diff_versions(&ctx.trade_id, 1, 2)?;
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].field, "notional_amount");
+    assert_eq!(changes[0].old.as_deref(), Some("20000"));
+    assert_eq!(changes[0].new.as_deref(), Some("25000"));
+
+    let fields: Vec<_> = service
+        .diff_versions(&ctx.trade_id, 0, 3)?
+        .into_iter()
+        .map(|change| change.field)
+        .collect();
+    assert_eq!(fields, vec!["notional_amount", "value_date"]);
+
+    assert!(matches!(
+        service.diff_versions(&ctx.trade_id, 0, 4),
+        Err(ServiceError::Validation(ValidationError::NoSuchWitness {
+            index: 4,
+            len: 4
+        }))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn quorum_policy_needs_two_of_three_approvers() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let db = Arc::new(open(temp_dir.path().join("quorum_policy.db"))?);
+
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let service = TradeService::new(Arc::new(SledStore::new(&db)?), registry.clone());
+
+    let requester_id = utils::new_uuid_to_bech32("user_")?;
+    let requester_key = keys::generate_signing_key();
+    registry.register(&requester_id, requester_key.verifying_key())?;
+
+    let mut approvers = Vec::new();
+    for _ in 0..3 {
+        let approver_id = utils::new_uuid_to_bech32("user_")?;
+        let approver_key = keys::generate_signing_key();
+        registry.register(&approver_id, approver_key.verifying_key())?;
+        approvers.push((approver_id, approver_key));
+    }
+    let ids: Vec<String> = approvers.iter().map(|(id, _)| id.clone()).collect();
+
+    let trade_details = || {
+        trade::TradeDetails::new()
+            .set_trade_entity("entity_1abc")
+            .set_counter_party("counter_1xyz")
+            .set_notional_currency(trade::Currency::USD)
+            .set_direction(trade::Direction::Buy)
+            .set_notional_amount(5_000_000)
+            .set_underlying_amount(4_000_000)
+            .set_underlying_currency(trade::Currency::GBP)
+            .set_trade_date(trade::TimeStamp::new_with(2025, 6, 2, 0, 0, 0))
+            .set_value_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+            .set_delivery_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+    };
+
+    // A policy that can never be met is refused before anything is stored
+    let impossible = context::ApprovalPolicy::quorum(ids.clone(), 4);
+    assert!(matches!(
+        service.submit_trade_with_policy(
+            trade_details(),
+            requester_id.clone(),
+            impossible,
+            requester_id.clone(),
+            &requester_key,
+        ),
+        Err(ServiceError::Validation(
+            ValidationError::InvalidApprovalPolicy(_)
+        ))
+    ));
+
+    let ctx = service.submit_trade_with_policy(
+        trade_details(),
+        requester_id.clone(),
+        context::ApprovalPolicy::quorum(ids.clone(), 2),
+        requester_id.clone(),
+        &requester_key,
+    )?;
+    let trade_id = ctx.trade_id.clone();
+
+    // Every named approver sees the trade as waiting on them
+    for id in &ids {
+        assert_eq!(service.list_pending_for_approver(id)?.len(), 1);
+    }
+
+    // The requester is not on the policy
+    assert!(matches!(
+        service.approve_trade(trade_id.clone(), requester_id.clone(), &requester_key),
+        Err(ServiceError::UnauthorizedApprover { .. })
+    ));
+
+    let ctx = service.approve_trade(trade_id.clone(), ids[0].clone(), &approvers[0].1)?;
+    assert_eq!(ctx.current_state(), context::TradeState::PendingApproval);
+    assert!(service.list_pending_for_approver(&ids[0])?.is_empty());
+    assert_eq!(service.list_pending_for_approver(&ids[2])?.len(), 1);
+
+    // The same approver cannot make up the quorum alone
+    assert!(matches!(
+        service.approve_trade(trade_id.clone(), ids[0].clone(), &approvers[0].1),
+        Err(ServiceError::AlreadyApproved { .. })
+    ));
+
+    let ctx = service.approve_trade(trade_id.clone(), ids[2].clone(), &approvers[2].1)?;
+    assert_eq!(ctx.current_state(), context::TradeState::Approved);
+    assert_eq!(
+        ctx.approval_progress()?.approved_by,
+        vec![ids[0].clone(), ids[2].clone()]
+    );
+    assert!(service.list_pending_for_approver(&ids[1])?.is_empty());
+
+    // The policy survives a reload from the store
+    let reloaded = service.get_trade(&trade_id)?;
+    assert_eq!(reloaded.current_state(), context::TradeState::Approved);
+
+    Ok(())
+}
+
+#[cfg(feature = "config")]
+#[test]
+fn submissions_are_routed_by_configured_rules() -> anyhow::Result<()> {
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let requester_key = keys::generate_signing_key();
+    registry.register("user_requester", requester_key.verifying_key())?;
+
+    let config = trade_approval::config::ServiceConfig::from_toml(
+        r#"
+        [[rules]]
+        name = "large"
+        min_notional = 1000000
+        levels = [{ approvers = ["user_a", "user_b", "user_c"], required = 2 }]
+
+        [[rules]]
+        name = "small GBP"
+        notional_currency = "GBP"
+        levels = [{ approvers = ["user_desk"], required = 1 }]
+        "#,
+    )?;
+    let service =
+        TradeService::new(Arc::new(InMemoryStore::new()), registry.clone()).with_config(config)?;
+
+    let trade_details = |currency, amount| {
+        trade::TradeDetails::new()
+            .set_trade_entity("entity_1abc")
+            .set_counter_party("counter_1xyz")
+            .set_notional_currency(currency)
+            .set_direction(trade::Direction::Buy)
+            .set_notional_amount(amount)
+            .set_underlying_amount(15_000)
+            .set_underlying_currency(trade::Currency::EUR)
+            .set_trade_date(trade::TimeStamp::new_with(2025, 6, 2, 0, 0, 0))
+            .set_value_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+            .set_delivery_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+    };
+    let submit = |currency, amount| {
+        service.submit_routed_trade(
+            trade_details(currency, amount),
+            "user_requester".to_string(),
+            "user_requester".to_string(),
+            &requester_key,
+        )
+    };
+
+    let large = submit(trade::Currency::USD, 2_000_000)?;
+    let policy = large.approval_policy()?;
+    assert_eq!(policy.required(), 2);
+    assert_eq!(
+        policy.approvers().collect::<Vec<_>>(),
+        vec!["user_a", "user_b", "user_c"]
+    );
+
+    // A single approver is recorded the same way `submit_trade` records it
+    let small = submit(trade::Currency::GBP, 20_000)?;
+    assert_eq!(small.get_expected_approver()?, "user_desk");
+    assert!(matches!(
+        small.witness_set[0].witness_type,
+        context::WitnessType::Submit {
+            approval_policy: None,
+            ..
+        }
+    ));
+
+    assert!(matches!(
+        submit(trade::Currency::USD, 20_000),
+        Err(ServiceError::NoMatchingRule)
+    ));
+
+    Ok(())
+}
+
+#[cfg(feature = "config")]
+#[test]
+fn notional_limits_cap_counterparty_exposure() -> anyhow::Result<()> {
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let requester_key = keys::generate_signing_key();
+    let approver_key = keys::generate_signing_key();
+    registry.register("user_requester", requester_key.verifying_key())?;
+    registry.register("user_approver", approver_key.verifying_key())?;
+
+    let config = trade_approval::config::ServiceConfig::from_toml(
+        r#"
+        [[limits]]
+        counter_party = "counter_1xyz"
+        currency = "USD"
+        cap = 1000000
+
+        [[limits]]
+        trading_entity = "entity_1abc"
+        currency = "GBP"
+        cap = 100
+        "#,
+    )?;
+    let service =
+        TradeService::new(Arc::new(InMemoryStore::new()), registry.clone()).with_config(config)?;
+
+    let trade_details = |amount| {
+        trade::TradeDetails::new()
+            .set_trade_entity("entity_1abc")
+            .set_counter_party("counter_1xyz")
+            .set_notional_currency(trade::Currency::USD)
+            .set_direction(trade::Direction::Buy)
+            .set_notional_amount(amount)
+            .set_underlying_amount(15_000)
+            .set_underlying_currency(trade::Currency::EUR)
+            .set_trade_date(trade::TimeStamp::new_with(2025, 6, 2, 0, 0, 0))
+            .set_value_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+            .set_delivery_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+    };
+    let submit = |amount| {
+        service.submit_trade(
+            trade_details(amount),
+            "user_requester".to_string(),
+            "user_approver".to_string(),
+            "user_requester".to_string(),
+            &requester_key,
+        )
+    };
+    let approve = |trade_id: &str| {
+        service.approve_trade(
+            trade_id.to_string(),
+            "user_approver".to_string(),
+            &approver_key,
+        )
+    };
+    let breach = |result: Result<context::TradeContext, ServiceError>| match result {
+        Err(ServiceError::LimitBreach {
+            exposure,
+            requested,
+            headroom,
+            ..
+        }) => (exposure, requested, headroom),
+        other => panic!("expected a limit breach, got {:?}", other.map(|_| ())),
+    };
+
+    // Pending trades do not count towards exposure until they are approved
+    let first = submit(600_000)?;
+    approve(&first.trade_id)?;
+    assert_eq!(breach(submit(500_000)), (600_000, 500_000, 400_000));
+
+    let second = submit(300_000)?;
+    approve(&second.trade_id)?;
+    let scope = trade_approval::limits::LimitScope::CounterParty("counter_1xyz".to_string());
+    assert_eq!(service.exposure(&scope, trade::Currency::USD)?, 900_000);
+
+    // An update replaces what the trade counted for instead of adding to it
+    let update = |amount| {
+        service.update_trade(
+            second.trade_id.clone(),
+            trade_details(amount),
+            "user_requester".to_string(),
+            &requester_key,
+        )
+    };
+    assert_eq!(breach(update(450_000)), (600_000, 450_000, 400_000));
+    update(400_000)?;
+    assert_eq!(service.exposure(&scope, trade::Currency::USD)?, 600_000);
+
+    // Approval re-checks against whatever was approved in the meantime
+    let third = submit(100_000)?;
+    approve(&third.trade_id)?;
+    assert_eq!(
+        breach(approve(&second.trade_id)),
+        (700_000, 400_000, 300_000)
+    );
+
+    assert_eq!(
+        service.get_trade(&second.trade_id)?.current_state(),
+        context::TradeState::PendingApproval
+    );
+
+    // A trade that can no longer be updated is refused as such, not as a breach
+    service.cancel_trade(
+        third.trade_id.clone(),
+        "user_requester".to_string(),
+        &requester_key,
+    )?;
+    assert!(matches!(
+        service.update_trade(
+            third.trade_id.clone(),
+            trade_details(2_000_000),
+            "user_requester".to_string(),
+            &requester_key,
+        ),
+        Err(ServiceError::InvalidTransition {
+            from: context::TradeState::Cancelled,
+            ..
+        })
+    ));
+
+    // Limits are per currency: only the GBP limit on the trading entity applies here
+    let sterling = service.submit_trade(
+        trade_details(200).set_notional_currency(trade::Currency::GBP),
+        "user_requester".to_string(),
+        "user_approver".to_string(),
+        "user_requester".to_string(),
+        &requester_key,
+    );
+    assert_eq!(breach(sterling), (0, 200, 100));
+
+    Ok(())
+}
+
+#[test]
+fn delegates_approve_on_behalf_of_absent_approvers() -> anyhow::Result<()> {
+    use trade_approval::delegation::{Delegation, ExpectedApprover};
+
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let requester_key = keys::generate_signing_key();
+    let deputy_key = keys::generate_signing_key();
+    registry.register("user_requester", requester_key.verifying_key())?;
+    registry.register("user_deputy", deputy_key.verifying_key())?;
+    let service = TradeService::new(Arc::new(InMemoryStore::new()), registry.clone());
+
+    let days_from_now =
+        |days| trade::TimeStamp::from(chrono::Utc::now() + chrono::Duration::days(days));
+    service.delegate_approvals(
+        Delegation::new(
+            "user_approver",
+            "user_deputy",
+            days_from_now(-1),
+            days_from_now(1),
+        )
+        .with_max_notional(1_000_000),
+    )?;
+
+    let submit = |amount| {
+        service.submit_trade(
+            trade::TradeDetails::new()
+                .set_trade_entity("entity_1abc")
+                .set_counter_party("counter_1xyz")
+                .set_notional_currency(trade::Currency::USD)
+                .set_direction(trade::Direction::Buy)
+                .set_notional_amount(amount)
+                .set_underlying_amount(15_000)
+                .set_underlying_currency(trade::Currency::EUR)
+                .set_trade_date(trade::TimeStamp::new_with(2025, 6, 2, 0, 0, 0))
+                .set_value_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+                .set_delivery_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0)),
+            "user_requester".to_string(),
+            "user_approver".to_string(),
+            "user_requester".to_string(),
+            &requester_key,
+        )
+    };
+    let deputy_approves = |trade_id: &str| {
+        service.approve_trade(trade_id.to_string(), "user_deputy".to_string(), &deputy_key)
+    };
+
+    let small = submit(500_000)?;
+    assert_eq!(
+        service.get_expected_approvers(&small.trade_id)?,
+        vec![ExpectedApprover {
+            approver_id: "user_approver".to_string(),
+            delegates: vec!["user_deputy".to_string()],
+        }]
+    );
+
+    // The deputy signs the Approve, and it records who they approved for
+    let approved = deputy_approves(&small.trade_id)?;
+    assert_eq!(approved.current_state(), context::TradeState::Approved);
+    let witness = approved.witness_set.last().unwrap();
+    assert_eq!(witness.user_id, "user_deputy");
+    assert_eq!(
+        witness.witness_type,
+        context::WitnessType::Approve {
+            on_behalf_of: Some("user_approver".to_string())
+        }
+    );
+    assert!(service.get_expected_approvers(&small.trade_id)?.is_empty());
+
+    // Above the ceiling the delegation does not apply
+    let large = submit(2_000_000)?;
+    assert_eq!(
+        service.get_expected_approvers(&large.trade_id)?[0].delegates,
+        Vec::<String>::new()
+    );
+    assert!(matches!(
+        deputy_approves(&large.trade_id),
+        Err(ServiceError::UnauthorizedApprover { .. })
+    ));
+
+    // Nor once it has expired or been revoked
+    service.delegate_approvals(Delegation::new(
+        "user_approver",
+        "user_deputy",
+        days_from_now(-3),
+        days_from_now(-2),
+    ))?;
+    let later = submit(500_000)?;
+    assert!(matches!(
+        deputy_approves(&later.trade_id),
+        Err(ServiceError::UnauthorizedApprover { .. })
+    ));
+    assert!(service.revoke_delegation("user_approver", "user_deputy")?);
+    assert!(!service.revoke_delegation("user_approver", "user_deputy")?);
+
+    assert!(matches!(
+        service.delegate_approvals(Delegation::new(
+            "user_approver",
+            "user_approver",
+            days_from_now(0),
+            days_from_now(1),
+        )),
+        Err(ServiceError::Validation(
+            ValidationError::InvalidDelegation(_)
+        ))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn delegates_cannot_cast_a_second_vote() -> anyhow::Result<()> {
+    use trade_approval::delegation::Delegation;
+
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let requester_key = keys::generate_signing_key();
+    let a_key = keys::generate_signing_key();
+    let deputy_key = keys::generate_signing_key();
+    registry.register("user_requester", requester_key.verifying_key())?;
+    registry.register("user_a", a_key.verifying_key())?;
+    registry.register("user_deputy", deputy_key.verifying_key())?;
+    let service = TradeService::new(Arc::new(InMemoryStore::new()), registry.clone());
+
+    let days_from_now =
+        |days| trade::TimeStamp::from(chrono::Utc::now() + chrono::Duration::days(days));
+    for (delegator, delegate) in [
+        ("user_b", "user_a"),
+        ("user_b", "user_deputy"),
+        ("user_c", "user_deputy"),
+    ] {
+        service.delegate_approvals(Delegation::new(
+            delegator,
+            delegate,
+            days_from_now(-1),
+            days_from_now(1),
+        ))?;
+    }
+
+    let submit = || {
+        service.submit_trade_with_policy(
+            trade::TradeDetails::new()
+                .set_trade_entity("entity_1abc")
+                .set_counter_party("counter_1xyz")
+                .set_notional_currency(trade::Currency::USD)
+                .set_direction(trade::Direction::Buy)
+                .set_notional_amount(500_000)
+                .set_underlying_amount(15_000)
+                .set_underlying_currency(trade::Currency::EUR)
+                .set_trade_date(trade::TimeStamp::new_with(2025, 6, 2, 0, 0, 0))
+                .set_value_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+                .set_delivery_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0)),
+            "user_requester".to_string(),
+            context::ApprovalPolicy::quorum(
+                vec![
+                    "user_a".to_string(),
+                    "user_b".to_string(),
+                    "user_c".to_string(),
+                ],
+                2,
+            ),
+            "user_requester".to_string(),
+            &requester_key,
+        )
+    };
+
+    // A approves in their own right, then cannot approve again as B's delegate
+    let trade_id = submit()?.trade_id;
+    service.approve_trade(trade_id.clone(), "user_a".to_string(), &a_key)?;
+    assert!(matches!(
+        service.approve_trade(trade_id.clone(), "user_a".to_string(), &a_key),
+        Err(ServiceError::AlreadyApproved { .. })
+    ));
+    assert_eq!(
+        service.get_trade(&trade_id)?.current_state(),
+        context::TradeState::PendingApproval
+    );
+
+    // Nor can one delegate stand in for two approvers
+    let trade_id = submit()?.trade_id;
+    let approved =
+        service.approve_trade(trade_id.clone(), "user_deputy".to_string(), &deputy_key)?;
+    assert_eq!(approved.approval_signers(), vec!["user_deputy"]);
+    assert!(matches!(
+        service.approve_trade(trade_id.clone(), "user_deputy".to_string(), &deputy_key),
+        Err(ServiceError::AlreadyApproved { .. })
+    ));
+
+    // The second vote has to come from someone else
+    let approved = service.approve_trade(trade_id, "user_a".to_string(), &a_key)?;
+    assert_eq!(approved.current_state(), context::TradeState::Approved);
+
+    Ok(())
+}
+
+#[test]
+fn rotated_keys_still_verify_earlier_trades() -> anyhow::Result<()> {
+    use trade_approval::clock::FixedClock;
+
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let requester_key = keys::generate_signing_key();
+    let old_key = keys::generate_signing_key();
+    registry.register("user_requester", requester_key.verifying_key())?;
+    registry.register("user_approver", old_key.verifying_key())?;
+    let clock = Arc::new(FixedClock::new(trade::TimeStamp::new_with(
+        2025, 6, 2, 9, 0, 0,
+    )));
+    let service = TradeService::new(Arc::new(InMemoryStore::new()), registry.clone())
+        .with_clock(clock.clone());
+
+    let submit = || {
+        service.submit_trade(
+            trade::TradeDetails::new()
+                .set_trade_entity("entity_1abc")
+                .set_counter_party("counter_1xyz")
+                .set_notional_currency(trade::Currency::USD)
+                .set_direction(trade::Direction::Buy)
+                .set_notional_amount(1_000_000)
+                .set_underlying_amount(15_000)
+                .set_underlying_currency(trade::Currency::EUR)
+                .set_trade_date(trade::TimeStamp::new_with(2025, 6, 2, 0, 0, 0))
+                .set_value_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+                .set_delivery_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0)),
+            "user_requester".to_string(),
+            "user_approver".to_string(),
+            "user_requester".to_string(),
+            &requester_key,
+        )
+    };
+    let old_trade = submit()?.trade_id;
+    service.approve_trade(old_trade.clone(), "user_approver".to_string(), &old_key)?;
+
+    // Registering again would hand the identity to whoever holds the new key
+    let new_key = keys::generate_signing_key();
+    let err = registry
+        .register("user_approver", new_key.verifying_key())
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<ValidationError>(),
+        Some(ValidationError::KeyAlreadyRegistered(user)) if user == "user_approver"
+    ));
+
+    clock.advance(chrono::TimeDelta::hours(1));
+    service.rotate_key("user_approver", new_key.verifying_key())?;
+    clock.advance(chrono::TimeDelta::hours(1));
+
+    // The trade approved with the old key still loads
+    let reloaded = service.get_trade(&old_trade)?;
+    assert_eq!(reloaded.current_state(), context::TradeState::Approved);
+    assert_eq!(service.list_trades()?.len(), 1);
+
+    // From the rotation on, only the new key signs for the approver
+    let new_trade = submit()?.trade_id;
+    assert!(matches!(
+        service.approve_trade(new_trade.clone(), "user_approver".to_string(), &old_key),
+        Err(ServiceError::Validation(ValidationError::InvalidSignature(
+            _
+        )))
+    ));
+    service.approve_trade(new_trade.clone(), "user_approver".to_string(), &new_key)?;
+    assert_eq!(
+        service.get_trade(&new_trade)?.current_state(),
+        context::TradeState::Approved
+    );
+
+    // A rotation may not reach back before the previous one
+    assert!(matches!(
+        registry.rotate(
+            "user_approver",
+            keys::generate_signing_key().verifying_key(),
+            trade::TimeStamp::new_with(2025, 6, 2, 9, 30, 0),
+        ),
+        Err(err) if matches!(
+            err.downcast_ref::<ValidationError>(),
+            Some(ValidationError::InvalidKeyRotation(_))
+        )
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn rejected_trade_is_resubmitted_by_update() -> anyhow::Result<()> {
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let requester_key = keys::generate_signing_key();
+    let approver_key = keys::generate_signing_key();
+    registry.register("user_requester", requester_key.verifying_key())?;
+    registry.register("user_approver", approver_key.verifying_key())?;
+    let service = TradeService::new(Arc::new(InMemoryStore::new()), registry.clone());
+
+    let trade_details = |strike| {
+        trade::TradeDetails::new()
+            .set_trade_entity("entity_1abc")
+            .set_counter_party("counter_1xyz")
+            .set_notional_currency(trade::Currency::USD)
+            .set_direction(trade::Direction::Buy)
+            .set_notional_amount(1_000_000)
+            .set_underlying_amount(15_000)
+            .set_underlying_currency(trade::Currency::EUR)
+            .set_trade_date(trade::TimeStamp::new_with(2025, 6, 2, 0, 0, 0))
+            .set_value_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+            .set_delivery_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+            .set_strike(strike)
+    };
+    let trade_id = service
+        .submit_trade(
+            trade_details(90_000),
+            "user_requester".to_string(),
+            "user_approver".to_string(),
+            "user_requester".to_string(),
+            &requester_key,
+        )?
+        .trade_id;
+    let reject = |user_id: &str, key| {
+        service.reject_trade(
+            trade_id.clone(),
+            user_id.to_string(),
+            "strike outside band".to_string(),
+            key,
+        )
+    };
+
+    // Only the approver can send the trade back
+    assert!(matches!(
+        reject("user_requester", &requester_key),
+        Err(ServiceError::UnauthorizedApprover { .. })
+    ));
+    // ...and only with a reason
+    for blank in ["", "   "] {
+        assert!(matches!(
+            service.reject_trade(
+                trade_id.clone(),
+                "user_approver".to_string(),
+                blank.to_string(),
+                &approver_key,
+            ),
+            Err(ServiceError::MissingReason { action: "Reject" })
+        ));
+    }
+    let rejected = reject("user_approver", &approver_key)?;
+    assert_eq!(rejected.current_state(), context::TradeState::Rejected);
+    assert_eq!(
+        rejected.witness_set.last().unwrap().witness_type,
+        context::WitnessType::Reject {
+            reason: "strike outside band".to_string()
+        }
+    );
+    rejected.view_history();
+    assert_eq!(
+        service.list_by_state(&context::TradeState::Rejected)?.len(),
+        1
+    );
+
+    // Nothing but an Update or Cancel is accepted until the requester resubmits
+    assert!(matches!(
+        service.approve_trade(trade_id.clone(), "user_approver".to_string(), &approver_key),
+        Err(ServiceError::InvalidTransition {
+            from: context::TradeState::Rejected,
+            ..
+        })
+    ));
+    assert!(matches!(
+        reject("user_approver", &approver_key),
+        Err(ServiceError::InvalidTransition { .. })
+    ));
+
+    let resubmitted = service.update_trade(
+        trade_id.clone(),
+        trade_details(85_000),
+        "user_requester".to_string(),
+        &requester_key,
+    )?;
+    assert_eq!(
+        resubmitted.current_state(),
+        context::TradeState::PendingApproval
+    );
+    let approved =
+        service.approve_trade(trade_id.clone(), "user_approver".to_string(), &approver_key)?;
+    assert_eq!(approved.current_state(), context::TradeState::Approved);
+    assert!(
+        service
+            .list_by_state(&context::TradeState::Rejected)?
+            .is_empty()
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "config")]
+#[test]
+fn required_reasons_are_enforced_and_recorded() -> anyhow::Result<()> {
+    use trade_approval::context::Note;
+
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let requester_key = keys::generate_signing_key();
+    let approver_key = keys::generate_signing_key();
+    registry.register("user_requester", requester_key.verifying_key())?;
+    registry.register("user_approver", approver_key.verifying_key())?;
+
+    let config = trade_approval::config::ServiceConfig::from_toml(
+        r#"
+        [required_reasons]
+        cancel = true
+        "#,
+    )?;
+    let service =
+        TradeService::new(Arc::new(InMemoryStore::new()), registry.clone()).with_config(config)?;
+
+    let trade_details = trade::TradeDetails::new()
+        .set_trade_entity("entity_1abc")
+        .set_counter_party("counter_1xyz")
+        .set_notional_currency(trade::Currency::USD)
+        .set_direction(trade::Direction::Buy)
+        .set_notional_amount(1_000_000)
+        .set_underlying_amount(15_000)
+        .set_underlying_currency(trade::Currency::EUR)
+        .set_trade_date(trade::TimeStamp::new_with(2025, 6, 2, 0, 0, 0))
+        .set_value_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+        .set_delivery_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0));
+    let trade_id = service
+        .submit_trade(
+            trade_details,
+            "user_requester".to_string(),
+            "user_approver".to_string(),
+            "user_requester".to_string(),
+            &requester_key,
+        )?
+        .trade_id;
+
+    // Approving needs no reason, but the conditions attached are kept
+    let conditions = Note::default().tag("condition", "hedge by EOD");
+    service.approve_trade_with_note(
+        trade_id.clone(),
+        "user_approver".to_string(),
+        conditions.clone(),
+        &approver_key,
+    )?;
+
+    let cancel = |note| {
+        service.cancel_trade_with_note(
+            trade_id.clone(),
+            "user_requester".to_string(),
+            note,
+            &requester_key,
+        )
+    };
+    for note in [Note::default(), Note::reason(" ")] {
+        assert!(matches!(
+            cancel(note),
+            Err(ServiceError::MissingReason { action: "Cancel" })
+        ));
+    }
+    let cancelled = cancel(Note::reason("client withdrew the order"))?;
+    assert_eq!(cancelled.current_state(), context::TradeState::Cancelled);
+
+    // Notes survive the round trip through the store and its signature checks
+    let stored = service.get_trade(&trade_id)?;
+    assert_eq!(stored.witness_set[0].note, None);
+    assert_eq!(stored.witness_set[1].note, Some(conditions));
+    assert_eq!(
+        stored.witness_set[2].note,
+        Some(Note::reason("client withdrew the order"))
+    );
+    stored.view_history();
+
+    Ok(())
+}
+
+#[test]
+fn as_of_reconstructs_state_and_details_at_a_point_in_time() -> anyhow::Result<()> {
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let requester_key = keys::generate_signing_key();
+    let approver_key = keys::generate_signing_key();
+    registry.register("user_requester", requester_key.verifying_key())?;
+    registry.register("user_approver", approver_key.verifying_key())?;
+    let service = TradeService::new(Arc::new(InMemoryStore::new()), registry.clone());
+
+    let trade_details = |notional_amount| {
+        trade::TradeDetails::new()
+            .set_trade_entity("entity_1abc")
+            .set_counter_party("counter_1xyz")
+            .set_notional_currency(trade::Currency::USD)
+            .set_direction(trade::Direction::Buy)
+            .set_notional_amount(notional_amount)
+            .set_underlying_amount(15_000)
+            .set_underlying_currency(trade::Currency::EUR)
+            .set_trade_date(trade::TimeStamp::new_with(2025, 6, 2, 0, 0, 0))
+            .set_value_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+            .set_delivery_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+    };
+
+    let before_submit = trade::TimeStamp::new();
+    let trade_id = service
+        .submit_trade(
+            trade_details(1_000_000),
+            "user_requester".to_string(),
+            "user_approver".to_string(),
+            "user_requester".to_string(),
+            &requester_key,
+        )?
+        .trade_id;
+    service.approve_trade(trade_id.clone(), "user_approver".to_string(), &approver_key)?;
+    let approved_at = trade::TimeStamp::new();
+
+    // The amended trade is pending again, but the look-back still sees the approval
+    service.update_trade(
+        trade_id.clone(),
+        trade_details(2_000_000),
+        "user_requester".to_string(),
+        &requester_key,
+    )?;
+
+    let then = service.as_of(&trade_id, approved_at.clone())?;
+    assert_eq!(then.state, context::TradeState::Approved);
+    assert_eq!(then.witness_count, 2);
+    assert_eq!(then.details.map(|d| d.notional_amount()), Some(1_000_000));
+
+    let now = service.as_of(&trade_id, trade::TimeStamp::new())?;
+    assert_eq!(now.state, context::TradeState::PendingApproval);
+    assert_eq!(now.details.map(|d| d.notional_amount()), Some(2_000_000));
+    assert_eq!(
+        now.details_hash,
+        Some(service.get_trade(&trade_id)?.current_details_hash()?)
+    );
+
+    // Before the Submit the trade did not exist yet
+    let before = service.as_of(&trade_id, before_submit)?;
+    assert_eq!(before.state, context::TradeState::Draft);
+    assert_eq!(before.witness_count, 0);
+    assert!(before.details.is_none());
+
+    assert!(matches!(
+        service.as_of("trade_missing", approved_at),
+        Err(ServiceError::NotFound { .. })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn witness_timestamps_never_go_backwards_beyond_the_skew() -> anyhow::Result<()> {
+    use chrono::TimeDelta;
+    use trade_approval::clock::FixedClock;
+
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let requester_key = keys::generate_signing_key();
+    let approver_key = keys::generate_signing_key();
+    registry.register("user_requester", requester_key.verifying_key())?;
+    registry.register("user_approver", approver_key.verifying_key())?;
+
+    // Two services share a store, each stamping witnesses with its own host's clock
+    let store = Arc::new(InMemoryStore::new());
+    let submitted_at = trade::TimeStamp::new_with(2025, 6, 2, 9, 0, 0);
+    let ahead = Arc::new(FixedClock::new(submitted_at.clone()));
+    let behind = Arc::new(FixedClock::new(submitted_at.clone()));
+    behind.advance(TimeDelta::seconds(-30));
+    let service = TradeService::new(store.clone(), registry.clone()).with_clock(ahead.clone());
+    let lagging = TradeService::new(store, registry.clone()).with_clock(behind.clone());
+
+    let trade_details = trade::TradeDetails::new()
+        .set_trade_entity("entity_1abc")
+        .set_counter_party("counter_1xyz")
+        .set_notional_currency(trade::Currency::USD)
+        .set_direction(trade::Direction::Buy)
+        .set_notional_amount(1_000_000)
+        .set_underlying_amount(15_000)
+        .set_underlying_currency(trade::Currency::EUR)
+        .set_trade_date(trade::TimeStamp::new_with(2025, 6, 2, 0, 0, 0))
+        .set_value_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0))
+        .set_delivery_date(trade::TimeStamp::new_with(2025, 6, 4, 0, 0, 0));
+    let ctx = service.submit_trade(
+        trade_details,
+        "user_requester".to_string(),
+        "user_approver".to_string(),
+        "user_requester".to_string(),
+        &requester_key,
+    )?;
+    assert_eq!(ctx.witness_set[0].user_timestamp, submitted_at);
+
+    // By default the lagging host cannot append a witness dated before the Submit
+    let err = lagging
+        .approve_trade(
+            ctx.trade_id.clone(),
+            "user_approver".to_string(),
+            &approver_key,
+        )
+        .unwrap_err();
+    assert!(matches!(err, ServiceError::ClockBehind { .. }));
+
+    // Allowing a minute of skew between hosts lets it through
+    let lagging = lagging.with_max_clock_skew(TimeDelta::minutes(1));
+    let approved = lagging.approve_trade(
+        ctx.trade_id.clone(),
+        "user_approver".to_string(),
+        &approver_key,
+    )?;
+    assert_eq!(approved.current_state(), context::TradeState::Approved);
+
+    // A service without the skew refuses to load the chain, as it would refuse to
+    // write it
+    assert!(matches!(
+        service.get_trade(&ctx.trade_id),
+        Err(ServiceError::Validation(
+            ValidationError::TimestampRegression { index: 1, .. }
+        ))
+    ));
+
+    // The strict check still reports the regression, the tolerant one accepts it
+    let stored = lagging.get_trade(&ctx.trade_id)?;
+    let err = stored.verify_chain().unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<ValidationError>(),
+        Some(ValidationError::TimestampRegression { index: 1, .. })
+    ));
+    stored.verify_chain_with_skew(TimeDelta::minutes(1))?;
+
+    // Beyond the tolerance the lagging host is refused again
+    behind.advance(TimeDelta::minutes(-2));
+    assert!(matches!(
+        lagging.execute_trade(
+            ctx.trade_id.clone(),
+            "user_requester".to_string(),
+            &requester_key
+        ),
+        Err(ServiceError::ClockBehind { .. })
+    ));
+
+    Ok(())
+}
+
+#[cfg(feature = "config")]
+#[test]
+fn settlement_dates_must_be_joint_business_days() -> anyhow::Result<()> {
+    let registry = Arc::new(InMemoryKeyRegistry::new());
+    let requester_key = keys::generate_signing_key();
+    registry.register("user_requester", requester_key.verifying_key())?;
+
+    let config = trade_approval::config::ServiceConfig::from_toml(
+        r#"
+        [calendars.USD]
+        holidays = ["2025-07-04"]
+        "#,
+    )?;
+    let service =
+        TradeService::new(Arc::new(InMemoryStore::new()), registry.clone()).with_config(config)?;
+
+    let trade_details = |value_day| {
+        trade::TradeDetails::new()
+            .set_trade_entity("entity_1abc")
+            .set_counter_party("counter_1xyz")
+            .set_notional_currency(trade::Currency::USD)
+            .set_direction(trade::Direction::Buy)
+            .set_notional_amount(1_000_000)
+            .set_underlying_amount(850_000)
+            .set_underlying_currency(trade::Currency::EUR)
+            .set_trade_date(trade::TimeStamp::new_with(2025, 7, 2, 0, 0, 0))
+            .set_value_date(trade::TimeStamp::new_with(2025, 7, value_day, 0, 0, 0))
+            .set_delivery_date(trade::TimeStamp::new_with(2025, 7, 8, 0, 0, 0))
+    };
+    let submit = |details| {
+        service.submit_trade(
+            details,
+            "user_requester".to_string(),
+            "user_approver".to_string(),
+            "user_requester".to_string(),
+            &requester_key,
+        )
+    };
+
+    // The US holiday and the weekend after it are refused, Monday is spot
+    for closed in [4, 5, 6] {
+        assert!(matches!(
+            submit(trade_details(closed)),
+            Err(ServiceError::Validation(ValidationError::NonBusinessDay {
+                field: "value_date",
+                ..
+            }))
+        ));
+    }
+    let ctx = submit(trade_details(7))?;
+
+    // Amendments are held to the same calendars
+    let update = service.update_trade(
+        ctx.trade_id.clone(),
+        trade_details(4),
+        "user_requester".to_string(),
+        &requester_key,
+    );
+    assert!(matches!(
+        update,
+        Err(ServiceError::Validation(
+            ValidationError::NonBusinessDay { .. }
+        ))
+    ));
+
+    Ok(())
+}
@@ -8,7 +8,20 @@
 
 use chrono::{Datelike, Timelike, Utc};
 use trade_approval::{
-    context::{TradeContext, TradeState, Witness, WitnessType},
+    canonical::{MAX_DEPTH, canonicalize, verify_canonical},
+    config::{RoutingRule, ServiceConfig},
+    context::{
+        ApprovalLevel, ApprovalPolicy, Note, Tag, TradeContext, TradeState, Witness, WitnessType,
+    },
+    delegation::{Delegation, DelegationStore, InMemoryDelegationStore, SledDelegationStore},
+    error::{ServiceError, ValidationError},
+    keys::{
+        DetachedSignature, InMemoryKeyRegistry, KeyRegistry, SledKeyRegistry, generate_signing_key,
+    },
+    limits::{LimitScope, NotionalLimit},
+    service::{SegregationOfDuties, TradeService},
+    store::{InMemoryStore, SledStore, TradeStore, WriteBatch},
+    timeline::TimelineFormat,
     trade::{Currency, Direction, TimeStamp, TradeDetails},
     utils::new_uuid_to_bech32,
 };
@@ -323,6 +336,73 @@ mod trade_tests {
         assert!(Direction::Buy < Direction::Sell);
         assert_eq!(Direction::Buy, Direction::Buy);
     }
+
+    /// Test that stored details are only decoded if they hash to their key
+    #[test]
+    fn details_from_cbor_checks_hash() {
+        let details = TradeDetails::new()
+            .set_trade_entity("entity_1abc")
+            .set_counter_party("counter_1xyz")
+            .set_direction(Direction::Buy)
+            .set_notional_currency(Currency::USD)
+            .set_notional_amount(100)
+            .set_underlying_currency(Currency::EUR)
+            .set_underlying_amount(90)
+            .set_trade_date(TimeStamp::new_with(2025, 1, 1, 0, 0, 0))
+            .set_value_date(TimeStamp::new_with(2025, 1, 2, 0, 0, 0))
+            .set_delivery_date(TimeStamp::new_with(2025, 1, 2, 0, 0, 0));
+        let (hash, cbor) = details.validate_and_finalise().unwrap();
+
+        assert_eq!(TradeDetails::from_cbor(&hash, &cbor).unwrap(), details);
+
+        let err = TradeDetails::from_cbor(&"0".repeat(64), &cbor).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ValidationError>(),
+            Some(ValidationError::ContentHashMismatch { .. })
+        ));
+    }
+
+    /// Test that diff reports only the fields that differ, with old and new values
+    #[test]
+    fn details_diff_lists_changed_fields() {
+        let base = || {
+            TradeDetails::new()
+                .set_trade_entity("entity_1abc")
+                .set_counter_party("counter_1xyz")
+                .set_direction(Direction::Buy)
+                .set_notional_currency(Currency::USD)
+                .set_notional_amount(100)
+                .set_underlying_currency(Currency::EUR)
+                .set_underlying_amount(90)
+                .set_trade_date(TimeStamp::new_with(2025, 1, 1, 0, 0, 0))
+                .set_value_date(TimeStamp::new_with(2025, 1, 2, 0, 0, 0))
+                .set_delivery_date(TimeStamp::new_with(2025, 1, 2, 0, 0, 0))
+        };
+
+        assert!(base().diff(&base()).is_empty());
+
+        let changed = base()
+            .set_notional_amount(150)
+            .set_underlying_currency(Currency::GBP)
+            .set_delivery_date(TimeStamp::new_with(2025, 1, 5, 0, 0, 0))
+            .set_strike(12);
+        let changes = base().diff(&changed);
+
+        let fields: Vec<_> = changes.iter().map(|c| c.field).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "notional_amount",
+                "underlying_currency",
+                "delivery_date",
+                "strike"
+            ]
+        );
+        assert_eq!(changes[0].old.as_deref(), Some("100"));
+        assert_eq!(changes[0].new.as_deref(), Some("150"));
+        assert_eq!(changes[3].old, None);
+        assert_eq!(changes[3].to_string(), "strike: unset → 12");
+    }
 }
 
 // CONTEXT MODULE TESTS
@@ -365,13 +445,181 @@ mod context_tests {
         let mut ctx = TradeContext::new();
         let trade_id = ctx.trade_id.clone();
 
-        let witness = create_test_witness(trade_id, "user_123".to_string(), WitnessType::Approve);
+        let witness = create_test_witness(
+            trade_id,
+            "user_123".to_string(),
+            WitnessType::Approve { on_behalf_of: None },
+        );
 
         assert_eq!(ctx.witness_set.len(), 0);
-        ctx.insert_witness(witness);
+        ctx.insert_witness(witness).unwrap();
         assert_eq!(ctx.witness_set.len(), 1);
     }
 
+    /// Test that the timeline reports each witness with the state it left the trade in,
+    /// and renders IDs and times as its format says
+    #[test]
+    fn timeline_entries_carry_derived_state() {
+        let mut ctx = TradeContext::new();
+        let trade_id = ctx.trade_id.clone();
+        ctx.insert_witness(create_test_witness(
+            trade_id.clone(),
+            "requester_long_id".to_string(),
+            WitnessType::Submit {
+                details_hash: "a".repeat(64),
+                requester_id: "requester_long_id".to_string(),
+                approver_id: "approver_a".to_string(),
+                approval_policy: Some(ApprovalPolicy::quorum(
+                    vec!["approver_a".to_string(), "approver_b".to_string()],
+                    2,
+                )),
+            },
+        ))
+        .unwrap();
+        ctx.insert_witness(create_test_witness(
+            trade_id.clone(),
+            "approver_a".to_string(),
+            WitnessType::Approve { on_behalf_of: None },
+        ))
+        .unwrap();
+        ctx.insert_witness(create_test_witness(
+            trade_id,
+            "approver_b".to_string(),
+            WitnessType::Approve { on_behalf_of: None },
+        ))
+        .unwrap();
+
+        let timeline = ctx.timeline();
+        let states: Vec<TradeState> = timeline.iter().map(|e| e.state_after.clone()).collect();
+        assert_eq!(
+            states,
+            [
+                TradeState::PendingApproval,
+                TradeState::PendingApproval,
+                TradeState::Approved
+            ]
+        );
+        assert_eq!(timeline[1].action, "Approve");
+        assert_eq!(timeline[1].user_id, "approver_a");
+        assert_eq!(
+            timeline[1].progress.as_ref().map(|p| p.approved_by.len()),
+            Some(1)
+        );
+        // The approval that completes the policy has nothing left to count
+        assert!(timeline[2].progress.is_none());
+
+        let rendered = timeline[1].to_string();
+        assert!(rendered.contains("│ approver_a → PendingApproval"));
+        assert!(rendered.contains("approvals: 1/2"));
+        assert!(rendered.contains(" UTC │"));
+
+        let short = TimelineFormat::default().truncate_ids(8);
+        assert!(
+            timeline[0]
+                .display(&short)
+                .to_string()
+                .contains("requeste... →")
+        );
+        let full = TimelineFormat::default().full_ids();
+        assert!(
+            timeline[0]
+                .display(&full)
+                .to_string()
+                .contains("requester_long_id →")
+        );
+        let local = TimelineFormat::default().local_time();
+        assert!(!timeline[0].display(&local).to_string().contains(" UTC"));
+    }
+
+    /// Test that a history renders every entry between its trade_id and current state
+    #[test]
+    fn history_displays_the_whole_timeline() {
+        let mut ctx = TradeContext::new();
+        let trade_id = ctx.trade_id.clone();
+        assert!(
+            ctx.history()
+                .to_string()
+                .ends_with("No witnesses recorded yet (Draft state)")
+        );
+
+        ctx.insert_witness(create_test_witness(
+            trade_id.clone(),
+            "requester_long_id".to_string(),
+            WitnessType::Submit {
+                details_hash: "a".repeat(64),
+                requester_id: "requester_long_id".to_string(),
+                approver_id: "approver_a".to_string(),
+                approval_policy: None,
+            },
+        ))
+        .unwrap();
+        ctx.insert_witness(create_test_witness(
+            trade_id.clone(),
+            "approver_a".to_string(),
+            WitnessType::Approve { on_behalf_of: None },
+        ))
+        .unwrap();
+
+        let history = ctx.history();
+        assert_eq!(history.entries, ctx.timeline());
+        let rendered = history.to_string();
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[0], format!("Trade Timeline: {}", trade_id));
+        assert!(lines[2].starts_with(" 1. ") && lines[2].contains("│ Submit"));
+        assert!(rendered.contains("\n 2. ") && rendered.contains("│ Approve"));
+        assert_eq!(lines.last(), Some(&"Current State: Approved"));
+
+        let full = TimelineFormat::default().full_ids();
+        assert!(
+            history
+                .display(&full)
+                .to_string()
+                .contains("requester_long_id →")
+        );
+    }
+
+    /// Test that past states are replayed from the witnesses recorded by then
+    #[test]
+    fn state_at_replays_witnesses_up_to_a_point() {
+        let mut ctx = TradeContext::new();
+        let trade_id = ctx.trade_id.clone();
+        let at = |hour| TimeStamp::new_with(2025, 6, 2, hour, 0, 0);
+        for (hour, witness_type) in [
+            (
+                9,
+                WitnessType::Submit {
+                    details_hash: "a".repeat(64),
+                    requester_id: "user_requester".to_string(),
+                    approver_id: "user_approver".to_string(),
+                    approval_policy: None,
+                },
+            ),
+            (11, WitnessType::Approve { on_behalf_of: None }),
+            (13, WitnessType::Cancel),
+        ] {
+            ctx.insert_witness(Witness::new(
+                trade_id.clone(),
+                "user_approver".to_string(),
+                at(hour),
+                witness_type,
+            ))
+            .unwrap();
+        }
+
+        assert_eq!(ctx.state_at(at(8)), TradeState::Draft);
+        assert_eq!(ctx.state_at(at(9)), TradeState::PendingApproval);
+        assert_eq!(ctx.state_at(at(12)), TradeState::Approved);
+        assert_eq!(ctx.state_at(at(14)), TradeState::Cancelled);
+        assert_eq!(ctx.witnesses_at(&at(12)), 2);
+
+        assert_eq!(ctx.state_at_index(1).unwrap(), TradeState::Approved);
+        let err = ctx.state_at_index(3).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ValidationError>(),
+            Some(ValidationError::NoSuchWitness { index: 3, len: 3 })
+        ));
+    }
+
     /// Test that empty witness set results in Draft state
     #[test]
     fn current_state_draft_with_empty_witnesses() {
@@ -392,10 +640,11 @@ mod context_tests {
                 details_hash: "hash_abc".to_string(),
                 requester_id: "user_123".to_string(),
                 approver_id: "user_456".to_string(),
+                approval_policy: None,
             },
         );
 
-        ctx.insert_witness(submit_witness);
+        ctx.insert_witness(submit_witness).unwrap();
         assert_eq!(ctx.current_state(), TradeState::PendingApproval);
     }
 
@@ -412,14 +661,18 @@ mod context_tests {
                 details_hash: "hash_abc".to_string(),
                 requester_id: "user_123".to_string(),
                 approver_id: "user_456".to_string(),
+                approval_policy: None,
             },
         );
 
-        let approve_witness =
-            create_test_witness(trade_id, "user_456".to_string(), WitnessType::Approve);
+        let approve_witness = create_test_witness(
+            trade_id,
+            "user_456".to_string(),
+            WitnessType::Approve { on_behalf_of: None },
+        );
 
-        ctx.insert_witness(submit_witness);
-        ctx.insert_witness(approve_witness);
+        ctx.insert_witness(submit_witness).unwrap();
+        ctx.insert_witness(approve_witness).unwrap();
 
         assert_eq!(ctx.current_state(), TradeState::Approved);
     }
@@ -437,13 +690,14 @@ mod context_tests {
                 details_hash: "hash_abc".to_string(),
                 requester_id: "user_123".to_string(),
                 approver_id: "user_456".to_string(),
+                approval_policy: None,
             },
         );
 
         let approve_witness = create_test_witness(
             trade_id.clone(),
             "user_456".to_string(),
-            WitnessType::Approve,
+            WitnessType::Approve { on_behalf_of: None },
         );
 
         let update_witness = create_test_witness(
@@ -454,11 +708,11 @@ mod context_tests {
             },
         );
 
-        ctx.insert_witness(submit_witness);
-        ctx.insert_witness(approve_witness);
+        ctx.insert_witness(submit_witness).unwrap();
+        ctx.insert_witness(approve_witness).unwrap();
         assert_eq!(ctx.current_state(), TradeState::Approved);
 
-        ctx.insert_witness(update_witness);
+        ctx.insert_witness(update_witness).unwrap();
         assert_eq!(ctx.current_state(), TradeState::PendingApproval);
     }
 
@@ -475,16 +729,17 @@ mod context_tests {
                 details_hash: "hash_abc".to_string(),
                 requester_id: "user_123".to_string(),
                 approver_id: "user_456".to_string(),
+                approval_policy: None,
             },
         );
-        ctx.insert_witness(submit_witness);
+        ctx.insert_witness(submit_witness).unwrap();
 
         let approve1 = create_test_witness(
             trade_id.clone(),
             "user_456".to_string(),
-            WitnessType::Approve,
+            WitnessType::Approve { on_behalf_of: None },
         );
-        ctx.insert_witness(approve1);
+        ctx.insert_witness(approve1).unwrap();
         assert_eq!(ctx.current_state(), TradeState::Approved);
 
         let update_witness = create_test_witness(
@@ -494,12 +749,16 @@ mod context_tests {
                 details_hash: "hash_def".to_string(),
             },
         );
-        ctx.insert_witness(update_witness);
+        ctx.insert_witness(update_witness).unwrap();
         assert_eq!(ctx.current_state(), TradeState::PendingApproval);
 
         // Add another approval
-        let approve2 = create_test_witness(trade_id, "user_456".to_string(), WitnessType::Approve);
-        ctx.insert_witness(approve2);
+        let approve2 = create_test_witness(
+            trade_id,
+            "user_456".to_string(),
+            WitnessType::Approve { on_behalf_of: None },
+        );
+        ctx.insert_witness(approve2).unwrap();
 
         assert_eq!(
             ctx.current_state(),
@@ -521,14 +780,15 @@ mod context_tests {
                 details_hash: "hash_abc".to_string(),
                 requester_id: "user_123".to_string(),
                 approver_id: "user_456".to_string(),
+                approval_policy: None,
             },
         );
 
         let cancel_witness =
             create_test_witness(trade_id, "user_123".to_string(), WitnessType::Cancel);
 
-        ctx.insert_witness(submit_witness);
-        ctx.insert_witness(cancel_witness);
+        ctx.insert_witness(submit_witness).unwrap();
+        ctx.insert_witness(cancel_witness).unwrap();
 
         assert_eq!(ctx.current_state(), TradeState::Cancelled);
     }
@@ -546,21 +806,22 @@ mod context_tests {
                 details_hash: "hash_abc".to_string(),
                 requester_id: "user_123".to_string(),
                 approver_id: "user_456".to_string(),
+                approval_policy: None,
             },
         );
 
         let approve_witness = create_test_witness(
             trade_id.clone(),
             "user_456".to_string(),
-            WitnessType::Approve,
+            WitnessType::Approve { on_behalf_of: None },
         );
 
         let execute_witness =
             create_test_witness(trade_id, "user_123".to_string(), WitnessType::SendToExecute);
 
-        ctx.insert_witness(submit_witness);
-        ctx.insert_witness(approve_witness);
-        ctx.insert_witness(execute_witness);
+        ctx.insert_witness(submit_witness).unwrap();
+        ctx.insert_witness(approve_witness).unwrap();
+        ctx.insert_witness(execute_witness).unwrap();
 
         assert_eq!(ctx.current_state(), TradeState::SentToExecute);
     }
@@ -578,13 +839,14 @@ mod context_tests {
                 details_hash: "hash_abc".to_string(),
                 requester_id: "user_123".to_string(),
                 approver_id: "user_456".to_string(),
+                approval_policy: None,
             },
         );
 
         let approve_witness = create_test_witness(
             trade_id.clone(),
             "user_456".to_string(),
-            WitnessType::Approve,
+            WitnessType::Approve { on_behalf_of: None },
         );
 
         let execute_witness = create_test_witness(
@@ -599,10 +861,10 @@ mod context_tests {
             WitnessType::Book { strike: 100_000 },
         );
 
-        ctx.insert_witness(submit_witness);
-        ctx.insert_witness(approve_witness);
-        ctx.insert_witness(execute_witness);
-        ctx.insert_witness(book_witness);
+        ctx.insert_witness(submit_witness).unwrap();
+        ctx.insert_witness(approve_witness).unwrap();
+        ctx.insert_witness(execute_witness).unwrap();
+        ctx.insert_witness(book_witness).unwrap();
 
         assert_eq!(ctx.current_state(), TradeState::Booked);
     }
@@ -620,10 +882,11 @@ mod context_tests {
                 details_hash: "hash_abc".to_string(),
                 requester_id: "user_123".to_string(),
                 approver_id: "user_456".to_string(),
+                approval_policy: None,
             },
         );
 
-        ctx.insert_witness(submit_witness);
+        ctx.insert_witness(submit_witness).unwrap();
         assert!(ctx.requires_approval());
     }
 
@@ -640,14 +903,18 @@ mod context_tests {
                 details_hash: "hash_abc".to_string(),
                 requester_id: "user_123".to_string(),
                 approver_id: "user_456".to_string(),
+                approval_policy: None,
             },
         );
 
-        let approve_witness =
-            create_test_witness(trade_id, "user_456".to_string(), WitnessType::Approve);
+        let approve_witness = create_test_witness(
+            trade_id,
+            "user_456".to_string(),
+            WitnessType::Approve { on_behalf_of: None },
+        );
 
-        ctx.insert_witness(submit_witness);
-        ctx.insert_witness(approve_witness);
+        ctx.insert_witness(submit_witness).unwrap();
+        ctx.insert_witness(approve_witness).unwrap();
 
         assert!(!ctx.requires_approval());
     }
@@ -666,10 +933,11 @@ mod context_tests {
                 details_hash: "hash_abc".to_string(),
                 requester_id: "user_123".to_string(),
                 approver_id: expected_approver.clone(),
+                approval_policy: None,
             },
         );
 
-        ctx.insert_witness(submit_witness);
+        ctx.insert_witness(submit_witness).unwrap();
 
         let approver = ctx.get_expected_approver().unwrap();
         assert_eq!(approver, expected_approver);
@@ -681,4 +949,1501 @@ mod context_tests {
         let ctx = TradeContext::new();
         assert!(ctx.get_expected_approver().is_err());
     }
+
+    /// Test that insert_witness links each witness to the hash of its predecessor
+    #[test]
+    fn insert_witness_links_parent_hash() {
+        let mut ctx = TradeContext::new();
+        let trade_id = ctx.trade_id.clone();
+
+        let submit_witness = create_test_witness(
+            trade_id.clone(),
+            "user_123".to_string(),
+            WitnessType::Submit {
+                details_hash: "hash_abc".to_string(),
+                requester_id: "user_123".to_string(),
+                approver_id: "user_456".to_string(),
+                approval_policy: None,
+            },
+        );
+        let approve_witness = create_test_witness(
+            trade_id,
+            "user_456".to_string(),
+            WitnessType::Approve { on_behalf_of: None },
+        );
+
+        ctx.insert_witness(submit_witness).unwrap();
+        ctx.insert_witness(approve_witness).unwrap();
+
+        let (submit_hash, _) = ctx.witness_set[0].serialize_with_hash().unwrap();
+
+        assert_eq!(ctx.witness_set[0].parent_hash, None);
+        assert_eq!(ctx.witness_set[1].parent_hash, Some(submit_hash));
+        assert!(ctx.verify_chain().is_ok());
+    }
+
+    /// Test that verify_chain reports the first witness after a tampered one
+    #[test]
+    fn verify_chain_detects_tampered_witness() {
+        let mut ctx = TradeContext::new();
+        let trade_id = ctx.trade_id.clone();
+
+        let submit_witness = create_test_witness(
+            trade_id.clone(),
+            "user_123".to_string(),
+            WitnessType::Submit {
+                details_hash: "hash_abc".to_string(),
+                requester_id: "user_123".to_string(),
+                approver_id: "user_456".to_string(),
+                approval_policy: None,
+            },
+        );
+        let approve_witness = create_test_witness(
+            trade_id,
+            "user_456".to_string(),
+            WitnessType::Approve { on_behalf_of: None },
+        );
+
+        ctx.insert_witness(submit_witness).unwrap();
+        ctx.insert_witness(approve_witness).unwrap();
+
+        // Rewrite the approver on the already-linked Submit witness
+        ctx.witness_set[0].witness_type = WitnessType::Submit {
+            details_hash: "hash_abc".to_string(),
+            requester_id: "user_123".to_string(),
+            approver_id: "user_789".to_string(),
+            approval_policy: None,
+        };
+
+        let err = ctx.verify_chain().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ValidationError>(),
+            Some(ValidationError::BrokenChain { index: 1, .. })
+        ));
+    }
+
+    /// Helper to build a context submitted with `policy` and approved by `approvers`
+    fn quorum_context(policy: ApprovalPolicy, approvers: &[&str]) -> TradeContext {
+        let mut ctx = TradeContext::new();
+        let trade_id = ctx.trade_id.clone();
+        let approver_id = policy.approvers().next().unwrap().to_string();
+
+        ctx.insert_witness(create_test_witness(
+            trade_id.clone(),
+            "user_123".to_string(),
+            WitnessType::Submit {
+                details_hash: "hash_abc".to_string(),
+                requester_id: "user_123".to_string(),
+                approver_id,
+                approval_policy: Some(policy),
+            },
+        ))
+        .unwrap();
+        for approver in approvers {
+            ctx.insert_witness(create_test_witness(
+                trade_id.clone(),
+                approver.to_string(),
+                WitnessType::Approve { on_behalf_of: None },
+            ))
+            .unwrap();
+        }
+        ctx
+    }
+
+    fn approvers(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    /// Test that a two-of-three policy stays pending until two distinct members approve
+    #[test]
+    fn quorum_requires_distinct_member_approvals() {
+        let policy = ApprovalPolicy::quorum(approvers(&["user_a", "user_b", "user_c"]), 2);
+
+        let ctx = quorum_context(policy.clone(), &["user_a"]);
+        assert_eq!(ctx.current_state(), TradeState::PendingApproval);
+        let progress = ctx.approval_progress().unwrap();
+        assert_eq!(progress.approved_by, vec!["user_a"]);
+        assert_eq!(progress.required, 2);
+        assert_eq!(progress.awaiting, vec!["user_b", "user_c"]);
+
+        // Repeats and outsiders do not count
+        let ctx = quorum_context(policy.clone(), &["user_a", "user_a", "user_z"]);
+        assert_eq!(ctx.current_state(), TradeState::PendingApproval);
+
+        let ctx = quorum_context(policy, &["user_a", "user_c"]);
+        assert_eq!(ctx.current_state(), TradeState::Approved);
+        assert!(ctx.approval_progress().unwrap().awaiting.is_empty());
+    }
+
+    /// Test that ordered levels only count approvals once earlier levels are satisfied
+    #[test]
+    fn ordered_levels_are_satisfied_in_turn() {
+        let policy = ApprovalPolicy::quorum(approvers(&["desk_1", "desk_2"]), 1)
+            .then(approvers(&["risk_1"]), 1);
+
+        // Risk approving first is ignored, the desk level is still open
+        let ctx = quorum_context(policy.clone(), &["risk_1", "desk_2"]);
+        assert_eq!(ctx.current_state(), TradeState::PendingApproval);
+        assert_eq!(ctx.approval_progress().unwrap().awaiting, vec!["risk_1"]);
+
+        let ctx = quorum_context(policy, &["desk_2", "risk_1"]);
+        assert_eq!(ctx.current_state(), TradeState::Approved);
+    }
+
+    /// Test that an Update resets the approvals counted towards the quorum
+    #[test]
+    fn update_resets_quorum_progress() {
+        let policy = ApprovalPolicy::quorum(approvers(&["user_a", "user_b"]), 2);
+        let mut ctx = quorum_context(policy, &["user_a", "user_b"]);
+        assert_eq!(ctx.current_state(), TradeState::Approved);
+
+        let trade_id = ctx.trade_id.clone();
+        ctx.insert_witness(create_test_witness(
+            trade_id.clone(),
+            "user_123".to_string(),
+            WitnessType::Update {
+                details_hash: "hash_def".to_string(),
+            },
+        ))
+        .unwrap();
+        ctx.insert_witness(create_test_witness(
+            trade_id,
+            "user_b".to_string(),
+            WitnessType::Approve { on_behalf_of: None },
+        ))
+        .unwrap();
+
+        assert_eq!(ctx.current_state(), TradeState::PendingApproval);
+        assert_eq!(ctx.approval_progress().unwrap().approved_by, vec!["user_b"]);
+        assert_eq!(ctx.approval_progress_at(2).unwrap().approved_by.len(), 2);
+    }
+
+    /// Test that policies which can never be satisfied are rejected
+    #[test]
+    fn approval_policy_validation() {
+        assert!(ApprovalPolicy::single("user_a").validate().is_ok());
+        assert!(
+            ApprovalPolicy::quorum(approvers(&["user_a", "user_b"]), 2)
+                .then(approvers(&["user_c"]), 1)
+                .validate()
+                .is_ok()
+        );
+
+        let invalid = [
+            ApprovalPolicy { levels: vec![] },
+            ApprovalPolicy::quorum(approvers(&["user_a"]), 0),
+            ApprovalPolicy::quorum(approvers(&["user_a", "user_b"]), 3),
+            ApprovalPolicy::quorum(approvers(&["user_a"]), 1).then(approvers(&["user_a"]), 1),
+        ];
+        for policy in invalid {
+            assert!(matches!(
+                policy.validate(),
+                Err(ValidationError::InvalidApprovalPolicy(_))
+            ));
+        }
+    }
+
+    /// Test that a Submit without a policy encodes exactly as before policies existed,
+    /// and one with a policy round-trips
+    #[test]
+    fn submit_policy_encoding_is_backward_compatible() {
+        let legacy = WitnessType::Submit {
+            details_hash: "h".to_string(),
+            requester_id: "r".to_string(),
+            approver_id: "a".to_string(),
+            approval_policy: None,
+        };
+        // [0, ["h", "r", "a"]]
+        assert_eq!(
+            minicbor::to_vec(&legacy).unwrap(),
+            hex::decode("820083616861726161").unwrap()
+        );
+
+        let quorum = WitnessType::Submit {
+            details_hash: "h".to_string(),
+            requester_id: "r".to_string(),
+            approver_id: "a".to_string(),
+            approval_policy: Some(ApprovalPolicy::quorum(approvers(&["a", "b"]), 2)),
+        };
+        let bytes = minicbor::to_vec(&quorum).unwrap();
+        assert_eq!(minicbor::decode::<WitnessType>(&bytes).unwrap(), quorum);
+    }
+
+    /// Test that a delegate's Approve counts as the delegator's, and an Approve made
+    /// in the signer's own right encodes exactly as before delegation existed
+    #[test]
+    fn delegated_approve_counts_for_the_delegator() {
+        let policy = ApprovalPolicy::quorum(approvers(&["user_a", "user_b"]), 2);
+        let mut ctx = quorum_context(policy, &["user_b"]);
+        let delegated = WitnessType::Approve {
+            on_behalf_of: Some("user_a".to_string()),
+        };
+        ctx.insert_witness(create_test_witness(
+            ctx.trade_id.clone(),
+            "user_delegate".to_string(),
+            delegated.clone(),
+        ))
+        .unwrap();
+
+        assert_eq!(ctx.current_state(), TradeState::Approved);
+        let progress = ctx.approval_progress().unwrap();
+        assert_eq!(progress.approved_by, vec!["user_b", "user_a"]);
+        assert_eq!(ctx.witness_set[2].user_id, "user_delegate");
+
+        // [1, []]
+        assert_eq!(
+            minicbor::to_vec(WitnessType::Approve { on_behalf_of: None }).unwrap(),
+            hex::decode("820180").unwrap()
+        );
+        let bytes = minicbor::to_vec(&delegated).unwrap();
+        assert_eq!(minicbor::decode::<WitnessType>(&bytes).unwrap(), delegated);
+    }
+}
+
+// KEYS MODULE TESTS
+#[cfg(test)]
+mod keys_tests {
+    use super::*;
+
+    /// Helper to create an unsigned Approve witness
+    fn approve_witness(user_id: &str) -> Witness {
+        Witness::new(
+            "trade_keys".to_string(),
+            user_id.to_string(),
+            TimeStamp::new(),
+            WitnessType::Approve { on_behalf_of: None },
+        )
+    }
+
+    /// Test that a signed witness verifies against the signer's public key
+    #[test]
+    fn signed_witness_verifies() {
+        let key = generate_signing_key();
+        let mut witness = approve_witness("user_123");
+
+        witness.sign(&key).unwrap();
+
+        assert!(witness.signature.is_some());
+        assert!(witness.verify_signature(&key.verifying_key()).is_ok());
+    }
+
+    /// Test that verification fails for unsigned, wrongly signed and altered witnesses
+    #[test]
+    fn invalid_signatures_are_rejected() {
+        let key = generate_signing_key();
+        let other_key = generate_signing_key();
+
+        let unsigned = approve_witness("user_123");
+        let err = unsigned.verify_signature(&key.verifying_key()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ValidationError>(),
+            Some(ValidationError::MissingSignature(_))
+        ));
+
+        let mut witness = approve_witness("user_123");
+        witness.sign(&other_key).unwrap();
+        assert!(witness.verify_signature(&key.verifying_key()).is_err());
+
+        let mut witness = approve_witness("user_123");
+        witness.sign(&key).unwrap();
+        witness.user_id = "user_456".to_string();
+        assert!(witness.verify_signature(&key.verifying_key()).is_err());
+
+        let mut witness = approve_witness("user_123").with_note(Note::reason("limits checked"));
+        witness.sign(&key).unwrap();
+        witness.note = Some(Note::reason("limits ignored"));
+        assert!(witness.verify_signature(&key.verifying_key()).is_err());
+    }
+
+    /// Test that a witness without a note encodes exactly as before notes existed, and
+    /// one with a note round-trips
+    #[test]
+    fn note_encoding_is_backward_compatible() {
+        let witness = approve_witness("user_123");
+        let legacy = minicbor::to_vec(&witness).unwrap();
+        // Four fields: the parent hash, signature and note are all absent
+        assert_eq!(legacy[0], 0x84);
+        assert_eq!(
+            minicbor::to_vec(witness.clone().with_note(Note::default())).unwrap(),
+            legacy
+        );
+        assert_eq!(minicbor::decode::<Witness>(&legacy).unwrap().note, None);
+
+        let noted = witness.with_note(
+            Note::reason("within desk limits")
+                .tag("condition", "hedge by EOD")
+                .tag("ticket", "RISK-42"),
+        );
+        let bytes = minicbor::to_vec(&noted).unwrap();
+        assert_eq!(minicbor::decode::<Witness>(&bytes).unwrap(), noted);
+        assert_eq!(
+            noted.note.unwrap().to_string(),
+            "reason: within desk limits; tags: condition=hedge by EOD, ticket=RISK-42"
+        );
+    }
+
+    /// Test that tags parse from `key=value`, splitting on the first `=`
+    #[test]
+    fn tags_parse_from_key_value() {
+        let tag: Tag = "condition=a=b".parse().unwrap();
+        assert_eq!(tag.key, "condition");
+        assert_eq!(tag.value, "a=b");
+
+        assert!("no_value".parse::<Tag>().is_err());
+        assert!("=value".parse::<Tag>().is_err());
+        assert!(!Note::reason("  ").has_reason());
+    }
+
+    /// Test that the in-memory registry returns registered keys only
+    #[test]
+    fn in_memory_registry_round_trip() {
+        let registry = InMemoryKeyRegistry::new();
+        let key = generate_signing_key();
+
+        registry.register("user_123", key.verifying_key()).unwrap();
+
+        assert_eq!(
+            registry.get_key("user_123").unwrap(),
+            Some(key.verifying_key())
+        );
+        assert_eq!(registry.get_key("user_456").unwrap(), None);
+    }
+
+    /// Test that the sled registry persists keys in its own tree
+    #[test]
+    fn sled_registry_round_trip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = sled::open(temp_dir.path().join("keys.db")).unwrap();
+        let registry = SledKeyRegistry::new(&db).unwrap();
+        let key = generate_signing_key();
+
+        registry.register("user_123", key.verifying_key()).unwrap();
+
+        assert_eq!(
+            registry.get_key("user_123").unwrap(),
+            Some(key.verifying_key())
+        );
+        assert_eq!(registry.get_key("user_456").unwrap(), None);
+        assert!(db.get("user_123").unwrap().is_none());
+    }
+
+    /// Test that both registries refuse a second registration, and pick the key in
+    /// force at a timestamp once a user has rotated
+    #[test]
+    fn registries_keep_a_history_of_rotated_keys() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = sled::open(temp_dir.path().join("keys.db")).unwrap();
+        let registries: [Box<dyn KeyRegistry>; 2] = [
+            Box::new(InMemoryKeyRegistry::new()),
+            Box::new(SledKeyRegistry::new(&db).unwrap()),
+        ];
+        let at = |hour| TimeStamp::new_with(2025, 6, 2, hour, 0, 0);
+
+        for registry in &registries {
+            let first = generate_signing_key().verifying_key();
+            let second = generate_signing_key().verifying_key();
+            let third = generate_signing_key().verifying_key();
+
+            assert!(registry.rotate("user_123", first, at(9)).is_err());
+            registry.register("user_123", first).unwrap();
+            assert!(registry.register("user_123", second).is_err());
+            assert_eq!(registry.get_key("user_123").unwrap(), Some(first));
+
+            registry.rotate("user_123", second, at(10)).unwrap();
+            registry.rotate("user_123", third, at(12)).unwrap();
+            assert!(registry.rotate("user_123", first, at(11)).is_err());
+
+            assert_eq!(registry.key_history("user_123").unwrap().len(), 3);
+            assert_eq!(registry.get_key("user_123").unwrap(), Some(third));
+            assert_eq!(registry.key_at("user_123", &at(9)).unwrap(), Some(first));
+            assert_eq!(registry.key_at("user_123", &at(10)).unwrap(), Some(second));
+            assert_eq!(registry.key_at("user_123", &at(11)).unwrap(), Some(second));
+            assert_eq!(registry.key_at("user_123", &at(13)).unwrap(), Some(third));
+            assert_eq!(registry.key_at("user_456", &at(13)).unwrap(), None);
+        }
+    }
+}
+
+// STORE MODULE TESTS
+#[cfg(test)]
+mod store_tests {
+    use super::*;
+
+    /// Exercise the store contract, shared by every backend
+    fn check_store(store: &dyn TradeStore) {
+        assert_eq!(store.get_context("trade_1").unwrap(), None);
+
+        // A new trade only lands when nothing is stored under its ID
+        let batch = WriteBatch::new()
+            .put_context("trade_1", b"v1")
+            .put_details("hash_1", b"details")
+            .insert_index([b"state\0A\0trade_1".to_vec()]);
+        assert!(store.compare_and_swap("trade_1", None, &batch).unwrap());
+        assert!(!store.compare_and_swap("trade_1", None, &batch).unwrap());
+
+        assert_eq!(store.get_context("trade_1").unwrap(), Some(b"v1".to_vec()));
+        assert_eq!(
+            store.get_details("hash_1").unwrap(),
+            Some(b"details".to_vec())
+        );
+
+        // A stale expectation writes nothing, not even the details
+        let stale = WriteBatch::new()
+            .put_context("trade_1", b"v3")
+            .put_details("hash_2", b"other");
+        assert!(
+            !store
+                .compare_and_swap("trade_1", Some(b"v0"), &stale)
+                .unwrap()
+        );
+        assert_eq!(store.get_details("hash_2").unwrap(), None);
+
+        // Index entries are swapped in the same write
+        let next = WriteBatch::new()
+            .put_context("trade_1", b"v2")
+            .remove_index([b"state\0A\0trade_1".to_vec()])
+            .insert_index([b"state\0B\0trade_1".to_vec()]);
+        assert!(
+            store
+                .compare_and_swap("trade_1", Some(b"v1"), &next)
+                .unwrap()
+        );
+        assert!(!store.index_contains(b"state\0A\0trade_1").unwrap());
+        assert_eq!(
+            store.scan_index(b"state\0B\0").unwrap(),
+            vec![b"state\0B\0trade_1".to_vec()]
+        );
+
+        store.put_context("trade_0", b"v1").unwrap();
+        let contexts: Vec<_> = store.iter_contexts().map(Result::unwrap).collect();
+        assert_eq!(
+            contexts,
+            vec![
+                ("trade_0".to_string(), b"v1".to_vec()),
+                ("trade_1".to_string(), b"v2".to_vec()),
+            ]
+        );
+
+        store.replace_index(vec![b"x".to_vec()]).unwrap();
+        assert!(store.scan_index(b"state").unwrap().is_empty());
+        assert!(store.index_contains(b"x").unwrap());
+    }
+
+    /// Test the in-memory store honours the store contract
+    #[test]
+    fn in_memory_store_contract() {
+        check_store(&InMemoryStore::new());
+    }
+
+    /// Test the sled store honours the store contract
+    #[test]
+    fn sled_store_contract() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = sled::open(temp_dir.path().join("store.db")).unwrap();
+        check_store(&SledStore::new(&db).unwrap());
+    }
+
+    /// Test that contexts round trip through a store and are verified on load
+    #[test]
+    fn context_round_trips_through_store() {
+        let store = InMemoryStore::new();
+        let mut ctx = TradeContext::new();
+        ctx.insert_witness(Witness::new(
+            ctx.trade_id.clone(),
+            "user_123".to_string(),
+            TimeStamp::new(),
+            WitnessType::Approve { on_behalf_of: None },
+        ))
+        .unwrap();
+
+        ctx.save_to_store(&store).unwrap();
+        let loaded = TradeContext::load_from_store(&store, &ctx.trade_id).unwrap();
+
+        assert_eq!(loaded.witness_set, ctx.witness_set);
+        assert!(TradeContext::load_from_store(&store, "trade_missing").is_err());
+    }
+}
+
+// CANONICAL MODULE TESTS
+#[cfg(test)]
+mod canonical_tests {
+    use super::*;
+
+    fn rejected(bytes: &[u8]) -> bool {
+        matches!(
+            verify_canonical(bytes),
+            Err(ValidationError::NonCanonical(_))
+        )
+    }
+
+    /// Test that non-shortest integers and lengths are rewritten and rejected
+    #[test]
+    fn non_shortest_arguments() {
+        assert_eq!(canonicalize(&[0x18, 0x05]).unwrap(), vec![0x05]);
+        assert!(rejected(&[0x18, 0x05]));
+        // A one element array with its length in a two byte argument
+        assert_eq!(
+            canonicalize(&[0x99, 0x00, 0x01, 0x00]).unwrap(),
+            vec![0x81, 0x00]
+        );
+        assert!(verify_canonical(&[0x18, 0x18]).is_ok());
+    }
+
+    /// Test that indefinite lengths are made definite
+    #[test]
+    fn indefinite_lengths() {
+        let indefinite_array = [0x9f, 0x01, 0x02, 0xff];
+        assert_eq!(
+            canonicalize(&indefinite_array).unwrap(),
+            vec![0x82, 0x01, 0x02]
+        );
+        assert!(rejected(&indefinite_array));
+
+        // "ab" as two chunks of an indefinite text string
+        let chunked = [0x7f, 0x61, b'a', 0x61, b'b', 0xff];
+        assert_eq!(canonicalize(&chunked).unwrap(), vec![0x62, b'a', b'b']);
+    }
+
+    /// Test that map keys are sorted bytewise and duplicates are refused
+    #[test]
+    fn map_key_order() {
+        let unsorted = [0xa2, 0x02, 0x00, 0x01, 0x00];
+        assert_eq!(
+            canonicalize(&unsorted).unwrap(),
+            vec![0xa2, 0x01, 0x00, 0x02, 0x00]
+        );
+        assert!(rejected(&unsorted));
+        assert!(rejected(&[0xa2, 0x01, 0x00, 0x01, 0x00]));
+    }
+
+    /// Test that floats, truncated input and trailing bytes are refused
+    #[test]
+    fn malformed_input() {
+        assert!(rejected(&[0xf9, 0x3c, 0x00]));
+        assert!(rejected(&[0x82, 0x01]));
+        assert!(rejected(&[0x01, 0x02]));
+    }
+
+    /// Test that nesting beyond the depth limit is refused rather than recursed into
+    #[test]
+    fn deeply_nested_input() {
+        let nested = |depth: usize, head: u8| {
+            let mut bytes = vec![head; depth];
+            bytes.push(0x00);
+            bytes
+        };
+
+        // One-element arrays, and tag 1 on tag 1 on ... on 0
+        assert!(verify_canonical(&nested(MAX_DEPTH, 0x81)).is_ok());
+        assert!(verify_canonical(&nested(MAX_DEPTH, 0xc1)).is_ok());
+        assert!(rejected(&nested(MAX_DEPTH + 1, 0x81)));
+        assert!(rejected(&nested(MAX_DEPTH + 1, 0xc1)));
+        // Enough indefinite-length arrays to overflow the stack without the limit
+        assert!(rejected(&nested(1_000_000, 0x9f)));
+    }
+
+    /// Test that trade details already encode canonically
+    #[test]
+    fn trade_details_are_canonical() {
+        let (_, cbor) = TradeDetails::new()
+            .new_trade_entity("entity_")
+            .new_counter_party("counter_")
+            .set_direction(Direction::Sell)
+            .set_notional_currency(Currency::GBP)
+            .set_notional_amount(u64::MAX)
+            .set_underlying_currency(Currency::EUR)
+            .set_underlying_amount(300)
+            .set_trade_date(TimeStamp::new_with(2025, 1, 1, 0, 0, 0))
+            .set_value_date(TimeStamp::new_with(2025, 1, 3, 0, 0, 0))
+            .set_delivery_date(TimeStamp::new_with(2025, 1, 3, 0, 0, 0))
+            .validate_and_finalise()
+            .unwrap();
+
+        assert!(verify_canonical(&cbor).is_ok());
+    }
+
+    /// Test that a stored context with a non-canonical encoding is refused on load,
+    /// even though a lenient decoder would accept it
+    #[test]
+    fn non_canonical_context_is_rejected() {
+        let ctx = TradeContext::new();
+        let (_, cbor) = ctx.serialize_with_hash().unwrap();
+        assert_eq!(cbor[0], 0x82);
+
+        // Same context, with the outer array length written as a one byte argument
+        let mut padded = vec![0x98, 0x02];
+        padded.extend_from_slice(&cbor[1..]);
+        assert!(minicbor::decode::<TradeContext>(&padded).is_ok());
+
+        let err = TradeContext::from_cbor(&padded).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ValidationError>(),
+            Some(ValidationError::NonCanonical(_))
+        ));
+    }
+}
+
+// SERVICE MODULE TESTS
+#[cfg(test)]
+mod service_tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+    use std::sync::Arc;
+
+    const REQUESTER: &str = "user_requester";
+    const SUBMITTER: &str = "user_submitter";
+    const UPDATER: &str = "user_updater";
+    const OTHER: &str = "user_other";
+
+    /// Service over an in-memory store with a key registered for every user
+    struct Desk {
+        service: TradeService,
+        keys: Vec<(&'static str, SigningKey)>,
+    }
+
+    impl Desk {
+        fn new(duties: SegregationOfDuties) -> Self {
+            let registry = Arc::new(InMemoryKeyRegistry::new());
+            let keys: Vec<_> = [REQUESTER, SUBMITTER, UPDATER, OTHER]
+                .into_iter()
+                .map(|user| (user, generate_signing_key()))
+                .collect();
+            for (user, key) in &keys {
+                registry.register(user, key.verifying_key()).unwrap();
+            }
+
+            let service = TradeService::new(Arc::new(InMemoryStore::new()), registry)
+                .with_segregation_of_duties(duties);
+            Self { service, keys }
+        }
+
+        fn key(&self, user: &str) -> &SigningKey {
+            &self.keys.iter().find(|(id, _)| *id == user).unwrap().1
+        }
+
+        /// A trade requested by REQUESTER, submitted by SUBMITTER and then updated by
+        /// UPDATER. Any one of the four users may approve it.
+        fn pending_trade(&self) -> String {
+            let policy = ApprovalPolicy::quorum(
+                [REQUESTER, SUBMITTER, UPDATER, OTHER]
+                    .map(String::from)
+                    .to_vec(),
+                1,
+            );
+            let ctx = self
+                .service
+                .submit_trade_with_policy(
+                    details(100),
+                    REQUESTER.to_string(),
+                    policy,
+                    SUBMITTER.to_string(),
+                    self.key(SUBMITTER),
+                )
+                .unwrap();
+            self.service
+                .update_trade(
+                    ctx.trade_id.clone(),
+                    details(200),
+                    UPDATER.to_string(),
+                    self.key(UPDATER),
+                )
+                .unwrap();
+            ctx.trade_id
+        }
+
+        /// Bring a fresh trade to the point where `action` is allowed, using OTHER for
+        /// every earlier step, then attempt `action` as `actor`
+        fn attempt(&self, action: &str, actor: &str) -> Result<TradeContext, ServiceError> {
+            let trade_id = self.pending_trade();
+            if action != "approve" {
+                self.service
+                    .approve_trade(trade_id.clone(), OTHER.to_string(), self.key(OTHER))
+                    .unwrap();
+            }
+            if action == "book" {
+                self.service
+                    .execute_trade(trade_id.clone(), OTHER.to_string(), self.key(OTHER))
+                    .unwrap();
+            }
+
+            let (user, key) = (actor.to_string(), self.key(actor));
+            match action {
+                "approve" => self.service.approve_trade(trade_id, user, key),
+                "execute" => self.service.execute_trade(trade_id, user, key),
+                "book" => self.service.book_trade(trade_id, user, 1_000, key),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn details(notional_amount: u64) -> TradeDetails {
+        TradeDetails::new()
+            .set_trade_entity("entity_1abc")
+            .set_counter_party("counter_1xyz")
+            .set_direction(Direction::Buy)
+            .set_notional_currency(Currency::USD)
+            .set_notional_amount(notional_amount)
+            .set_underlying_currency(Currency::EUR)
+            .set_underlying_amount(90)
+            .set_trade_date(TimeStamp::new_with(2025, 1, 1, 0, 0, 0))
+            .set_value_date(TimeStamp::new_with(2025, 1, 2, 0, 0, 0))
+            .set_delivery_date(TimeStamp::new_with(2025, 1, 2, 0, 0, 0))
+    }
+
+    /// Test that a signature made elsewhere over a prepared witness appends exactly that
+    /// witness, and is refused once stale or under a trade_id the service never issued
+    #[test]
+    fn detached_signatures_append_the_prepared_witness() {
+        let desk = Desk::new(SegregationOfDuties::default());
+        let trade_id = desk.pending_trade();
+
+        // What the client signs: the Approve the service will build, linked to the head
+        let signed_at = TimeStamp::new();
+        let mut witness = Witness::new(
+            trade_id.clone(),
+            OTHER.to_string(),
+            signed_at.clone(),
+            WitnessType::Approve { on_behalf_of: None },
+        );
+        witness.parent_hash = desk
+            .service
+            .get_trade(&trade_id)
+            .unwrap()
+            .head_hash()
+            .unwrap();
+        witness.sign(desk.key(OTHER)).unwrap();
+        let signature = DetachedSignature {
+            signed_at,
+            signature: witness.signature.clone().unwrap(),
+            trade_id: None,
+        };
+
+        let stale = DetachedSignature {
+            signed_at: (Utc::now() - chrono::TimeDelta::hours(1)).into(),
+            ..signature.clone()
+        };
+        assert!(matches!(
+            desk.service
+                .approve_trade(trade_id.clone(), OTHER.to_string(), &stale),
+            Err(ServiceError::Validation(
+                ValidationError::UntimelySignature { .. }
+            ))
+        ));
+
+        let ctx = desk
+            .service
+            .approve_trade(trade_id, OTHER.to_string(), &signature)
+            .unwrap();
+        assert_eq!(ctx.witness_set.last(), Some(&witness));
+
+        let unissued = DetachedSignature {
+            trade_id: Some("trade_123".to_string()),
+            ..signature
+        };
+        assert!(matches!(
+            desk.service.submit_trade(
+                details(100),
+                REQUESTER.to_string(),
+                OTHER.to_string(),
+                SUBMITTER.to_string(),
+                &unissued,
+            ),
+            Err(ServiceError::Validation(ValidationError::InvalidTradeId(_)))
+        ));
+    }
+
+    /// Test every guarded action against every principal, with the check on and off
+    #[test]
+    fn segregation_of_duties_matrix() {
+        for duties in [
+            SegregationOfDuties::default(),
+            SegregationOfDuties::strict(),
+        ] {
+            let desk = Desk::new(duties);
+
+            for action in ["approve", "execute", "book"] {
+                for actor in [REQUESTER, SUBMITTER, UPDATER, OTHER] {
+                    let result = desk.attempt(action, actor);
+                    let blocked = duties != SegregationOfDuties::default() && actor != OTHER;
+
+                    match result {
+                        Err(ServiceError::SegregationOfDuties { user_id, .. }) if blocked => {
+                            assert_eq!(user_id, actor);
+                        }
+                        Ok(_) if !blocked => {}
+                        other => panic!(
+                            "{} by {} under {:?}: unexpected {:?}",
+                            action,
+                            actor,
+                            duties,
+                            other.map(|ctx| ctx.current_state())
+                        ),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Test that the four-eyes rule only guards approval
+    #[test]
+    fn four_eyes_only_guards_approval() {
+        let desk = Desk::new(SegregationOfDuties::four_eyes());
+
+        assert!(matches!(
+            desk.attempt("approve", REQUESTER),
+            Err(ServiceError::SegregationOfDuties {
+                action: "Approve",
+                ..
+            })
+        ));
+        assert!(desk.attempt("execute", REQUESTER).is_ok());
+        assert!(desk.attempt("book", UPDATER).is_ok());
+    }
+
+    /// Test that only the latest Update makes its author an originator
+    #[test]
+    fn originators_follow_the_latest_update() {
+        let desk = Desk::new(SegregationOfDuties::four_eyes());
+        let trade_id = desk.pending_trade();
+
+        desk.service
+            .update_trade(
+                trade_id.clone(),
+                details(300),
+                OTHER.to_string(),
+                desk.key(OTHER),
+            )
+            .unwrap();
+
+        let ctx = desk.service.get_trade(&trade_id).unwrap();
+        assert_eq!(ctx.originators(), vec![OTHER, REQUESTER, SUBMITTER]);
+
+        // UPDATER's edit has been superseded, so they may now approve
+        let ctx = desk
+            .service
+            .approve_trade(trade_id, UPDATER.to_string(), desk.key(UPDATER))
+            .unwrap();
+        assert_eq!(ctx.current_state(), TradeState::Approved);
+    }
+}
+
+// CONFIG MODULE TESTS
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[cfg(feature = "config")]
+    const CONFIG_TOML: &str = r#"
+        max_retries = 3
+
+        [segregation_of_duties]
+        approve = true
+
+        [[rules]]
+        name = "large USD buys"
+        direction = "Buy"
+        notional_currency = "USD"
+        min_notional = 1000000
+
+        [[rules.levels]]
+        approvers = ["desk_head"]
+        required = 1
+
+        [[rules.levels]]
+        approvers = ["risk_1", "risk_2", "risk_3"]
+        required = 2
+
+        [[rules]]
+        name = "everything else"
+        levels = [{ approvers = ["desk_head"], required = 1 }]
+    "#;
+
+    fn details(direction: Direction, currency: Currency, notional_amount: u64) -> TradeDetails {
+        TradeDetails::new()
+            .set_counter_party("counter_1xyz")
+            .set_direction(direction)
+            .set_notional_currency(currency)
+            .set_notional_amount(notional_amount)
+    }
+
+    /// Test that a configuration parses from TOML, with unset settings defaulted
+    #[cfg(feature = "config")]
+    #[test]
+    fn config_parses_from_toml() {
+        let config = ServiceConfig::from_toml(CONFIG_TOML).unwrap();
+
+        assert_eq!(config.max_retries, 3);
+        assert_eq!(
+            config.segregation_of_duties,
+            SegregationOfDuties::four_eyes()
+        );
+        assert_eq!(config.rules.len(), 2);
+        assert_eq!(config.rules[0].direction, Some(Direction::Buy));
+        assert_eq!(config.rules[0].levels[1].required, 2);
+        assert_eq!(config.rules[1].min_notional, None);
+
+        assert_eq!(
+            ServiceConfig::from_toml("").unwrap(),
+            ServiceConfig::default()
+        );
+        assert!(ServiceConfig::from_toml("max_retry = 3").is_err());
+        assert_eq!(
+            ServiceConfig::from_toml("max_clock_skew_ms = 500")
+                .unwrap()
+                .max_clock_skew_ms,
+            500
+        );
+    }
+
+    /// Test that the first matching rule wins and unset conditions match anything
+    #[cfg(feature = "config")]
+    #[test]
+    fn route_picks_first_matching_rule() {
+        let config = ServiceConfig::from_toml(CONFIG_TOML).unwrap();
+        let service = TradeService::new(
+            Arc::new(InMemoryStore::new()),
+            Arc::new(InMemoryKeyRegistry::new()),
+        )
+        .with_config(config.clone())
+        .unwrap();
+        let route = |details: TradeDetails| {
+            let policy = service.route(&details).unwrap();
+            let rule = config.rules.iter().find(|rule| rule.policy() == policy);
+            rule.unwrap().name.clone()
+        };
+
+        assert_eq!(
+            route(details(Direction::Buy, Currency::USD, 1_000_000)),
+            "large USD buys"
+        );
+        assert_eq!(
+            route(details(Direction::Buy, Currency::USD, 999_999)),
+            "everything else"
+        );
+        assert_eq!(
+            route(details(Direction::Sell, Currency::USD, 5_000_000)),
+            "everything else"
+        );
+        assert_eq!(
+            route(details(Direction::Buy, Currency::GBP, 5_000_000)),
+            "everything else"
+        );
+
+        let policy = config.rules[0].policy();
+        assert_eq!(policy.required(), 3);
+        assert_eq!(policy.approvers().count(), 4);
+    }
+
+    /// Test the notional range is half-open and counterparty must match exactly
+    #[test]
+    fn rule_conditions() {
+        let rule = RoutingRule {
+            name: "mid".to_string(),
+            direction: None,
+            notional_currency: None,
+            underlying_currency: None,
+            counter_party: Some("counter_1xyz".to_string()),
+            min_notional: Some(100),
+            max_notional: Some(200),
+            levels: vec![],
+        };
+
+        assert!(rule.matches(&details(Direction::Buy, Currency::USD, 100)));
+        assert!(rule.matches(&details(Direction::Sell, Currency::EUR, 199)));
+        assert!(!rule.matches(&details(Direction::Buy, Currency::USD, 99)));
+        assert!(!rule.matches(&details(Direction::Buy, Currency::USD, 200)));
+        assert!(
+            !rule.matches(&details(Direction::Buy, Currency::USD, 150).set_counter_party("other"))
+        );
+    }
+
+    /// Test that rules which can never be satisfied are rejected on load
+    #[cfg(feature = "config")]
+    #[test]
+    fn invalid_rules_are_rejected() {
+        let empty_range = r#"
+            [[rules]]
+            name = "backwards"
+            min_notional = 200
+            max_notional = 100
+            levels = [{ approvers = ["desk_head"], required = 1 }]
+        "#;
+        let too_few = r#"
+            [[rules]]
+            name = "short"
+            levels = [{ approvers = ["desk_head"], required = 2 }]
+        "#;
+
+        for toml in [empty_range, too_few] {
+            let err = ServiceConfig::from_toml(toml).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ValidationError>(),
+                Some(ValidationError::InvalidRoutingRule { .. })
+            ));
+        }
+    }
+    /// Test that a service refuses a config with an unsatisfiable level or a zero cap
+    #[test]
+    fn with_config_rejects_invalid_configs() {
+        let service = || {
+            TradeService::new(
+                Arc::new(InMemoryStore::new()),
+                Arc::new(InMemoryKeyRegistry::new()),
+            )
+        };
+        let routed = |approvers: &[&str], required| ServiceConfig {
+            rules: vec![RoutingRule {
+                name: "desk".to_string(),
+                direction: None,
+                notional_currency: None,
+                underlying_currency: None,
+                counter_party: None,
+                min_notional: None,
+                max_notional: None,
+                levels: vec![ApprovalLevel {
+                    approvers: approvers.iter().map(|id| id.to_string()).collect(),
+                    required,
+                }],
+            }],
+            ..ServiceConfig::default()
+        };
+
+        assert!(service().with_config(routed(&["desk_head"], 1)).is_ok());
+        for config in [
+            routed(&["desk_head"], 0),
+            routed(&["desk_head"], 2),
+            routed(&[], 0),
+        ] {
+            assert!(matches!(
+                service().with_config(config),
+                Err(ValidationError::InvalidRoutingRule { .. })
+            ));
+        }
+
+        let capped = ServiceConfig {
+            limits: vec![NotionalLimit {
+                scope: LimitScope::CounterParty("counter_1xyz".to_string()),
+                currency: Currency::USD,
+                cap: 0,
+            }],
+            ..ServiceConfig::default()
+        };
+        assert!(matches!(
+            service().with_config(capped),
+            Err(ValidationError::InvalidLimit(_))
+        ));
+    }
+
+    /// Test that limits parse from TOML and report headroom when breached
+    #[cfg(feature = "config")]
+    #[test]
+    fn limits_check_headroom() {
+        let config = ServiceConfig::from_toml(
+            r#"
+            [[limits]]
+            counter_party = "counter_1xyz"
+            currency = "USD"
+            cap = 1000
+            "#,
+        )
+        .unwrap();
+        let limit = &config.limits[0];
+        assert_eq!(
+            limit.scope,
+            LimitScope::CounterParty("counter_1xyz".to_string())
+        );
+
+        assert!(limit.applies_to(&details(Direction::Sell, Currency::USD, 1)));
+        assert!(!limit.applies_to(&details(Direction::Buy, Currency::GBP, 1)));
+        assert!(
+            !limit
+                .applies_to(&details(Direction::Buy, Currency::USD, 1).set_counter_party("other"))
+        );
+
+        assert!(limit.check(600, 400).is_ok());
+        assert!(matches!(
+            limit.check(600, 401),
+            Err(ServiceError::LimitBreach {
+                cap: 1000,
+                exposure: 600,
+                requested: 401,
+                headroom: 400,
+                ..
+            })
+        ));
+        // Exposure already past the cap, e.g. after the cap was lowered
+        assert!(matches!(
+            limit.check(1200, 1),
+            Err(ServiceError::LimitBreach { headroom: 0, .. })
+        ));
+    }
+}
+
+// DELEGATION MODULE TESTS
+#[cfg(test)]
+mod delegation_tests {
+    use super::*;
+
+    fn delegation() -> Delegation {
+        Delegation::new(
+            "user_a",
+            "user_b",
+            TimeStamp::new_with(2025, 6, 2, 0, 0, 0),
+            TimeStamp::new_with(2025, 6, 9, 0, 0, 0),
+        )
+    }
+
+    /// Test the validity window is half-open and the ceiling is inclusive
+    #[test]
+    fn delegation_covers_window_and_ceiling() {
+        let delegation = delegation().with_max_notional(1_000);
+        let at = |day| TimeStamp::new_with(2025, 6, day, 0, 0, 0);
+
+        assert!(delegation.covers(1_000, &at(2)));
+        assert!(delegation.covers(1, &at(8)));
+        assert!(!delegation.covers(1, &at(1)));
+        assert!(!delegation.covers(1, &at(9)));
+        assert!(!delegation.covers(1_001, &at(5)));
+    }
+
+    /// Test that delegations to oneself or with an empty window are rejected
+    #[test]
+    fn invalid_delegations_are_rejected() {
+        assert!(delegation().validate().is_ok());
+
+        let to_self = Delegation {
+            delegate: "user_a".to_string(),
+            ..delegation()
+        };
+        let backwards = Delegation {
+            valid_until: TimeStamp::new_with(2025, 6, 1, 0, 0, 0),
+            ..delegation()
+        };
+        for invalid in [to_self, backwards] {
+            assert!(matches!(
+                invalid.validate(),
+                Err(ValidationError::InvalidDelegation(_))
+            ));
+        }
+    }
+
+    /// Test both stores replace, list and revoke delegations per delegator
+    #[test]
+    fn stores_keep_one_delegation_per_pair() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = sled::open(temp_dir.path().join("delegations.db")).unwrap();
+        let stores: [Box<dyn DelegationStore>; 2] = [
+            Box::new(InMemoryDelegationStore::new()),
+            Box::new(SledDelegationStore::new(&db).unwrap()),
+        ];
+
+        for store in stores {
+            store.put(&delegation()).unwrap();
+            store.put(&delegation().with_max_notional(5)).unwrap();
+            store
+                .put(&Delegation::new(
+                    "user_ab",
+                    "user_c",
+                    TimeStamp::new_with(2025, 6, 2, 0, 0, 0),
+                    TimeStamp::new_with(2025, 6, 9, 0, 0, 0),
+                ))
+                .unwrap();
+
+            assert_eq!(
+                store.delegations_from("user_a").unwrap(),
+                vec![delegation().with_max_notional(5)]
+            );
+            assert!(store.revoke("user_a", "user_b").unwrap());
+            assert!(!store.revoke("user_a", "user_b").unwrap());
+            assert!(store.delegations_from("user_a").unwrap().is_empty());
+            assert_eq!(store.delegations_from("user_ab").unwrap().len(), 1);
+        }
+    }
+}
+
+// CLOCK MODULE TESTS
+#[cfg(test)]
+mod clock_tests {
+    use super::*;
+    use chrono::TimeDelta;
+    use trade_approval::clock::{Clock, FixedClock, SystemClock};
+
+    /// Test that a fixed clock only moves when it is set or advanced
+    #[test]
+    fn fixed_clock_moves_only_when_told() {
+        let clock = FixedClock::new(TimeStamp::new_with(2025, 6, 2, 9, 0, 0));
+        assert_eq!(clock.now(), clock.now());
+
+        clock.advance(TimeDelta::minutes(90));
+        assert_eq!(clock.now(), TimeStamp::new_with(2025, 6, 2, 10, 30, 0));
+        clock.advance(TimeDelta::hours(-2));
+        assert_eq!(clock.now(), TimeStamp::new_with(2025, 6, 2, 8, 30, 0));
+        clock.set(TimeStamp::new_with(2025, 1, 1, 0, 0, 0));
+        assert_eq!(clock.now(), TimeStamp::new_with(2025, 1, 1, 0, 0, 0));
+
+        assert!(SystemClock.now().to_datetime_utc().year() >= 2025);
+    }
+
+    /// Test that the service tells the time by the clock it was given
+    #[test]
+    fn service_reads_its_clock() {
+        let clock = std::sync::Arc::new(FixedClock::new(TimeStamp::new_with(2025, 6, 2, 9, 0, 0)));
+        let service = TradeService::new(
+            std::sync::Arc::new(InMemoryStore::new()),
+            std::sync::Arc::new(InMemoryKeyRegistry::new()),
+        )
+        .with_clock(clock.clone());
+
+        assert_eq!(service.now(), TimeStamp::new_with(2025, 6, 2, 9, 0, 0));
+        clock.advance(TimeDelta::days(1));
+        assert_eq!(service.now(), TimeStamp::new_with(2025, 6, 3, 9, 0, 0));
+    }
+
+    /// Test that a witness may trail the one it follows by at most the skew
+    #[test]
+    fn check_follows_allows_configured_skew() {
+        let witness = |sec| {
+            Witness::new(
+                "trade_clock".to_string(),
+                "user_123".to_string(),
+                TimeStamp::new_with(2025, 6, 2, 9, 0, sec),
+                WitnessType::Cancel,
+            )
+        };
+
+        assert!(
+            witness(5)
+                .check_follows(&witness(5), 1, TimeDelta::zero())
+                .is_ok()
+        );
+        assert!(matches!(
+            witness(3).check_follows(&witness(5), 1, TimeDelta::zero()),
+            Err(ValidationError::TimestampRegression { index: 1, .. })
+        ));
+        assert!(
+            witness(3)
+                .check_follows(&witness(5), 1, TimeDelta::seconds(2))
+                .is_ok()
+        );
+    }
+
+    /// Test that verify_chain reports the earliest violation, whether a link or a
+    /// timestamp
+    #[test]
+    fn verify_chain_reports_the_earliest_violation() {
+        let mut ctx = TradeContext::new_with("trade_clock".to_string());
+        for (sec, witness_type) in [
+            (
+                5,
+                WitnessType::Update {
+                    details_hash: "abc".to_string(),
+                },
+            ),
+            (3, WitnessType::Cancel),
+            (9, WitnessType::Cancel),
+        ] {
+            ctx.insert_witness(Witness::new(
+                "trade_clock".to_string(),
+                "user_123".to_string(),
+                TimeStamp::new_with(2025, 6, 2, 9, 0, sec),
+                witness_type,
+            ))
+            .unwrap();
+        }
+        ctx.witness_set[2].parent_hash = Some("tampered".to_string());
+
+        let err = ctx.verify_chain().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ValidationError>(),
+            Some(ValidationError::TimestampRegression { index: 1, .. })
+        ));
+        assert!(ctx.verify_chain_with_skew(TimeDelta::seconds(2)).is_err());
+    }
+}
+
+// CALENDAR MODULE TESTS
+#[cfg(test)]
+mod calendar_tests {
+    use super::*;
+    use chrono::{NaiveDate, Weekday};
+    use trade_approval::calendar::{BusinessCalendars, HolidayCalendar, MAX_ROLL_DAYS};
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, month, day).unwrap()
+    }
+
+    fn calendars() -> BusinessCalendars {
+        BusinessCalendars::new()
+            .with_calendar(
+                Currency::USD,
+                HolidayCalendar::new().with_holiday(date(7, 4)),
+            )
+            .with_calendar(
+                Currency::GBP,
+                HolidayCalendar::new()
+                    .with_weekend(&[Weekday::Fri, Weekday::Sat])
+                    .unwrap(),
+            )
+    }
+
+    /// Test that a day must be open in every currency to be a joint business day
+    #[test]
+    fn joint_business_days_need_every_currency_open() {
+        let calendars = calendars();
+
+        // Friday 4 July 2025 is a US holiday
+        assert!(calendars.is_business_day(date(7, 4), &[Currency::EUR]));
+        assert!(!calendars.is_business_day(date(7, 4), &[Currency::EUR, Currency::USD]));
+        // GBP here closes on Friday and Saturday, but trades on Sunday
+        assert!(
+            calendars
+                .calendar(Currency::GBP)
+                .is_business_day(date(7, 6))
+        );
+        assert!(!calendars.is_business_day(date(7, 6), &[Currency::GBP, Currency::EUR]));
+
+        assert_eq!(
+            calendars
+                .roll_forward(date(7, 4), &[Currency::USD, Currency::EUR])
+                .unwrap(),
+            date(7, 7)
+        );
+        assert_eq!(
+            calendars
+                .roll_forward(date(7, 3), &[Currency::USD])
+                .unwrap(),
+            date(7, 3)
+        );
+    }
+
+    /// Test that spot is two joint business days on, skipping weekends and holidays
+    #[test]
+    fn spot_date_skips_closed_days() {
+        let calendars = calendars();
+
+        // Wednesday + 2 lands on the US holiday, so USD/EUR spot rolls to Monday
+        assert_eq!(
+            calendars.spot_date(date(7, 2), &[Currency::EUR]).unwrap(),
+            date(7, 4)
+        );
+        assert_eq!(
+            calendars
+                .spot_date(date(7, 2), &[Currency::USD, Currency::EUR])
+                .unwrap(),
+            date(7, 7)
+        );
+        // Thursday + 2 crosses the weekend
+        assert_eq!(
+            BusinessCalendars::new()
+                .spot_date(date(7, 10), &[Currency::EUR])
+                .unwrap(),
+            date(7, 14)
+        );
+    }
+
+    /// Test that settlement dates on closed days are refused with the next good date
+    #[test]
+    fn validate_rejects_dates_on_closed_days() {
+        let details = |value_day| {
+            TradeDetails::new()
+                .set_notional_currency(Currency::USD)
+                .set_underlying_currency(Currency::EUR)
+                .set_trade_date(TimeStamp::new_with(2025, 7, 2, 0, 0, 0))
+                .set_value_date(TimeStamp::new_with(2025, 7, value_day, 0, 0, 0))
+                .set_delivery_date(TimeStamp::new_with(2025, 7, 7, 0, 0, 0))
+        };
+        let calendars = calendars();
+
+        assert!(calendars.validate(&details(3)).is_ok());
+        let err = calendars.validate(&details(4)).unwrap_err();
+        assert!(matches!(
+            &err,
+            ValidationError::NonBusinessDay { field: "value_date", currencies, next, .. }
+                if currencies == "USD" && *next == date(7, 7)
+        ));
+
+        // A weekend covering the whole week could never settle
+        let every_day = [
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+            Weekday::Sat,
+            Weekday::Sun,
+        ];
+        assert!(HolidayCalendar::new().with_weekend(&every_day).is_err());
+        #[cfg(feature = "config")]
+        assert!(BusinessCalendars::from_toml("[USD]\nholidays = [\"4 July\"]").is_err());
+    }
+
+    /// Test that currencies whose weekends cover the week between them fail to roll
+    /// instead of searching without end
+    #[test]
+    fn rolling_without_a_joint_business_day_fails() {
+        let calendars = BusinessCalendars::new()
+            .with_calendar(
+                Currency::USD,
+                HolidayCalendar::new()
+                    .with_weekend(&[Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu])
+                    .unwrap(),
+            )
+            .with_calendar(
+                Currency::EUR,
+                HolidayCalendar::new()
+                    .with_weekend(&[Weekday::Fri, Weekday::Sat, Weekday::Sun])
+                    .unwrap(),
+            );
+        let currencies = [Currency::USD, Currency::EUR];
+
+        assert!(matches!(
+            calendars.roll_forward(date(7, 4), &currencies),
+            Err(ValidationError::NoBusinessDay { date: from, days: MAX_ROLL_DAYS, .. })
+                if from == date(7, 4)
+        ));
+        assert!(matches!(
+            calendars.spot_date(date(7, 2), &currencies),
+            Err(ValidationError::NoBusinessDay { .. })
+        ));
+        // Each currency still settles on its own
+        assert_eq!(
+            calendars
+                .roll_forward(date(7, 4), &[Currency::USD])
+                .unwrap(),
+            date(7, 4)
+        );
+
+        let details = TradeDetails::new()
+            .set_notional_currency(Currency::USD)
+            .set_underlying_currency(Currency::EUR)
+            .set_value_date(TimeStamp::new_with(2025, 7, 4, 0, 0, 0));
+        assert!(matches!(
+            calendars.validate(&details),
+            Err(ValidationError::NoBusinessDay { .. })
+        ));
+    }
+
+    /// Test that counting business days off the end of the calendar reports the days
+    /// asked for, not a failed search
+    #[test]
+    fn adding_days_past_the_last_date_fails() {
+        // Open every day, so only the end of the calendar can stop the count
+        let calendars = BusinessCalendars::new().with_calendar(
+            Currency::USD,
+            HolidayCalendar::new().with_weekend(&[]).unwrap(),
+        );
+        let last = NaiveDate::MAX.pred_opt().unwrap();
+
+        assert_eq!(
+            calendars
+                .add_business_days(last, 1, &[Currency::USD])
+                .unwrap(),
+            NaiveDate::MAX
+        );
+        assert!(matches!(
+            calendars.add_business_days(last, 5, &[Currency::USD]),
+            Err(ValidationError::DateOutOfRange { date, days: 5 }) if date == last
+        ));
+    }
 }
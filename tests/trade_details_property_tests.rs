@@ -323,3 +323,73 @@ mod extensive_tests {
         }
     }
 }
+
+// BUSINESS-DAY CALENDAR PROPERTIES
+
+#[cfg(test)]
+mod calendar_tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use trade_approval::calendar::{BusinessCalendars, HolidayCalendar};
+
+    /// Strategy to generate a date in 2025
+    fn date_strategy() -> impl Strategy<Value = NaiveDate> {
+        (0u64..365).prop_map(|offset| {
+            NaiveDate::from_ymd_opt(2025, 1, 1).unwrap() + chrono::Days::new(offset)
+        })
+    }
+
+    /// Strategy to generate calendars with a few random holidays per currency
+    fn calendars_strategy() -> impl Strategy<Value = BusinessCalendars> {
+        prop::collection::vec((currency_strategy(), date_strategy()), 0..20).prop_map(|holidays| {
+            let mut per_currency: Vec<(Currency, HolidayCalendar)> = Vec::new();
+            for (currency, date) in holidays {
+                match per_currency.iter_mut().find(|(c, _)| *c == currency) {
+                    Some((_, calendar)) => *calendar = calendar.clone().with_holiday(date),
+                    None => {
+                        per_currency.push((currency, HolidayCalendar::new().with_holiday(date)))
+                    }
+                }
+            }
+            per_currency.into_iter().fold(
+                BusinessCalendars::new(),
+                |calendars, (currency, calendar)| calendars.with_calendar(currency, calendar),
+            )
+        })
+    }
+
+    proptest! {
+        /// Property: Rolling forward lands on the first joint business day at or after
+        /// the date, and spot is a later joint business day
+        #[test]
+        fn prop_roll_forward_and_spot_land_on_business_days(
+            calendars in calendars_strategy(),
+            date in date_strategy(),
+            currency1 in currency_strategy(),
+            currency2 in currency_strategy(),
+        ) {
+            let currencies = [currency1, currency2];
+
+            let rolled = calendars.roll_forward(date, &currencies).unwrap();
+            prop_assert!(rolled >= date);
+            prop_assert!(calendars.is_business_day(rolled, &currencies));
+            prop_assert!(
+                date.iter_days()
+                    .take_while(|day| *day < rolled)
+                    .all(|day| !calendars.is_business_day(day, &currencies)),
+                "No business day is skipped"
+            );
+
+            let spot = calendars.spot_date(date, &currencies).unwrap();
+            prop_assert!(spot > date);
+            prop_assert!(calendars.is_business_day(spot, &currencies));
+            let business_days_between = date
+                .iter_days()
+                .skip(1)
+                .take_while(|day| *day <= spot)
+                .filter(|day| calendars.is_business_day(*day, &currencies))
+                .count();
+            prop_assert_eq!(business_days_between, 2);
+        }
+    }
+}
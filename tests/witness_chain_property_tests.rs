@@ -10,7 +10,8 @@
 
 use proptest::prelude::*;
 use trade_approval::{
-    context::{TradeContext, TradeState, Witness, WitnessType},
+    canonical::{canonicalize, verify_canonical},
+    context::{ApprovalPolicy, Note, TradeContext, TradeState, Witness, WitnessType},
     trade::TimeStamp,
 };
 
@@ -23,6 +24,11 @@ use trade_approval::{
 // 5. Serialization correctness - critical for persistence
 // 6. Basic approval workflow - validates happy path
 // 7. Update invalidation - validates critical business rule
+// 8. Hash linking - every inserted chain verifies, any tampering is detected
+// 9. Transition table - every (state, witness type) pair agrees with state derivation
+// 10. Canonical encoding - hashed bytes are canonical and canonicalisation is stable
+// 11. Quorum approval - approved exactly when enough distinct members have approved
+// 12. Rejection - a rejected trade stays rejected until an Update resubmits it
 //
 // What these tests DON'T cover (deliberately):
 //
@@ -39,28 +45,50 @@ fn witness_type_strategy() -> impl Strategy<Value = WitnessType> {
                 details_hash: format!("hash_{}", h),
                 requester_id: format!("user_{}", r),
                 approver_id: format!("user_{}", a),
+                approval_policy: None,
             }
         }),
-        Just(WitnessType::Approve),
+        Just(WitnessType::Approve { on_behalf_of: None }),
         Just(WitnessType::Cancel),
         any::<u32>().prop_map(|h| WitnessType::Update {
             details_hash: format!("hash_{}", h),
         }),
         Just(WitnessType::SendToExecute),
         any::<u64>().prop_map(|strike| WitnessType::Book { strike }),
+        "[a-z ]{0,12}".prop_map(|reason| WitnessType::Reject { reason }),
     ]
 }
 
+/// Strategy to generate a note, empty about half the time
+fn note_strategy() -> impl Strategy<Value = Note> {
+    (
+        prop::option::of("[a-z ]{0,12}"),
+        prop::collection::vec(("[a-z]{1,6}", "[a-z0-9]{0,6}"), 0..=2),
+    )
+        .prop_map(|(reason, tags)| {
+            tags.into_iter().fold(
+                Note {
+                    reason,
+                    tags: Vec::new(),
+                },
+                |note, (key, value)| note.tag(key, value),
+            )
+        })
+}
+
 /// Strategy to generate a witness with a given trade_id
 fn witness_strategy(trade_id: String) -> impl Strategy<Value = Witness> {
-    (any::<u32>(), witness_type_strategy()).prop_map(move |(user_num, witness_type)| {
-        Witness::new(
-            trade_id.clone(),
-            format!("user_{}", user_num),
-            TimeStamp::new(),
-            witness_type,
-        )
-    })
+    (any::<u32>(), witness_type_strategy(), note_strategy()).prop_map(
+        move |(user_num, witness_type, note)| {
+            Witness::new(
+                trade_id.clone(),
+                format!("user_{}", user_num),
+                TimeStamp::new(),
+                witness_type,
+            )
+            .with_note(note)
+        },
+    )
 }
 
 /// Strategy to generate a sequence of witnesses (1 to 10 witnesses)
@@ -81,6 +109,7 @@ fn valid_workflow_strategy(trade_id: String) -> impl Strategy<Value = Vec<Witnes
                 details_hash: format!("hash_{}", h),
                 requester_id: format!("user_{}", r),
                 approver_id: format!("user_{}", a),
+                approval_policy: None,
             },
         );
 
@@ -105,7 +134,7 @@ proptest! {
         let mut ctx = TradeContext::new_with("trade_test123".to_string());
 
         for witness in witnesses {
-            ctx.insert_witness(witness);
+            ctx.insert_witness(witness).unwrap();
         }
 
         // Call current_state multiple times - should always return the same value
@@ -142,7 +171,7 @@ proptest! {
 
         // Add initial witnesses
         for witness in initial_witnesses {
-            ctx.insert_witness(witness);
+            ctx.insert_witness(witness).unwrap();
         }
 
         // Add terminal witness
@@ -152,7 +181,7 @@ proptest! {
             TimeStamp::new(),
             terminal_type.clone(),
         );
-        ctx.insert_witness(terminal_witness);
+        ctx.insert_witness(terminal_witness).unwrap();
 
         let terminal_state = ctx.current_state();
         prop_assert!(
@@ -162,7 +191,7 @@ proptest! {
 
         // Add more witnesses
         for witness in additional_witnesses.iter() {
-            ctx.insert_witness(witness.clone());
+            ctx.insert_witness(witness.clone()).unwrap();
         }
 
         let final_state = ctx.current_state();
@@ -202,7 +231,7 @@ proptest! {
         let mut ctx = TradeContext::new_with("trade_test789".to_string());
 
         for witness in witnesses {
-            ctx.insert_witness(witness);
+            ctx.insert_witness(witness).unwrap();
         }
 
         let state = ctx.current_state();
@@ -234,7 +263,7 @@ proptest! {
         let mut original_ctx = TradeContext::new_with("trade_test999".to_string());
 
         for witness in witnesses {
-            original_ctx.insert_witness(witness);
+            original_ctx.insert_witness(witness).unwrap();
         }
 
         let original_state = original_ctx.current_state();
@@ -287,6 +316,7 @@ proptest! {
                 details_hash: format!("hash_{}", hash_num),
                 requester_id: format!("user_{}", requester_num),
                 approver_id: format!("user_{}", approver_num),
+                approval_policy: None,
             },
         );
 
@@ -294,17 +324,17 @@ proptest! {
             "trade_approval_test".to_string(),
             format!("user_{}", approver_num),
             TimeStamp::new(),
-            WitnessType::Approve,
+            WitnessType::Approve { on_behalf_of: None },
         );
 
-        ctx.insert_witness(submit);
+        ctx.insert_witness(submit).unwrap();
         prop_assert_eq!(
             &ctx.current_state(),
             &TradeState::PendingApproval,
             "After Submit, state should be PendingApproval"
         );
 
-        ctx.insert_witness(approve);
+        ctx.insert_witness(approve).unwrap();
         prop_assert_eq!(
             &ctx.current_state(),
             &TradeState::Approved,
@@ -327,7 +357,7 @@ proptest! {
 
         // Add initial witnesses (starts with Submit)
         for witness in initial_witnesses {
-            ctx.insert_witness(witness);
+            ctx.insert_witness(witness).unwrap();
         }
 
         // Skip this test if there's already a Book witness (terminal state)
@@ -346,9 +376,9 @@ proptest! {
             "trade_update_test".to_string(),
             "user_approver".to_string(),
             TimeStamp::new(),
-            WitnessType::Approve,
+            WitnessType::Approve { on_behalf_of: None },
         );
-        ctx.insert_witness(approve);
+        ctx.insert_witness(approve).unwrap();
 
         // State might be Approved or might be something else depending on witnesses
         // But after adding Update, it should definitely be PendingApproval
@@ -360,7 +390,7 @@ proptest! {
                 details_hash: format!("hash_{}", update_hash),
             },
         );
-        ctx.insert_witness(update);
+        ctx.insert_witness(update).unwrap();
 
         prop_assert_eq!(
             &ctx.current_state(),
@@ -369,3 +399,374 @@ proptest! {
         );
     }
 }
+
+// HASH-LINKED CHAIN PROPERTIES
+
+proptest! {
+    /// Property: Any chain built through insert_witness verifies
+    ///
+    /// insert_witness links each witness to the current head, so regardless of the
+    /// witness sequence the resulting chain must pass verify_chain(), and must still
+    /// pass after a CBOR round-trip.
+    #[test]
+    fn prop_inserted_chain_always_verifies(
+        witnesses in witness_sequence_strategy("trade_chain_test".to_string())
+    ) {
+        let mut ctx = TradeContext::new_with("trade_chain_test".to_string());
+
+        for witness in witnesses {
+            ctx.insert_witness(witness).unwrap();
+        }
+
+        prop_assert!(ctx.verify_chain().is_ok(), "Linked chain should verify");
+
+        let (_hash, cbor) = ctx.serialize_with_hash()
+            .expect("Serialization should succeed");
+        let decoded_ctx: TradeContext = minicbor::decode(&cbor)
+            .expect("Deserialization should succeed");
+
+        prop_assert!(decoded_ctx.verify_chain().is_ok(), "Decoded chain should verify");
+    }
+
+    /// Property: Tampering with any witness except the head breaks the chain
+    ///
+    /// Changing the user on a witness changes its hash, so the witness after it
+    /// no longer points at its parent. The head has no successor to commit to it,
+    /// which is why the head itself is excluded.
+    #[test]
+    fn prop_tampered_witness_breaks_chain(
+        witnesses in prop::collection::vec(
+            witness_strategy("trade_tamper_test".to_string()),
+            2..=10
+        ),
+        target in any::<prop::sample::Index>(),
+    ) {
+        let mut ctx = TradeContext::new_with("trade_tamper_test".to_string());
+
+        for witness in witnesses {
+            ctx.insert_witness(witness).unwrap();
+        }
+
+        let index = target.index(ctx.witness_set.len() - 1);
+        ctx.witness_set[index].user_id.push_str("_forged");
+
+        prop_assert!(ctx.verify_chain().is_err(), "Tampered chain should fail verification");
+    }
+}
+
+// TRANSITION TABLE
+
+/// One representative of every witness type
+fn all_witness_types() -> Vec<WitnessType> {
+    vec![
+        WitnessType::Submit {
+            details_hash: "hash_1".to_string(),
+            requester_id: "user_1".to_string(),
+            approver_id: "user_2".to_string(),
+            approval_policy: None,
+        },
+        WitnessType::Approve { on_behalf_of: None },
+        WitnessType::Cancel,
+        WitnessType::Update {
+            details_hash: "hash_2".to_string(),
+        },
+        WitnessType::SendToExecute,
+        WitnessType::Book { strike: 100 },
+        WitnessType::Reject {
+            reason: "wrong strike".to_string(),
+        },
+    ]
+}
+
+/// Every state, including Executed which no witness chain currently derives
+fn all_states() -> Vec<TradeState> {
+    vec![
+        TradeState::Draft,
+        TradeState::PendingApproval,
+        TradeState::Approved,
+        TradeState::Rejected,
+        TradeState::Cancelled,
+        TradeState::SentToExecute,
+        TradeState::Executed,
+        TradeState::Booked,
+    ]
+}
+
+/// A witness chain whose derived state is `state`, `None` if no chain derives it
+fn chain_for_state(state: &TradeState) -> Option<Vec<WitnessType>> {
+    let [submit, approve, cancel, _update, execute, book, reject] =
+        all_witness_types().try_into().expect("seven witness types");
+
+    match state {
+        TradeState::Draft => Some(vec![]),
+        TradeState::PendingApproval => Some(vec![submit]),
+        TradeState::Approved => Some(vec![submit, approve]),
+        TradeState::Rejected => Some(vec![submit, reject]),
+        TradeState::Cancelled => Some(vec![submit, cancel]),
+        TradeState::SentToExecute => Some(vec![submit, approve, execute]),
+        TradeState::Booked => Some(vec![submit, approve, execute, book]),
+        TradeState::Executed => None,
+    }
+}
+
+/// The (state, witness type) pairs the workflow allows
+fn is_allowed(state: &TradeState, action: &WitnessType) -> bool {
+    matches!(
+        (state, action),
+        (TradeState::Draft, WitnessType::Submit { .. })
+            | (TradeState::PendingApproval, WitnessType::Approve { .. })
+            | (TradeState::PendingApproval, WitnessType::Update { .. })
+            | (TradeState::PendingApproval, WitnessType::Cancel)
+            | (TradeState::PendingApproval, WitnessType::Reject { .. })
+            | (TradeState::Rejected, WitnessType::Update { .. })
+            | (TradeState::Rejected, WitnessType::Cancel)
+            | (TradeState::Approved, WitnessType::Update { .. })
+            | (TradeState::Approved, WitnessType::SendToExecute)
+            | (TradeState::Approved, WitnessType::Cancel)
+            | (TradeState::SentToExecute, WitnessType::Book { .. })
+            | (TradeState::SentToExecute, WitnessType::Cancel)
+            | (TradeState::Executed, WitnessType::Book { .. })
+    )
+}
+
+/// The state an allowed action leads to under a single-approver policy, where the
+/// first Approve approves the trade
+fn single_approver_next(action: &WitnessType) -> TradeState {
+    match action {
+        WitnessType::Submit { .. } | WitnessType::Update { .. } => TradeState::PendingApproval,
+        WitnessType::Approve { .. } => TradeState::Approved,
+        WitnessType::Reject { .. } => TradeState::Rejected,
+        WitnessType::Cancel => TradeState::Cancelled,
+        WitnessType::SendToExecute => TradeState::SentToExecute,
+        WitnessType::Book { .. } => TradeState::Booked,
+    }
+}
+
+/// Exhaustive: every (state, witness type) pair is allowed or denied as the workflow
+/// specifies, and every allowed transition leads to the state that current_state()
+/// derives once the witness is appended.
+#[test]
+fn transition_table_covers_all_pairs() {
+    for state in all_states() {
+        for action in all_witness_types() {
+            let verdict = state.transition(&action);
+
+            assert_eq!(
+                verdict.is_ok(),
+                is_allowed(&state, &action),
+                "Unexpected verdict for {:?} + {}: {:?}",
+                state,
+                action.name(),
+                verdict
+            );
+
+            let Some(chain) = chain_for_state(&state) else {
+                continue;
+            };
+
+            let mut ctx = TradeContext::new_with("trade_table_test".to_string());
+            for witness_type in chain {
+                ctx.insert_witness(Witness::new(
+                    "trade_table_test".to_string(),
+                    "user_1".to_string(),
+                    TimeStamp::new(),
+                    witness_type,
+                ))
+                .unwrap();
+            }
+            assert_eq!(ctx.current_state(), state);
+
+            if verdict.is_ok() {
+                ctx.insert_witness(Witness::new(
+                    "trade_table_test".to_string(),
+                    "user_1".to_string(),
+                    TimeStamp::new(),
+                    action.clone(),
+                ))
+                .unwrap();
+                assert_eq!(
+                    ctx.current_state(),
+                    single_approver_next(&action),
+                    "Derived state disagrees with table for {:?} + {}",
+                    state,
+                    action.name()
+                );
+            }
+        }
+    }
+}
+
+proptest! {
+    /// Property: Chains built only from allowed transitions derive the expected state
+    ///
+    /// Random witness sequences are filtered through the transition table. After every
+    /// accepted witness, current_state() must equal the state that witness leads to, so
+    /// the table and the derivation logic can never drift apart.
+    #[test]
+    fn prop_table_guided_chain_matches_derivation(
+        actions in prop::collection::vec(witness_type_strategy(), 0..=20)
+    ) {
+        let mut ctx = TradeContext::new_with("trade_guided_test".to_string());
+        let mut predicted = TradeState::Draft;
+
+        for action in actions {
+            if predicted.transition(&action).is_err() {
+                continue;
+            }
+            let next = single_approver_next(&action);
+
+            ctx.insert_witness(Witness::new(
+                "trade_guided_test".to_string(),
+                "user_1".to_string(),
+                TimeStamp::new(),
+                action,
+            )).unwrap();
+            predicted = next;
+
+            prop_assert_eq!(&ctx.current_state(), &predicted);
+        }
+    }
+}
+
+// CANONICAL ENCODING PROPERTIES
+
+proptest! {
+    /// Property: the bytes a context is hashed over are canonical, and identical to
+    /// minicbor's own output for our types, so canonicalisation never changes a hash
+    /// that was computed before it was introduced.
+    #[test]
+    fn prop_context_encoding_is_canonical(
+        witnesses in witness_sequence_strategy("trade_canonical".to_string())
+    ) {
+        let mut ctx = TradeContext::new_with("trade_canonical".to_string());
+        for witness in witnesses {
+            ctx.insert_witness(witness).unwrap();
+        }
+
+        let (_, cbor) = ctx.serialize_with_hash().expect("Serialization should succeed");
+
+        prop_assert!(verify_canonical(&cbor).is_ok());
+        prop_assert_eq!(cbor, minicbor::to_vec(&ctx).unwrap());
+    }
+
+    /// Property: an integer written with an oversized argument canonicalises to the
+    /// shortest form, which is then a fixed point
+    #[test]
+    fn prop_canonical_ints_are_shortest(value in any::<u64>()) {
+        let mut widest = vec![0x1b];
+        widest.extend(value.to_be_bytes());
+
+        let canonical = canonicalize(&widest).unwrap();
+
+        prop_assert_eq!(&canonical, &minicbor::to_vec(value).unwrap());
+        prop_assert_eq!(canonicalize(&canonical).unwrap(), canonical.clone());
+        prop_assert_eq!(verify_canonical(&widest).is_ok(), value > u64::from(u32::MAX));
+    }
+}
+
+// QUORUM APPROVAL PROPERTIES
+
+proptest! {
+    /// Property: under a single-level quorum policy the trade is Approved exactly when
+    /// the number of distinct members among the approvers reaches the quorum
+    ///
+    /// Approvals from outside the policy and repeat approvals must never count.
+    #[test]
+    fn prop_quorum_counts_distinct_members(
+        members in 1usize..=5,
+        required_seed in any::<u32>(),
+        approvals in prop::collection::vec(0usize..8, 0..=10)
+    ) {
+        let approvers: Vec<String> = (0..members).map(|n| format!("user_{}", n)).collect();
+        let required = required_seed % members as u32 + 1;
+        let policy = ApprovalPolicy::quorum(approvers.clone(), required);
+
+        let mut ctx = TradeContext::new_with("trade_quorum".to_string());
+        ctx.insert_witness(Witness::new(
+            "trade_quorum".to_string(),
+            "user_requester".to_string(),
+            TimeStamp::new(),
+            WitnessType::Submit {
+                details_hash: "hash_quorum".to_string(),
+                requester_id: "user_requester".to_string(),
+                approver_id: approvers[0].clone(),
+                approval_policy: Some(policy),
+            },
+        )).unwrap();
+        // Indices past `members` name users outside the policy
+        for n in &approvals {
+            ctx.insert_witness(Witness::new(
+                "trade_quorum".to_string(),
+                format!("user_{}", n),
+                TimeStamp::new(),
+                WitnessType::Approve { on_behalf_of: None },
+            )).unwrap();
+        }
+
+        let mut distinct: Vec<usize> = approvals.iter().copied().filter(|n| *n < members).collect();
+        distinct.sort();
+        distinct.dedup();
+
+        let expected = if distinct.len() >= required as usize {
+            TradeState::Approved
+        } else {
+            TradeState::PendingApproval
+        };
+        prop_assert_eq!(ctx.current_state(), expected);
+    }
+}
+
+// REJECTION PROPERTIES
+
+proptest! {
+    /// Property: a rejected trade stays Rejected until an Update resubmits it, or a
+    /// Cancel ends it
+    ///
+    /// Every other witness type is refused by the transition table while the trade is
+    /// rejected, and the approvals given before the Reject never carry over to the
+    /// resubmitted details.
+    #[test]
+    fn prop_rejection_waits_for_update(
+        reason in "[a-z ]{0,12}",
+        actions in prop::collection::vec(witness_type_strategy(), 0..=10)
+    ) {
+        let mut ctx = TradeContext::new_with("trade_rejected".to_string());
+        for witness_type in [
+            all_witness_types()[0].clone(),
+            WitnessType::Reject { reason },
+        ] {
+            ctx.insert_witness(Witness::new(
+                "trade_rejected".to_string(),
+                "user_1".to_string(),
+                TimeStamp::new(),
+                witness_type,
+            )).unwrap();
+        }
+        prop_assert_eq!(ctx.current_state(), TradeState::Rejected);
+
+        let Some(action) = actions
+            .into_iter()
+            .find(|action| TradeState::Rejected.transition(action).is_ok())
+        else {
+            return Ok(());
+        };
+        prop_assert!(
+            matches!(action, WitnessType::Update { .. } | WitnessType::Cancel),
+            "Rejected trade accepted {}",
+            action.name()
+        );
+
+        ctx.insert_witness(Witness::new(
+            "trade_rejected".to_string(),
+            "user_1".to_string(),
+            TimeStamp::new(),
+            action.clone(),
+        )).unwrap();
+        if let WitnessType::Update { .. } = action {
+            prop_assert_eq!(ctx.current_state(), TradeState::PendingApproval);
+            prop_assert!(ctx.approval_progress().unwrap().approved_by.is_empty());
+        } else {
+            prop_assert_eq!(ctx.current_state(), TradeState::Cancelled);
+        }
+    }
+}